@@ -0,0 +1,306 @@
+//! Requests to the file server, shared by the desktop client and `fta-cli`.
+
+use std::collections::HashMap;
+use chrono::Utc;
+use reqwest::header::RANGE;
+use reqwest::{Client, StatusCode};
+use serde_json::Value;
+
+use crate::file_info::{parse_listing, FileInfo};
+use crate::jwt::{Claims, Verifier};
+use crate::permissions::status_error;
+
+/// Upload size limit for servers without `/limits`.
+pub const DEFAULT_SIZE_LIMIT: u64 = 524288000;
+pub const WRONG_LOGIN: &str = "Wrong username or password";
+/// Lifetime given to tokens that state none, so they are refreshed rather than trusted forever.
+const DEFAULT_TOKEN_SECONDS: i64 = 60 * 60;
+
+/// Tokens handed out by `/login` and `/refresh`.
+#[derive(Debug, Clone)]
+pub struct Tokens {
+    pub token: String,
+    pub token_exp: i64,
+    pub refresh_token: Option<String>,
+    pub claims: Claims,
+}
+
+pub fn is_token_expired(token_exp: i64) -> bool {
+    let cur_time = Utc::now().timestamp();
+
+    token_exp < cur_time
+}
+
+pub async fn delete_selected_request(client: Client, server_url: String, token: String, file_list: Vec<String>) -> Result<(), String> {
+    let url = format!("{}/files/", server_url);
+    let response = client.delete(&url)
+        .json(&file_list)
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await;
+
+    match response {
+        Ok(response) if response.status().is_success() => Ok(()),
+        Ok(response) => Err(status_error("delete files", response.status())),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+pub async fn log_in_request(client: Client, server_url: String, login: String, password: String, verifier: Result<Verifier, String>) -> Result<Tokens, String> {
+    let verifier = verifier?;
+    let params = [
+        ("username", login.as_str()),
+        ("password", password.as_str())
+    ];
+
+    let url = format!("{}/login", server_url);
+
+    let response = client.post(&url)
+        .form(&params)
+        .send()
+        .await;
+
+    match response
+    {
+        Ok(response) => {
+            let json_result: Result<HashMap<String, Value>, _> = response.json().await;
+            match json_result {
+                Ok(json) if json.contains_key("token") => tokens_from(json, &verifier),
+                _ => Err(String::from(WRONG_LOGIN)),
+            }
+        }
+        Err(e) => {
+            eprintln!("Error sending request: {}", e);
+            Err(String::from("Server connection error"))
+        }
+    }
+}
+
+/// Trades a refresh token for a new session, servers may rotate the refresh token on the way.
+pub async fn refresh_request(client: Client, server_url: String, refresh_token: String, verifier: Result<Verifier, String>) -> Result<Tokens, String> {
+    let verifier = verifier?;
+    let response = client
+        .post(format!("{}/refresh", server_url))
+        .json(&serde_json::json!({ "refresh_token": refresh_token }))
+        .send()
+        .await
+        .map_err(|err| format!("Server connection error: {}", err))?;
+
+    if !response.status().is_success() {
+        return Err(format!("Tokens refresh failed. Status: {}", response.status()));
+    }
+
+    let json: HashMap<String, Value> = response.json().await.map_err(|err| err.to_string())?;
+    let mut tokens = tokens_from(json, &verifier)?;
+    tokens.refresh_token.get_or_insert(refresh_token);
+    Ok(tokens)
+}
+
+/// Reads a `/login` or `/refresh` response. The expiry comes from the token's own `exp`,
+/// `jwt_exp_seconds` is only a fallback for tokens without one, then an hour is assumed.
+fn tokens_from(json: HashMap<String, Value>, verifier: &Verifier) -> Result<Tokens, String> {
+    let token = json.get("token")
+        .and_then(Value::as_str)
+        .ok_or_else(|| String::from("No token in server response"))?
+        .to_string();
+    let claims = verifier.decode(&token)?;
+
+    let token_exp = match (claims.exp, json.get("jwt_exp_seconds")) {
+        (Some(exp), _) => exp,
+        (None, Some(seconds)) => {
+            let seconds = match seconds {
+                Value::Number(number) => number.as_i64(),
+                Value::String(text) => text.trim().parse().ok(),
+                _ => None,
+            };
+            let seconds = seconds.ok_or_else(|| String::from("Server sent an invalid jwt_exp_seconds"))?;
+            Utc::now().timestamp() + seconds
+        }
+        (None, None) => Utc::now().timestamp() + DEFAULT_TOKEN_SECONDS,
+    };
+
+    Ok(Tokens {
+        token,
+        token_exp,
+        refresh_token: json.get("refresh_token").and_then(Value::as_str).map(String::from),
+        claims,
+    })
+}
+
+#[derive(serde::Deserialize)]
+struct Limits {
+    max_file_size: u64,
+}
+
+pub async fn limits_request(client: Client, server_url: String, token: String) -> Result<u64, String> {
+    let response = client
+        .get(format!("{}/limits", server_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .map_err(|err| err.to_string())?;
+
+    if !response.status().is_success() {
+        return Err(format!("Status: {}", response.status()));
+    }
+
+    let limits: Limits = response.json().await.map_err(|err| err.to_string())?;
+    Ok(limits.max_file_size)
+}
+
+pub async fn files_request(client: Client, server_url: String, token: String) -> Result<Vec<FileInfo>, String> {
+    let response = client
+        .get(format!("{}/files/", server_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .map_err(|err| format!("Ошибка при выполнении запроса: {}", err))?;
+
+    if !response.status().is_success() {
+        return Err(status_error("list files", response.status()));
+    }
+
+    let data = response.text()
+        .await
+        .map_err(|err| format!("Ошибка при получении текста ответа: {}", err))?;
+
+    parse_listing(&data)
+        .map_err(|err| format!("Ошибка при разборе JSON: {}", err))
+}
+
+/// The first `length` bytes of `name`, and whether that is the whole file.
+/// Servers that ignore the range still only have the start read.
+pub async fn file_head_request(client: Client, server_url: String, token: String, name: String, length: u64) -> Result<(Vec<u8>, bool), String> {
+    let mut response = client
+        .get(format!("{}/files/", server_url))
+        .json(&name)
+        .header("Authorization", format!("Bearer {}", token))
+        .header(RANGE, format!("bytes=0-{}", length.saturating_sub(1)))
+        .send()
+        .await
+        .map_err(|err| format!("Error sending preview request: {}", err))?;
+
+    if !response.status().is_success() {
+        return Err(status_error(&format!("preview {}", name), response.status()));
+    }
+
+    let mut bytes = vec![];
+    while let Some(chunk) = response.chunk().await.map_err(|err| err.to_string())? {
+        bytes.extend_from_slice(&chunk);
+        if bytes.len() as u64 >= length {
+            bytes.truncate(length as usize);
+            return Ok((bytes, false));
+        }
+    }
+    Ok((bytes, true))
+}
+
+pub async fn delete_file_request(client: Client, server_url: String, token: String, filename: String) -> Result<String, String> {
+    let response = client
+        .delete(format!("{}/files/{}", server_url, filename))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .map_err(|err| format!("Error sending delete request: {}", err))?;
+
+    if response.status().is_success() {
+        Ok(filename)
+    } else {
+        Err(status_error(&format!("delete {}", filename), response.status()))
+    }
+}
+
+/// `POST /folders/`. Servers without folder support answer 404 or 405,
+/// then the folder only lives in this session until a file is uploaded into it.
+pub async fn create_folder_request(client: Client, server_url: String, token: String, path: String) -> Result<String, String> {
+    let response = client
+        .post(format!("{}/folders/", server_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .json(&serde_json::json!({ "path": path }))
+        .send()
+        .await
+        .map_err(|err| format!("Error sending create folder request: {}", err))?;
+
+    match response.status() {
+        status if status.is_success() => Ok(path),
+        StatusCode::NOT_FOUND | StatusCode::METHOD_NOT_ALLOWED => Ok(path),
+        status => Err(status_error("create folders", status)),
+    }
+}
+
+/// Renames files on the server, `moves` are `(from, to)` pairs.
+pub async fn move_files_request(client: Client, server_url: String, token: String, moves: Vec<(String, String)>) -> Result<(), String> {
+    let moves: Vec<serde_json::Value> = moves
+        .into_iter()
+        .map(|(from, to)| serde_json::json!({ "from": from, "to": to }))
+        .collect();
+
+    let response = client
+        .post(format!("{}/files/move", server_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .json(&moves)
+        .send()
+        .await
+        .map_err(|err| format!("Error sending move request: {}", err))?;
+
+    if response.status().is_success() {
+        Ok(())
+    } else {
+        Err(status_error("move files", response.status()))
+    }
+}
+
+/// Deletes `files` (everything under the folder), then the folder marker itself.
+pub async fn delete_folder_request(client: Client, server_url: String, token: String, path: String, files: Vec<String>) -> Result<(), String> {
+    if !files.is_empty() {
+        delete_selected_request(client.clone(), server_url.clone(), token.clone(), files).await?;
+    }
+
+    let response = client
+        .delete(format!("{}/folders/", server_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .json(&serde_json::json!({ "path": path }))
+        .send()
+        .await
+        .map_err(|err| format!("Error sending delete folder request: {}", err))?;
+
+    match response.status() {
+        status if status.is_success() => Ok(()),
+        StatusCode::NOT_FOUND | StatusCode::METHOD_NOT_ALLOWED => Ok(()),
+        status => Err(status_error("delete folders", status)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jsonwebtoken::{encode, EncodingKey, Header};
+    use serde_json::json;
+
+    fn response(claims: Value, extra: Value) -> HashMap<String, Value> {
+        let token = encode(&Header::default(), &claims, &EncodingKey::from_secret(b"secret")).unwrap();
+        let mut json: HashMap<String, Value> = serde_json::from_value(extra).unwrap();
+        json.insert(String::from("token"), Value::String(token));
+        json
+    }
+
+    #[test]
+    fn takes_the_expiry_from_the_token_then_the_response() {
+        let now = Utc::now().timestamp();
+
+        let tokens = tokens_from(response(json!({ "exp": now + 60 }), json!({ "jwt_exp_seconds": 10 })), &Verifier::None).unwrap();
+        assert_eq!(tokens.token_exp, now + 60);
+
+        let tokens = tokens_from(response(json!({ "sub": "a" }), json!({ "jwt_exp_seconds": "120" })), &Verifier::None).unwrap();
+        assert!((now + 120..=now + 121).contains(&tokens.token_exp));
+
+        // Nothing says when it expires: it is not trusted for longer than the default.
+        let tokens = tokens_from(response(json!({ "sub": "a" }), json!({})), &Verifier::None).unwrap();
+        assert!((now + DEFAULT_TOKEN_SECONDS..=now + DEFAULT_TOKEN_SECONDS + 1).contains(&tokens.token_exp));
+
+        assert!(tokens_from(response(json!({ "sub": "a" }), json!({ "jwt_exp_seconds": "soon" })), &Verifier::None).is_err());
+        assert!(tokens_from(HashMap::new(), &Verifier::None).is_err());
+        let garbage = HashMap::from([(String::from("token"), json!("not.a.token"))]);
+        assert!(tokens_from(garbage, &Verifier::None).unwrap_err().starts_with("Malformed token"));
+    }
+}
@@ -1,105 +1,781 @@
+use std::collections::{BTreeSet, HashMap};
 use std::env;
+use std::iter;
+use std::mem;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
 use dotenv::dotenv;
-use iced::{Element, Sandbox, Theme};
-use reqwest::blocking::Client;
+use iced::{event, executor, mouse, time, window, Application, Command, Element, Event, Subscription, Theme};
+use iced::widget::image;
+use reqwest::Client;
 
 use crate::ui;
-use crate::handlers::{handle_update, is_token_expired, clear_login_field};
+use crate::subscriptions;
+use crate::transfer::{Kind, Progress};
+use crate::queue::{TransferQueue, DEFAULT_MAX_CONCURRENT};
+use crate::journal::Journal;
+use crate::chunks::Assembly;
+use crate::file_info::FileInfo;
+use crate::columns::{FileColumn, Resize};
+use crate::settings::{FileView, Settings, ThemeChoice};
+use crate::query::{self, Query};
+use crate::folder_upload::FolderUpload;
+use crate::collision::Collision;
+use crate::zip::Bundle;
+use crate::handlers::{handle_update, clear_password, load_thumbnails, select_profile};
+use crate::api::{is_token_expired, Tokens, DEFAULT_SIZE_LIMIT};
+use crate::credentials::{self, SavedLogin};
+use crate::encryption::{EncryptionKey, Import};
+use crate::jwt::{Claims, Verifier};
+use crate::preview::{Content, Preview};
+use crate::profiles::{self, Profile};
+use crate::sync::{Resolution, SyncFolder, SyncItem, Synced};
 
 pub struct App {
-    pub theme: Theme,
+    pub settings: Settings,
+    /// The tab being shown, or the one a tagged message is being handled for.
+    pub session: Session,
+    /// The other tabs, in no particular order: tabs are shown sorted by [`Session::id`].
+    pub background: Vec<Session>,
+    next_session_id: usize,
+    /// How tokens are checked, or why the configured key can't be used.
+    pub verifier: Result<Verifier, String>,
+    /// An encrypted "Remember me" login is on disk.
+    pub saved_login: bool,
+    pub profiles: Vec<Profile>,
+    pub profile_editor: Option<ProfileEditor>,
+    pub saved_searches: Vec<String>,
+    pub journal: Journal,
+    pub resizing: Option<Resize>,
+    /// Files are being dragged over the window.
+    pub drop_hover: bool,
+    pub collision: Collision,
+    pub toasts: Vec<Toast>,
+    /// Files picked up from the file list, waiting to be dropped on another tab.
+    pub dragging: Option<Vec<DraggedFile>>,
+    /// Server to server copies, see [`Relay`].
+    pub relays: Vec<Relay>,
+}
+
+/// Everything tied to one server login, shown as a tab.
+pub struct Session {
+    pub id: usize,
+    /// Each tab keeps its own page, so a background tab's login or expiry leaves the shown one alone.
     pub page: Page,
     pub login_field: LoginField,
+    pub login_error: Option<String>,
+    pub server: Server,
+    pub client: Client,
+    /// Index into `App::profiles` of the server in use.
+    pub profile: Option<usize>,
     pub token: String,
     pub token_exp: i64,
-    pub client: Client,
-    pub login_error: Option<String>,
+    pub refresh_token: Option<String>,
+    /// Claims of the current token, see [`Verifier`].
+    pub claims: Claims,
+    /// A `/refresh` request is in flight.
+    pub refreshing: bool,
     pub packages: Vec<ui::PackageRow>,
-    pub server: Server,
     pub search_text: String,
+    /// The parsed `search_text`, or why it does not parse.
+    pub search: Result<Query, String>,
+    pub transfers: TransferQueue,
+    pub size_limit: u64,
+    pub rejected: Vec<RejectedFile>,
+    pub assemblies: Vec<Assembly>,
+    /// Folder being browsed, `""` for the root, otherwise ending with `/`.
+    pub current_dir: String,
+    /// Empty folders, either listed by the server or created in this session.
+    pub folders: BTreeSet<String>,
+    pub new_folder_name: String,
+    pub renaming: Option<FolderRename>,
+    pub folder_error: Option<String>,
+    /// Last folder row click, to tell double clicks apart.
+    pub last_click: Option<(String, Instant)>,
+    /// Scanned folders waiting for the user to confirm the upload.
+    pub folder_uploads: Vec<FolderUpload>,
+    /// "Download as zip" archives waiting for their files.
+    pub bundles: Vec<Bundle>,
+    /// Folders kept in step with this server, loaded on the first login.
+    pub sync: Vec<SyncJob>,
+    /// The file shown in the side panel.
+    pub preview: Option<PreviewPane>,
+    /// Grid view pictures by [`cache_key`](crate::cache::cache_key) of their file.
+    pub thumbnails: HashMap<String, Thumbnail>,
 }
 
+impl Session {
+    pub fn new(id: usize) -> Self {
+        Session {
+            id,
+            page: Page::Login,
+            login_field: LoginField::default(),
+            login_error: None,
+            server: Server {
+                url: String::new(),
+                encrypt: false,
+                key: None,
+            },
+            client: Client::new(),
+            profile: None,
+            token: String::new(),
+            token_exp: 0,
+            refresh_token: None,
+            claims: Claims::default(),
+            refreshing: false,
+            packages: vec![],
+            search_text: String::new(),
+            search: Ok(Query::default()),
+            transfers: TransferQueue::new(
+                env::var("MAX_CONCURRENT_TRANSFERS")
+                    .ok()
+                    .and_then(|max| max.parse().ok())
+                    .unwrap_or(DEFAULT_MAX_CONCURRENT)
+            ),
+            size_limit: env::var("MAX_FILE_SIZE")
+                .ok()
+                .and_then(|size| size.parse().ok())
+                .unwrap_or(DEFAULT_SIZE_LIMIT),
+            rejected: vec![],
+            assemblies: vec![],
+            current_dir: String::new(),
+            folders: BTreeSet::new(),
+            new_folder_name: String::new(),
+            renaming: None,
+            folder_error: None,
+            last_click: None,
+            folder_uploads: vec![],
+            bundles: vec![],
+            sync: vec![],
+            preview: None,
+            thumbnails: HashMap::new(),
+        }
+    }
+
+    pub fn is_logged_in(&self) -> bool {
+        !self.token.is_empty() && !is_token_expired(self.token_exp)
+    }
+}
+
+/// A synced folder and what its last run found.
+pub struct SyncJob {
+    pub id: usize,
+    pub folder: SyncFolder,
+    /// Looking at both sides or carrying out the plan.
+    pub running: bool,
+    /// Something changed during the run, look again once it is done.
+    pub rerun: bool,
+    pub pending: Vec<SyncItem>,
+    pub conflicts: Vec<SyncItem>,
+    /// Recently synced files, newest first.
+    pub synced: Vec<String>,
+    /// `(file, error)`, the file is empty for errors of the whole run.
+    pub errors: Vec<(String, String)>,
+    pub last_run: Option<i64>,
+}
+
+impl SyncJob {
+    pub fn new(id: usize, folder: SyncFolder) -> Self {
+        SyncJob {
+            id,
+            folder,
+            running: false,
+            rerun: false,
+            pending: vec![],
+            conflicts: vec![],
+            synced: vec![],
+            errors: vec![],
+            last_run: None,
+        }
+    }
+}
+
+/// The side panel with a look into a file, see [`crate::preview`].
+pub struct PreviewPane {
+    pub name: String,
+    /// `None` while loading.
+    pub preview: Option<Result<Preview, String>>,
+    /// The image, or the PDF's thumbnails, decoded once so their textures are kept.
+    pub images: Vec<image::Handle>,
+}
+
+impl PreviewPane {
+    pub fn new(name: String) -> Self {
+        PreviewPane {
+            name,
+            preview: None,
+            images: vec![],
+        }
+    }
+
+    pub fn loaded(&mut self, result: Result<Preview, String>) {
+        self.images = match &result {
+            Ok(Preview { content: Content::Image(bytes), .. }) => vec![image::Handle::from_memory(bytes.clone())],
+            Ok(Preview { content: Content::Pdf(info), .. }) => info.thumbnails
+                .iter()
+                .map(|thumbnail| image::Handle::from_memory(thumbnail.clone()))
+                .collect(),
+            _ => vec![],
+        };
+        self.preview = Some(result);
+    }
+}
+
+pub enum Thumbnail {
+    Loading,
+    Ready(image::Handle),
+    /// Shown with the icon of its type instead.
+    Failed,
+}
+
+/// A file dragged out of a tab's list: its name and, for split files, the chunk names.
+#[derive(Debug, Clone)]
+pub struct DraggedFile {
+    pub name: String,
+    pub chunks: Vec<String>,
+}
+
+/// A file on its way from one server to another. It is downloaded by the `from` session
+/// into `path` under [`relay_dir`](crate::handlers::relay_dir), then uploaded by the `to` session as `name`.
+pub struct Relay {
+    pub from: usize,
+    pub to: usize,
+    pub path: PathBuf,
+    pub name: String,
+}
+
+/// A short lived notice at the top of the main page, for failed requests and skipped work.
+pub struct Toast {
+    pub message: String,
+    pub shown_at: Instant,
+}
+
+pub const TOAST_DURATION: Duration = Duration::from_secs(6);
+
+/// Seconds between looks at the server for synced folders, `SYNC_INTERVAL` overrides it.
+const DEFAULT_SYNC_INTERVAL: u64 = 60;
+
+pub struct FolderRename {
+    pub path: String,
+    pub name: String,
+}
+
+#[derive(Default)]
 pub struct LoginField {
     pub login: String,
     pub password: String,
+    pub remember: bool,
+    /// Unlocks the saved login, or protects it when "Remember me" is on.
+    pub passphrase: String,
 }
 
 pub struct Server {
     pub url: String,
+    /// Uploads are encrypted with `key`, from the profile.
+    pub encrypt: bool,
+    /// Opens the server's encrypted files, `None` until one is made or imported.
+    pub key: Option<EncryptionKey>,
+}
+
+/// The add / edit profile form on the login page.
+pub struct ProfileEditor {
+    /// `None` while adding a new profile.
+    pub index: Option<usize>,
+    pub name: String,
+    pub url: String,
+    pub username: String,
+    pub ca_certificate: String,
+    pub accept_invalid_certs: bool,
+    pub proxy: String,
+    pub encrypt: bool,
+    /// Protects the key file on export and import.
+    pub key_passphrase: String,
+    /// How the last key export or import went.
+    pub key_status: Option<Result<String, String>>,
+    /// Key file whose import waits for the user to confirm the warning in `key_status`.
+    pub key_import: Option<PathBuf>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProfileField {
+    Name,
+    Url,
+    Username,
+    CaCertificate,
+    Proxy,
+    KeyPassphrase,
+}
+
+impl ProfileEditor {
+    pub fn new(index: Option<usize>, profile: &Profile) -> Self {
+        ProfileEditor {
+            index,
+            name: profile.name.clone(),
+            url: profile.url.clone(),
+            username: profile.username.clone(),
+            ca_certificate: profile.ca_certificate
+                .as_ref()
+                .map(|path| path.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            accept_invalid_certs: profile.accept_invalid_certs,
+            proxy: profile.proxy.clone().unwrap_or_default(),
+            encrypt: profile.encrypt,
+            key_passphrase: String::new(),
+            key_status: None,
+            key_import: None,
+            error: None,
+        }
+    }
+
+    pub fn profile(&self) -> Profile {
+        let optional = |value: &str| Some(value.trim()).filter(|value| !value.is_empty()).map(String::from);
+
+        Profile {
+            name: self.name.trim().to_string(),
+            url: self.url.trim().trim_end_matches('/').to_string(),
+            username: self.username.trim().to_string(),
+            ca_certificate: optional(&self.ca_certificate).map(PathBuf::from),
+            accept_invalid_certs: self.accept_invalid_certs,
+            proxy: optional(&self.proxy),
+            encrypt: self.encrypt,
+        }
+    }
+}
+
+/// A picked file that is bigger than the server accepts.
+pub struct RejectedFile {
+    pub path: PathBuf,
+    pub file_name: String,
+    pub size: u64,
+    pub encrypt: bool,
 }
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Page{
     Login,
-    Main
+    Main,
+    Settings,
+    Sync,
 }
 
 #[derive(Debug, Clone)]
 pub enum Message {
     ToggleTheme,
+    OpenSettings,
+    CloseSettings,
+    ThemeChanged(ThemeChoice),
+    ChooseDownloadDir,
+    ClearDownloadDir,
+    AlwaysAskToggled(bool),
+    LastProfileChanged(String),
+    ResetWindow,
+    ResetColumns,
+    ViewChanged(FileView),
+    WindowResized(u32, u32),
+    WindowMoved(i32, i32),
+    WindowCloseRequested(window::Id),
+    ProfileSelected(String),
+    NewProfile,
+    EditProfile,
+    RemoveProfile,
+    ProfileFieldChanged(ProfileField, String),
+    InvalidCertsToggled(bool),
+    EncryptToggled(bool),
+    ExportKey,
+    KeyExported(Result<PathBuf, String>),
+    ImportKey,
+    /// Imports the key that was held back for a warning.
+    ConfirmKeyImport,
+    CancelKeyImport,
+    KeyImported(String, PathBuf, Result<Import, String>),
+    SaveProfile,
+    CancelProfileEdit,
     LoginSubmit,
+    LoggedIn(Result<Tokens, String>),
+    RememberToggled(bool),
+    PassphraseChanged(String),
+    UnlockSaved,
+    Unlocked(Result<SavedLogin, String>),
+    LoginSaved(Result<(), String>),
+    ForgetSaved,
+    RefreshTick,
+    SessionRefreshed(Result<Tokens, String>),
+    LimitsLoaded(Result<u64, String>),
     LoginFieldChanged(String, String),
     DeleteFile(usize),
+    FileDeleted(Result<String, String>),
     ToggleCheck(usize),
     SelectAll(bool),
     DeleteSelected,
+    SelectedDeleted(Result<(), String>),
     Refresh,
+    FilesLoaded(Result<Vec<FileInfo>, String>),
     DownloadFile(String),
+    /// `true` bundles the files into one zip archive.
+    DownloadSelected(bool),
+    CollisionChanged(Collision),
+    Zipped(Result<PathBuf, String>),
+    ResumeTransfers,
+    DiscardTransfers,
     UploadFiles,
+    UploadFolder,
+    FolderScanned(Result<FolderUpload, String>),
+    ConfirmFolderUpload(usize),
+    CancelFolderUpload(usize),
+    FileHovered,
+    FilesHoveredLeft,
+    FileDropped(PathBuf),
+    SplitUpload(usize),
+    DismissRejected(usize),
+    Assembled(Result<PathBuf, String>),
+    TransferProgress(usize, Progress),
+    CancelTransfer(usize),
+    PauseTransfer(usize),
+    ResumeTransfer(usize),
+    MoveTransfer(usize, isize),
+    ClearFinishedTransfers,
+    SetMaxConcurrent(usize),
     SearchFieldChanged(String),
+    SaveSearch,
+    ApplySavedSearch(usize),
+    RemoveSavedSearch(usize),
+    SortBy(FileColumn),
+    ResizeColumn(FileColumn),
+    ColumnDragged(f32),
+    ColumnResizeEnd,
+    OpenFolder(String),
+    FolderClicked(String),
+    NewFolderNameChanged(String),
+    CreateFolder,
+    FolderCreated(Result<String, String>),
+    StartRename(String),
+    RenameChanged(String),
+    SubmitRename,
+    CancelRename,
+    DeleteFolder(String),
+    MoveSelectedHere,
+    Moved(Result<(), String>),
+    DismissToast(usize),
+    ExpireToasts,
+    NewSession,
+    ShowSession(usize),
+    CloseSession(usize),
+    /// Leaves the login page for the last tab shown.
+    BackToSessions,
+    StartDrag(usize),
+    CancelDrag,
+    DropOnSession(usize),
+    OpenSync,
+    CloseSync,
+    AddSyncFolder,
+    RemoveSyncFolder(usize),
+    SyncNow(usize),
+    /// The watcher saw files change in the folder of this sync job.
+    SyncLocalChanged(usize),
+    SyncWatchFailed(usize, String),
+    /// Time to look for changes on the server.
+    SyncPoll,
+    SyncPlanned(usize, Result<Vec<SyncItem>, String>),
+    SyncApplied(usize, Vec<(String, Result<Option<Synced>, String>)>),
+    ResolveConflict(usize, String, Resolution),
+    /// Shows the file in the preview panel, or hides it if it is shown already.
+    OpenPreview(String),
+    PreviewLoaded(String, Result<Preview, String>),
+    ClosePreview,
+    ThumbnailLoaded(String, Result<Vec<u8>, String>),
+    /// A message for the session with this id, which may not be the one shown.
+    Session(usize, Box<Message>),
+}
+
+impl Message {
+    /// Whether the message reports on work already under way or comes from the window,
+    /// rather than being something the user starts. Those are handled even once the token
+    /// has expired, so transfers and loads still finish where the user can see them.
+    fn is_report(&self) -> bool {
+        matches!(
+            self,
+            Message::WindowResized(..)
+                | Message::WindowMoved(..)
+                | Message::WindowCloseRequested(_)
+                | Message::FileHovered
+                | Message::FilesHoveredLeft
+                | Message::ExpireToasts
+                | Message::KeyExported(_)
+                | Message::KeyImported(..)
+                | Message::LoggedIn(_)
+                | Message::Unlocked(_)
+                | Message::LoginSaved(_)
+                | Message::SessionRefreshed(_)
+                | Message::LimitsLoaded(_)
+                | Message::FileDeleted(_)
+                | Message::SelectedDeleted(_)
+                | Message::FilesLoaded(_)
+                | Message::Zipped(_)
+                | Message::FolderScanned(_)
+                | Message::Assembled(_)
+                | Message::TransferProgress(..)
+                | Message::FolderCreated(_)
+                | Message::Moved(_)
+                | Message::SyncWatchFailed(..)
+                | Message::SyncPlanned(..)
+                | Message::SyncApplied(..)
+                | Message::PreviewLoaded(..)
+                | Message::ThumbnailLoaded(..)
+        )
+    }
+}
+
+
+
+
+impl App {
+    fn update_shown(&mut self, message: Message) -> Command<Message> {
+        if self.session.page == Page::Login || message.is_report() {
+            handle_update(self, message)
+        }
+        else if is_token_expired(self.session.token_exp) {
+            // Everything but the token is kept, logging in again picks up where the user left off.
+            self.session.page = Page::Login;
+            clear_password(&mut self.session.login_field);
+            Command::none()
+        }
+        else {
+            handle_update(self, message)
+        }
+    }
+
+    /// Opens a blank tab and shows it, the login page then fills it in.
+    pub fn open_session(&mut self) {
+        let session = Session::new(self.next_session_id);
+        self.next_session_id += 1;
+        self.background.push(mem::replace(&mut self.session, session));
+    }
+
+    /// Shows the session `id`, returns false if there is no such tab.
+    pub fn show_session(&mut self, id: usize) -> bool {
+        if self.session.id == id {
+            return true;
+        }
+        match self.background.iter().position(|session| session.id == id) {
+            Some(index) => {
+                mem::swap(&mut self.session, &mut self.background[index]);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Runs `f` with the session `id` swapped into `App::session`.
+    pub fn with_session<T>(&mut self, id: usize, f: impl FnOnce(&mut App) -> T) -> Option<T> {
+        if self.session.id == id {
+            return Some(f(self));
+        }
+        let index = self.background.iter().position(|session| session.id == id)?;
+        mem::swap(&mut self.session, &mut self.background[index]);
+        let result = f(self);
+        mem::swap(&mut self.session, &mut self.background[index]);
+        Some(result)
+    }
+
+    /// What decides which files the grid shows, bar the listing itself.
+    fn grid(&self) -> (usize, Page, FileView, String, String) {
+        (
+            self.session.id,
+            self.session.page.clone(),
+            self.settings.view,
+            self.session.current_dir.clone(),
+            self.session.search_text.clone(),
+        )
+    }
+
+    /// All tabs in the order they were opened.
+    pub fn sessions(&self) -> Vec<&Session> {
+        let mut sessions: Vec<&Session> = iter::once(&self.session).chain(&self.background).collect();
+        sessions.sort_by_key(|session| session.id);
+        sessions
+    }
 }
 
+/// Transfers, token renewal and folder sync of one session, independent of which tab is shown.
+fn session_subscription(session: &Session) -> Subscription<Message> {
+    let transfers = Subscription::batch(
+        session.transfers
+            .running()
+            .map(|job| {
+                let client = session.client.clone();
+                let server_url = session.server.url.clone();
+                let token = session.token.clone();
+                let key = session.server.key;
 
+                match job.kind {
+                    Kind::Upload => subscriptions::upload(job, client, server_url, token, key.filter(|_| job.encrypt)),
+                    Kind::Download => subscriptions::download(job, client, server_url, token, key),
+                }
+            })
+    );
 
+    let refresh = match (&session.refresh_token, session.token.is_empty()) {
+        (Some(_), false) => time::every(Duration::from_secs(15)).map(|_| Message::RefreshTick),
+        _ => Subscription::none(),
+    };
 
-impl Sandbox for App {
+    let sync = if session.sync.is_empty() || session.token.is_empty() {
+        Subscription::none()
+    } else {
+        let interval = env::var("SYNC_INTERVAL")
+            .ok()
+            .and_then(|seconds| seconds.parse().ok())
+            .unwrap_or(DEFAULT_SYNC_INTERVAL);
+        let watchers = session.sync
+            .iter()
+            .map(|job| subscriptions::watch_folder(job.id, job.folder.local.clone()));
+
+        Subscription::batch(watchers.chain(iter::once(
+            time::every(Duration::from_secs(interval)).map(|_| Message::SyncPoll)
+        )))
+    };
+
+    Subscription::batch([transfers, refresh, sync])
+}
+
+impl Application for App {
+    type Executor = executor::Default;
     type Message = Message;
+    type Theme = Theme;
+    type Flags = Settings;
 
 
-    fn new() -> Self {
+    fn new(settings: Settings) -> (Self, Command<Message>) {
         dotenv().ok();
-        Self {
-            theme: Theme::Dark,
-            page: Page::Login,
-            login_field: LoginField {
-                login: String::new(),
-                password: String::new(),
-            },
-            token: String::new(),
-            token_exp: 0,
-            client: Client::new(),
-            login_error: None,
-            packages: (1..=100)
-                .map(|_| ui::PackageRow::new("filename".to_string()))
-                .collect(),
-            server: Server {
-                url: env::var("SERVER_URL").expect("SERVER_URL must be set").to_string(),
-            },
-            search_text: String::new(),
+        let mut app = Self {
+            settings,
+            session: Session::new(0),
+            background: vec![],
+            next_session_id: 1,
+            verifier: Verifier::from_env(),
+            saved_login: credentials::is_saved(),
+            profiles: profiles::load_profiles(),
+            profile_editor: None,
+            saved_searches: query::load_saved_searches(),
+            journal: Journal::load(),
+            resizing: None,
+            drop_hover: false,
+            collision: Collision::Rename,
+            toasts: vec![],
+            dragging: None,
+            relays: vec![],
+        };
+
+        // Without a profile or SERVER_URL there is nothing to log in to, so start with the editor.
+        if app.profiles.is_empty() {
+            app.profile_editor = Some(ProfileEditor::new(None, &Profile::default()));
+        } else {
+            let last = app.settings.last_profile
+                .as_ref()
+                .and_then(|name| app.profiles.iter().position(|profile| profile.name == *name));
+            select_profile(&mut app, last.unwrap_or(0));
         }
+
+        (app, Command::none())
     }
 
     fn title(&self) -> String {
         String::from("FTA")
     }
 
-    fn update(&mut self, message: Message) {
-        if self.page == Page::Login {
-            handle_update(self, message);
-        }
-        else if is_token_expired(self.token_exp) {
-            self.page = Page::Login;
-            clear_login_field(&mut self.login_field);
-        }
-        else {
-            handle_update(self, message);
+    /// Messages tagged with a session are handled with that session swapped into
+    /// `App::session`, and whatever they lead to is tagged the same way.
+    /// Untagged ones come from the view and belong to the tab being shown.
+    fn update(&mut self, message: Message) -> Command<Message> {
+        let (id, message) = match message {
+            Message::Session(id, message) => (id, *message),
+            message => (self.session.id, message),
+        };
+        let listed = matches!(message, Message::FilesLoaded(_) | Message::ThumbnailLoaded(..));
+        let grid = self.grid();
+
+        let command = if id == self.session.id {
+            self.update_shown(message)
+        } else {
+            // The tab may have been closed since.
+            let Some(index) = self.background.iter().position(|session| session.id == id) else {
+                return Command::none();
+            };
+            mem::swap(&mut self.session, &mut self.background[index]);
+            let command = handle_update(self, message);
+            mem::swap(&mut self.session, &mut self.background[index]);
+            command
+        };
+
+        let command = command.map(move |message| match message {
+            Message::Session(..) => message,
+            message => Message::Session(id, Box::new(message)),
+        });
+
+        // The grid asks for the thumbnails it shows once other files may be in it,
+        // and for the next ones whenever a thumbnail is done.
+        if !listed && self.grid() == grid {
+            return command;
         }
+        let shown = self.session.id;
+        let thumbnails = load_thumbnails(self).map(move |message| Message::Session(shown, Box::new(message)));
+        Command::batch([command, thumbnails])
     }
 
-    fn view(&self) -> Element<Message> {
+    fn subscription(&self) -> Subscription<Message> {
+        let resize = match self.resizing {
+            Some(_) => event::listen_with(|event, _status| match event {
+                Event::Mouse(mouse::Event::CursorMoved { position }) => Some(Message::ColumnDragged(position.x)),
+                Event::Mouse(mouse::Event::ButtonReleased(mouse::Button::Left)) => Some(Message::ColumnResizeEnd),
+                _ => None,
+            }),
+            None => Subscription::none(),
+        };
+
+        let window = event::listen_with(|event, _status| match event {
+            Event::Window(_, window::Event::Resized { width, height }) => Some(Message::WindowResized(width, height)),
+            Event::Window(_, window::Event::Moved { x, y }) => Some(Message::WindowMoved(x, y)),
+            Event::Window(id, window::Event::CloseRequested) => Some(Message::WindowCloseRequested(id)),
+            Event::Window(_, window::Event::FileHovered(_)) => Some(Message::FileHovered),
+            Event::Window(_, window::Event::FilesHoveredLeft) => Some(Message::FilesHoveredLeft),
+            Event::Window(_, window::Event::FileDropped(path)) => Some(Message::FileDropped(path)),
+            _ => None,
+        });
+
+        let drag = match self.dragging {
+            // Releases over a tab are captured by it, anything else drops the files nowhere.
+            Some(_) => event::listen_with(|event, status| match (event, status) {
+                (Event::Mouse(mouse::Event::ButtonReleased(mouse::Button::Left)), event::Status::Ignored) => Some(Message::CancelDrag),
+                _ => None,
+            }),
+            None => Subscription::none(),
+        };
+
+        let sessions = Subscription::batch(
+            iter::once(&self.session)
+                .chain(&self.background)
+                .map(|session| {
+                    session_subscription(session)
+                        .with(session.id)
+                        .map(|(id, message)| Message::Session(id, Box::new(message)))
+                })
+        );
+
+        let toasts = if self.toasts.is_empty() {
+            Subscription::none()
+        } else {
+            time::every(Duration::from_secs(1)).map(|_| Message::ExpireToasts)
+        };
+
+        Subscription::batch([resize, window, drag, toasts, sessions])
+    }
+
+    fn view(&self) -> Element<'_, Message> {
         ui::view(self)
     }
 
     fn theme(&self) -> Theme {
-        self.theme.clone()
+        self.settings.theme.theme()
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,770 @@
+//! `fta-cli`, the desktop client's file operations for scripts and CI.
+//!
+//! `fta-cli login` keeps the tokens in `cli-session.json` next to the client's other files,
+//! later commands refresh them when they expire. `FTA_TOKEN` is used instead when it is set.
+
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::UNIX_EPOCH;
+use futures::never::Never;
+use futures::{future, sink, Sink};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use fta::api::{self, Tokens, WRONG_LOGIN};
+use fta::chunks::ListedFile;
+use fta::checksum::{parse_sha256, sha256_file};
+use fta::collision::{resolve, Collision};
+use fta::encryption::{encrypted_name, encrypted_size, is_encrypted, key_for_upload, load_key, plain_name};
+use fta::file_info::format_timestamp;
+use fta::folder_upload::{scan, ScannedFile};
+use fta::folders::{base_name, join, SEPARATOR};
+use fta::jwt::{Claims, Verifier};
+use fta::permissions::{denied, Permission, PERMISSION_DENIED, SESSION_EXPIRED};
+use fta::profiles::{load_profiles, Profile};
+use fta::query::Query;
+use fta::remote::Remote;
+use fta::transfer::Progress;
+use fta::utils::{data_dir, format_size, write_private};
+
+const SESSION_FILE: &str = "cli-session.json";
+
+const EXIT_FAILED: u8 = 1;
+const EXIT_USAGE: u8 = 2;
+const EXIT_AUTH: u8 = 3;
+const EXIT_DENIED: u8 = 4;
+const EXIT_NOT_FOUND: u8 = 5;
+
+const USAGE: &str = "\
+Usage: fta-cli [--json] [--server URL | --profile NAME] <command> [arguments]
+
+Commands:
+  login [USERNAME]            Log in, the password is read from FTA_PASSWORD or stdin
+  ls [QUERY]                  List files, QUERY uses the client's search syntax
+  upload PATH... [--to DIR]   Upload files and folders into the server folder DIR
+  download NAME... [--to DIR] [--overwrite | --skip-existing]
+                              Download files, by default existing files are kept
+                              and the new ones renamed
+  rm NAME...                  Delete files
+  sync LOCAL_DIR [DIR] [--delete]
+                              Upload what is new or changed in LOCAL_DIR to DIR/LOCAL_DIR,
+                              --delete also removes files that are gone locally
+
+Without --server or --profile the server of the last login is used, then SERVER_URL.
+--json prints one JSON document on stdout, errors included.
+Profiles with encryption on upload NAME.fta files, downloads decrypt them with the
+key the desktop client keeps for the server.
+
+Exit codes:
+  0  success
+  1  a request or file operation failed
+  2  wrong usage
+  3  not logged in, or the session expired
+  4  the token does not allow the operation
+  5  a named file does not exist
+";
+
+/// An error and the exit code it ends the run with.
+#[derive(Debug)]
+struct Failure {
+    code: u8,
+    message: String,
+}
+
+impl Failure {
+    fn new(code: u8, message: impl Into<String>) -> Self {
+        Failure {
+            code,
+            message: message.into(),
+        }
+    }
+
+    fn usage(message: impl Into<String>) -> Self {
+        Failure::new(EXIT_USAGE, message)
+    }
+}
+
+/// Errors from the library are plain strings, permission and session errors have fixed prefixes.
+impl From<String> for Failure {
+    fn from(message: String) -> Self {
+        let code = if message.starts_with(PERMISSION_DENIED) {
+            EXIT_DENIED
+        } else if message.starts_with(SESSION_EXPIRED) {
+            EXIT_AUTH
+        } else {
+            EXIT_FAILED
+        };
+        Failure { code, message }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Options {
+    json: bool,
+    server: Option<String>,
+    profile: Option<String>,
+    to: Option<String>,
+    collision: Option<Collision>,
+    delete: bool,
+    command: String,
+    args: Vec<String>,
+}
+
+fn parse_args(args: Vec<String>) -> Result<Options, Failure> {
+    let mut options = Options::default();
+    let mut positional = vec![];
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        let mut value = |flag: &str| args.next().ok_or_else(|| Failure::usage(format!("{} needs a value", flag)));
+        match arg.as_str() {
+            "--json" => options.json = true,
+            "--server" => options.server = Some(value("--server")?.trim_end_matches('/').to_string()),
+            "--profile" => options.profile = Some(value("--profile")?),
+            "--to" => options.to = Some(value("--to")?),
+            "--overwrite" => options.collision = Some(Collision::Overwrite),
+            "--skip-existing" => options.collision = Some(Collision::Skip),
+            "--delete" => options.delete = true,
+            flag if flag.starts_with("--") => return Err(Failure::usage(format!("Unknown option {}", flag))),
+            _ => positional.push(arg),
+        }
+    }
+
+    if options.server.is_some() && options.profile.is_some() {
+        return Err(Failure::usage("Pass either --server or --profile"));
+    }
+    let mut positional = positional.into_iter();
+    options.command = positional.next().ok_or_else(|| Failure::usage("No command given"))?;
+    options.args = positional.collect();
+    Ok(options)
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    dotenv::dotenv().ok();
+
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        print!("{}", USAGE);
+        return ExitCode::SUCCESS;
+    }
+    let json = args.iter().any(|arg| arg == "--json");
+
+    let result = match parse_args(args) {
+        Ok(options) => run(&options).await,
+        Err(failure) => Err(failure),
+    };
+
+    match result {
+        Ok(code) => ExitCode::from(code),
+        Err(failure) => {
+            if json {
+                println!("{}", json!({ "error": failure.message, "exit_code": failure.code }));
+            } else {
+                eprintln!("fta-cli: {}", failure.message);
+                if failure.code == EXIT_USAGE {
+                    eprint!("\n{}", USAGE);
+                }
+            }
+            ExitCode::from(failure.code)
+        }
+    }
+}
+
+/// `Ok` once the command printed its own output, the code tells whether every item went through.
+async fn run(options: &Options) -> Result<u8, Failure> {
+    match options.command.as_str() {
+        "login" => login(options).await,
+        "ls" => list(options).await,
+        "upload" => upload(options).await,
+        "download" => download(options).await,
+        "rm" => remove(options).await,
+        "sync" => sync(options).await,
+        command => Err(Failure::usage(format!("Unknown command {}", command))),
+    }
+}
+
+/// What `login` leaves for the next commands.
+#[derive(Debug, Serialize, Deserialize)]
+struct SavedSession {
+    server_url: String,
+    #[serde(default)]
+    profile: Option<String>,
+    token: String,
+    token_exp: i64,
+    #[serde(default)]
+    refresh_token: Option<String>,
+}
+
+impl SavedSession {
+    fn new(server_url: &str, profile: Option<String>, tokens: &Tokens) -> Self {
+        SavedSession {
+            server_url: server_url.to_string(),
+            profile,
+            token: tokens.token.clone(),
+            token_exp: tokens.token_exp,
+            refresh_token: tokens.refresh_token.clone(),
+        }
+    }
+}
+
+fn session_path() -> PathBuf {
+    data_dir().join(SESSION_FILE)
+}
+
+fn load_session() -> Option<SavedSession> {
+    let data = fs::read_to_string(session_path()).ok()?;
+    serde_json::from_str(&data).ok()
+}
+
+fn save_session(session: &SavedSession) -> Result<(), Failure> {
+    let path = session_path();
+
+    if let Some(dir) = path.parent() {
+        let _ = fs::create_dir_all(dir);
+    }
+    let data = serde_json::to_vec_pretty(session).map_err(|e| Failure::new(EXIT_FAILED, e.to_string()))?;
+    write_private(&path, &data).map_err(|e| Failure::new(EXIT_FAILED, format!("Failed to save the session: {}", e)))
+}
+
+/// `--server`, `--profile`, the server of the saved session, then the first profile
+/// (which is `SERVER_URL` when that is set and not saved).
+fn pick_profile(options: &Options, saved: Option<&SavedSession>) -> Result<Profile, Failure> {
+    if let Some(url) = &options.server {
+        return Ok(Profile {
+            name: url.clone(),
+            url: url.clone(),
+            ..Profile::default()
+        });
+    }
+
+    let profiles = load_profiles();
+    if let Some(name) = &options.profile {
+        return profiles
+            .into_iter()
+            .find(|profile| &profile.name == name)
+            .ok_or_else(|| Failure::usage(format!("There is no profile named '{}'", name)));
+    }
+    if let Some(saved) = saved {
+        let profile = profiles.iter().find(|profile| Some(&profile.name) == saved.profile.as_ref() && profile.url == saved.server_url);
+        return Ok(profile.cloned().unwrap_or_else(|| Profile {
+            name: saved.server_url.clone(),
+            url: saved.server_url.clone(),
+            ..Profile::default()
+        }));
+    }
+    profiles
+        .into_iter()
+        .next()
+        .ok_or_else(|| Failure::usage("No server, pass --server URL or --profile NAME"))
+}
+
+struct Connection {
+    remote: Remote,
+    claims: Claims,
+    /// The profile encrypts uploads.
+    encrypt: bool,
+}
+
+impl Connection {
+    /// Fails early on what the token does not allow, the server checks again anyway.
+    fn require(&self, permission: Permission, action: &str) -> Result<(), Failure> {
+        if self.claims.can(permission) {
+            Ok(())
+        } else {
+            Err(Failure::new(EXIT_DENIED, denied(action)))
+        }
+    }
+
+    async fn listing(&self) -> Result<Vec<ListedFile>, Failure> {
+        Ok(self.remote.listing().await?)
+    }
+
+    /// Uploads of an encrypting profile need a key, it is made on the first one.
+    fn prepare_upload(&mut self) -> Result<(), Failure> {
+        if self.encrypt && self.remote.key.is_none() {
+            self.remote.key = Some(key_for_upload(&self.remote.server_url)?);
+        }
+        Ok(())
+    }
+
+    /// What a local file called `name` is stored as.
+    fn server_name(&self, name: String) -> String {
+        if self.encrypt { encrypted_name(&name) } else { name }
+    }
+}
+
+async fn connect(options: &Options) -> Result<Connection, Failure> {
+    let saved = load_session();
+    let profile = pick_profile(options, saved.as_ref())?;
+    let client = profile.client()?;
+    let verifier = Verifier::from_env();
+    let auth = |e: String| Failure::new(EXIT_AUTH, e);
+    let encrypt = profile.encrypt;
+    let key = load_key(&profile.url);
+
+    if let Ok(token) = env::var("FTA_TOKEN") {
+        let claims = verifier?.decode(&token).map_err(auth)?;
+        let remote = Remote { client, server_url: profile.url, token, key, encrypt };
+        return Ok(Connection { remote, claims, encrypt });
+    }
+
+    let Some(mut session) = saved.filter(|saved| saved.server_url == profile.url) else {
+        return Err(auth(format!("Not logged in to {}, run `fta-cli login` first", profile.url)));
+    };
+    if api::is_token_expired(session.token_exp) {
+        let Some(refresh_token) = session.refresh_token.clone() else {
+            return Err(auth(String::from(SESSION_EXPIRED)));
+        };
+        let tokens = api::refresh_request(client.clone(), profile.url.clone(), refresh_token, verifier.clone())
+            .await
+            .map_err(auth)?;
+        session = SavedSession::new(&profile.url, session.profile, &tokens);
+        save_session(&session)?;
+    }
+
+    let claims = verifier?.decode(&session.token).map_err(auth)?;
+    let remote = Remote { client, server_url: profile.url, token: session.token, key, encrypt };
+    Ok(Connection { remote, claims, encrypt })
+}
+
+fn read_password() -> Result<String, Failure> {
+    eprint!("Password: ");
+    let _ = io::stderr().flush();
+
+    let mut password = String::new();
+    io::stdin().read_line(&mut password).map_err(|e| Failure::new(EXIT_FAILED, e.to_string()))?;
+    Ok(password.trim_end_matches(['\r', '\n']).to_string())
+}
+
+async fn login(options: &Options) -> Result<u8, Failure> {
+    let saved = load_session();
+    let profile = pick_profile(options, saved.as_ref())?;
+
+    let username = match options.args.as_slice() {
+        [] => env::var("FTA_USERNAME").ok().or(Some(profile.username.clone()).filter(|name| !name.is_empty())),
+        [username] => Some(username.clone()),
+        _ => return Err(Failure::usage("login takes one username")),
+    }
+    .ok_or_else(|| Failure::usage("login needs a username, or FTA_USERNAME"))?;
+    let password = match env::var("FTA_PASSWORD") {
+        Ok(password) => password,
+        Err(_) => read_password()?,
+    };
+
+    let tokens = api::log_in_request(profile.client()?, profile.url.clone(), username.clone(), password, Verifier::from_env())
+        .await
+        .map_err(|e| if e == WRONG_LOGIN { Failure::new(EXIT_AUTH, e) } else { Failure::from(e) })?;
+    let profile_name = options.profile.clone().or(saved.and_then(|saved| saved.profile).filter(|name| *name == profile.name));
+    save_session(&SavedSession::new(&profile.url, profile_name, &tokens))?;
+
+    if options.json {
+        println!("{}", json!({ "server": profile.url, "username": username, "expires": tokens.token_exp }));
+    } else {
+        let expires = format_timestamp(Some(tokens.token_exp));
+        println!("Logged in to {} as {}, the token expires {}", profile.url, username, expires);
+    }
+    Ok(0)
+}
+
+fn file_json(file: &ListedFile) -> Value {
+    json!({
+        "name": file.info.name,
+        "size": file.info.size,
+        "created": file.info.created,
+        "modified": file.info.modified,
+        "content_type": file.info.mime(),
+        "owner": file.info.owner,
+        "checksum": file.info.checksum,
+        "is_dir": file.info.is_dir,
+        "encrypted": is_encrypted(&file.info.name),
+        "chunks": file.chunks,
+    })
+}
+
+async fn list(options: &Options) -> Result<u8, Failure> {
+    let query = Query::parse(&options.args.join(" ")).map_err(Failure::usage)?;
+    let connection = connect(options).await?;
+
+    let files: Vec<ListedFile> = connection
+        .listing()
+        .await?
+        .into_iter()
+        .filter(|file| query.matches(&file.info))
+        .collect();
+
+    if options.json {
+        println!("{}", Value::Array(files.iter().map(file_json).collect()));
+    } else {
+        for file in &files {
+            let size = file.info.size.map_or_else(|| String::from("-"), |size| format_size(size as f64));
+            println!("{:>10}  {:16}  {}", size, format_timestamp(file.info.modified), file.info.name);
+        }
+    }
+    Ok(0)
+}
+
+/// Server folder path for `--to`: no leading separator, one trailing one, empty for the root.
+fn remote_dir(dir: Option<&str>) -> String {
+    match dir.map(|dir| dir.trim_matches(SEPARATOR)) {
+        None | Some("") => String::new(),
+        Some(dir) => format!("{}{}", dir, SEPARATOR),
+    }
+}
+
+/// Prints progress on stderr when it is a terminal. Uploads know `total` up front,
+/// downloads learn it from the server.
+fn progress(name: &str, total: u64, quiet: bool) -> impl Sink<Progress, Error = Never> + Unpin {
+    let name = name.to_string();
+    let quiet = quiet || !io::stderr().is_terminal();
+
+    sink::unfold((total, false), move |(total, shown), progress| {
+        let state = match progress {
+            Progress::Started(total) => (total, shown),
+            Progress::Resumed(done) | Progress::Advanced(done) if !quiet => {
+                eprint!("\r{}  {} / {}", name, format_size(done as f64), format_size(total as f64));
+                (total, true)
+            }
+            Progress::Finished | Progress::Errored(_) if shown => {
+                eprintln!();
+                (total, false)
+            }
+            _ => (total, shown),
+        };
+        future::ready(Ok(state))
+    })
+}
+
+/// Prints a batch's results and returns the exit code of its first failure.
+fn report(options: &Options, items: Vec<(Value, Result<String, Failure>)>) -> u8 {
+    let code = items
+        .iter()
+        .find_map(|(_, result)| result.as_ref().err().map(|failure| failure.code))
+        .unwrap_or(0);
+
+    if options.json {
+        let items: Vec<Value> = items
+            .into_iter()
+            .map(|(mut item, result)| {
+                item["error"] = match result {
+                    Ok(_) => Value::Null,
+                    Err(failure) => Value::String(failure.message),
+                };
+                item
+            })
+            .collect();
+        println!("{}", Value::Array(items));
+    } else {
+        for (_, result) in items {
+            match result {
+                Ok(line) => println!("{}", line),
+                Err(failure) => eprintln!("fta-cli: {}", failure.message),
+            }
+        }
+    }
+    code
+}
+
+async fn upload(options: &Options) -> Result<u8, Failure> {
+    if options.args.is_empty() {
+        return Err(Failure::usage("upload needs at least one path"));
+    }
+    let mut connection = connect(options).await?;
+    connection.require(Permission::Upload, "upload files")?;
+    connection.prepare_upload()?;
+
+    let limit = connection.remote.size_limit().await;
+    let target_dir = remote_dir(options.to.as_deref());
+
+    let mut items = vec![];
+    for arg in &options.args {
+        let path = PathBuf::from(arg);
+        let files = match fs::metadata(&path) {
+            Ok(metadata) if metadata.is_dir() => match scan(path.clone(), target_dir.clone(), u64::MAX) {
+                Ok(folder) => folder
+                    .files
+                    .into_iter()
+                    .map(|file| (file.path, format!("{}{}", target_dir, file.relative), file.size))
+                    .collect(),
+                Err(e) => {
+                    items.push((json!({ "path": arg }), Err(Failure::from(e))));
+                    continue;
+                }
+            },
+            Ok(metadata) => {
+                let name = join(&target_dir, &path.file_name().unwrap_or_default().to_string_lossy());
+                vec![(path.clone(), name, metadata.len())]
+            }
+            Err(e) => {
+                items.push((json!({ "path": arg }), Err(Failure::new(EXIT_NOT_FOUND, format!("{}: {}", arg, e)))));
+                continue;
+            }
+        };
+
+        for (path, name, size) in files {
+            let name = connection.server_name(name);
+            let item = json!({ "path": path, "name": name, "size": size });
+            let result = connection.remote
+                .upload(&path, &name, size, limit, |piece, total| progress(piece, total, options.json))
+                .await
+                .map(|()| format!("Uploaded {}", name))
+                .map_err(|e| Failure::from(format!("{}: {}", name, e)));
+            items.push((item, result));
+        }
+    }
+
+    Ok(report(options, items))
+}
+
+async fn download(options: &Options) -> Result<u8, Failure> {
+    if options.args.is_empty() {
+        return Err(Failure::usage("download needs at least one file name"));
+    }
+    let dir = match &options.to {
+        Some(dir) => PathBuf::from(dir),
+        None => env::current_dir().map_err(|e| Failure::new(EXIT_FAILED, e.to_string()))?,
+    };
+    if !dir.is_dir() {
+        return Err(Failure::new(EXIT_NOT_FOUND, format!("{:?} is not a folder", dir)));
+    }
+
+    let connection = connect(options).await?;
+    connection.require(Permission::Download, "download files")?;
+    let listing = connection.listing().await?;
+    let policy = options.collision.unwrap_or(Collision::Rename);
+
+    let mut claimed = HashSet::new();
+    let mut items = vec![];
+    for name in &options.args {
+        let Some(file) = listing.iter().find(|file| &file.info.name == name) else {
+            items.push((json!({ "name": name }), Err(Failure::new(EXIT_NOT_FOUND, format!("{} is not on the server", name)))));
+            continue;
+        };
+        let Some(target) = resolve(dir.join(plain_name(base_name(name))), policy, &claimed) else {
+            items.push((json!({ "name": name, "skipped": true }), Ok(format!("Skipped {}, it already exists", name))));
+            continue;
+        };
+        claimed.insert(target.clone());
+
+        let item = json!({ "name": name, "path": target, "skipped": false });
+        let result = connection.remote
+            .download(file, &target, |piece, total| progress(piece, total, options.json))
+            .await
+            .map(|()| format!("Downloaded {} to {}", name, target.display()))
+            .map_err(|e| Failure::from(format!("{}: {}", name, e)));
+        items.push((item, result));
+    }
+
+    Ok(report(options, items))
+}
+
+async fn remove(options: &Options) -> Result<u8, Failure> {
+    if options.args.is_empty() {
+        return Err(Failure::usage("rm needs at least one file name"));
+    }
+    let connection = connect(options).await?;
+    connection.require(Permission::Delete, "delete files")?;
+    let listing = connection.listing().await?;
+
+    let mut items = vec![];
+    for name in &options.args {
+        let result = match listing.iter().find(|file| &file.info.name == name) {
+            Some(file) => connection.remote
+                .delete(file.server_names())
+                .await
+                .map(|()| format!("Deleted {}", name))
+                .map_err(|e| Failure::from(format!("{}: {}", name, e))),
+            None => Err(Failure::new(EXIT_NOT_FOUND, format!("{} is not on the server", name))),
+        };
+        items.push((json!({ "name": name }), result));
+    }
+
+    Ok(report(options, items))
+}
+
+/// Why `file` needs no upload over `existing`, `None` when it does. Encrypted copies
+/// can't be compared by checksum, and neither can files the server lists none for,
+/// those count as unchanged when the size matches and the local file is not newer.
+fn unchanged(file: &ScannedFile, existing: &ListedFile, encrypt: bool) -> Option<&'static str> {
+    if !encrypt {
+        if let Some(checksum) = existing.info.checksum.as_deref().and_then(parse_sha256) {
+            return sha256_file(&file.path).is_ok_and(|local| local == checksum).then_some("same checksum");
+        }
+    }
+
+    let size = if encrypt { encrypted_size(file.size) } else { file.size };
+    let mtime = fs::metadata(&file.path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .map(|modified| modified.as_secs() as i64)?;
+    let uploaded = existing.info.modified?;
+    (existing.info.size == Some(size) && mtime <= uploaded).then_some("same size, not changed since the upload")
+}
+
+/// One way: the server folder is made to look like `LOCAL_DIR`.
+async fn sync(options: &Options) -> Result<u8, Failure> {
+    let (local, dir) = match options.args.as_slice() {
+        [local] => (local, None),
+        [local, dir] => (local, Some(dir.as_str())),
+        _ => return Err(Failure::usage("sync takes a local folder and optionally a server folder")),
+    };
+    // Canonical, so that `.` still has a name to use on the server.
+    let root = match fs::canonicalize(local) {
+        Ok(root) if root.is_dir() => root,
+        _ => return Err(Failure::new(EXIT_NOT_FOUND, format!("{} is not a folder", local))),
+    };
+
+    let mut connection = connect(options).await?;
+    connection.require(Permission::Upload, "upload files")?;
+    if options.delete {
+        connection.require(Permission::Delete, "delete files")?;
+    }
+    connection.prepare_upload()?;
+
+    let target_dir = remote_dir(dir);
+    let folder = scan(root, target_dir.clone(), u64::MAX)?;
+    let limit = connection.remote.size_limit().await;
+    let listing = connection.listing().await?;
+    let remote: HashMap<&str, &ListedFile> = listing.iter().map(|file| (file.info.name.as_str(), file)).collect();
+    let can_delete = connection.claims.can(Permission::Delete);
+
+    let mut uploaded = vec![];
+    let mut deleted = vec![];
+    let mut errors = vec![];
+    let mut skipped = vec![];
+    let mut local_names = HashSet::new();
+
+    for file in &folder.files {
+        let name = connection.server_name(format!("{}{}", target_dir, file.relative));
+        local_names.insert(name.clone());
+
+        let existing = remote.get(name.as_str());
+        if let Some(reason) = existing.and_then(|existing| unchanged(file, existing, connection.encrypt)) {
+            skipped.push((name, reason));
+            continue;
+        }
+        // A changed chunked file may be cut into a different number of chunks now.
+        if let Some(existing) = existing.filter(|existing| !existing.chunks.is_empty() && can_delete) {
+            if let Err(e) = connection.remote.delete(existing.chunks.clone()).await {
+                errors.push((name, Failure::from(e)));
+                continue;
+            }
+        }
+        let upload = connection.remote.upload(&file.path, &name, file.size, limit, |piece, total| progress(piece, total, options.json));
+        match upload.await {
+            Ok(()) => uploaded.push(name),
+            Err(e) => errors.push((name, Failure::from(e))),
+        }
+    }
+
+    if options.delete {
+        let prefix = format!("{}{}{}", target_dir, base_name(&folder.root.to_string_lossy()), SEPARATOR);
+        for file in listing.iter().filter(|file| file.info.name.starts_with(&prefix) && !file.info.is_dir) {
+            if local_names.contains(&file.info.name) {
+                continue;
+            }
+            match connection.remote.delete(file.server_names()).await {
+                Ok(()) => deleted.push(file.info.name.clone()),
+                Err(e) => errors.push((file.info.name.clone(), Failure::from(e))),
+            }
+        }
+    }
+
+    let code = errors.first().map_or(0, |(_, failure)| failure.code);
+    if options.json {
+        let errors: Vec<Value> = errors
+            .iter()
+            .map(|(name, failure)| json!({ "name": name, "error": failure.message }))
+            .collect();
+        let skipped: Vec<Value> = skipped
+            .iter()
+            .map(|(name, reason)| json!({ "name": name, "reason": reason }))
+            .collect();
+        println!("{}", json!({
+            "uploaded": uploaded,
+            "deleted": deleted,
+            "unchanged": skipped.len(),
+            "skipped": skipped,
+            "ignored": folder.ignored,
+            "errors": errors,
+        }));
+    } else {
+        for name in &uploaded {
+            println!("Uploaded {}", name);
+        }
+        for name in &deleted {
+            println!("Deleted {}", name);
+        }
+        for (name, reason) in &skipped {
+            println!("Skipped {}, {}", name, reason);
+        }
+        for (name, failure) in &errors {
+            eprintln!("fta-cli: {}: {}", name, failure.message);
+        }
+        println!("{} uploaded, {} deleted, {} unchanged, {} failed", uploaded.len(), deleted.len(), skipped.len(), errors.len());
+    }
+    Ok(code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Options, Failure> {
+        parse_args(args.iter().map(|arg| arg.to_string()).collect())
+    }
+
+    #[test]
+    fn parses_options_around_the_command() {
+        let options = parse(&["--json", "download", "a.txt", "--to", "out", "b.txt", "--skip-existing"]).unwrap();
+        assert!(options.json);
+        assert_eq!(options.command, "download");
+        assert_eq!(options.args, ["a.txt", "b.txt"]);
+        assert_eq!(options.to.as_deref(), Some("out"));
+        assert_eq!(options.collision, Some(Collision::Skip));
+
+        let options = parse(&["--server", "https://example.com/", "sync", ".", "--delete"]).unwrap();
+        assert_eq!(options.server.as_deref(), Some("https://example.com"));
+        assert!(options.delete);
+    }
+
+    #[test]
+    fn rejects_wrong_usage() {
+        for args in [
+            &[][..],
+            &["--json"],
+            &["ls", "--bogus"],
+            &["upload", "a", "--to"],
+            &["--server", "https://a", "--profile", "b", "ls"],
+        ] {
+            assert_eq!(parse(args).unwrap_err().code, EXIT_USAGE, "{:?}", args);
+        }
+    }
+
+    #[test]
+    fn maps_library_errors_to_exit_codes() {
+        assert_eq!(Failure::from(String::from("Connection refused")).code, EXIT_FAILED);
+        assert_eq!(Failure::from(denied("delete files")).code, EXIT_DENIED);
+        assert_eq!(Failure::from(String::from(SESSION_EXPIRED)).code, EXIT_AUTH);
+        assert_eq!(Failure::usage("No command given").code, EXIT_USAGE);
+        assert_eq!(
+            (EXIT_FAILED, EXIT_USAGE, EXIT_AUTH, EXIT_DENIED, EXIT_NOT_FOUND),
+            (1, 2, 3, 4, 5)
+        );
+    }
+
+    #[test]
+    fn batches_exit_with_the_first_failure() {
+        let options = Options { json: true, ..Options::default() };
+        let ok = || (json!({}), Ok(String::new()));
+        let failed = |code| (json!({}), Err(Failure::new(code, "failed")));
+
+        assert_eq!(report(&options, vec![ok(), ok()]), 0);
+        assert_eq!(report(&options, vec![ok(), failed(EXIT_NOT_FOUND), failed(EXIT_DENIED)]), EXIT_NOT_FOUND);
+    }
+}
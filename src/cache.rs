@@ -0,0 +1,75 @@
+//! Files fetched or made only for showing, kept in the user's cache directory so they are
+//! still there next time. Everything in it may disappear at any time.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::checksum::Sha256;
+use crate::file_info::FileInfo;
+
+pub const PREVIEW_CACHE: &str = "previews";
+pub const THUMBNAIL_CACHE: &str = "thumbnails";
+
+/// Bytes kept per kind of cache, the oldest files go first.
+const CACHE_LIMIT: u64 = 256 * 1024 * 1024;
+
+pub fn cache_dir(kind: &str) -> PathBuf {
+    dirs_next::cache_dir()
+        .unwrap_or_else(env::temp_dir)
+        .join("fta")
+        .join(kind)
+}
+
+/// Cache file name for `file` on `server_url`. It changes with the checksum, or with size
+/// and modification time on servers that don't list checksums, so stale entries aren't used.
+pub fn cache_key(server_url: &str, file: &FileInfo) -> String {
+    let version = match &file.checksum {
+        Some(checksum) => checksum.clone(),
+        None => format!("{:?}-{:?}", file.size, file.modified.or(file.created)),
+    };
+
+    let mut sha256 = Sha256::new();
+    for part in [server_url, file.name.as_str(), version.as_str()] {
+        sha256.update(part.as_bytes());
+        sha256.update(&[0]);
+    }
+    sha256.finish()
+}
+
+pub fn read(dir: &Path, key: &str) -> Option<Vec<u8>> {
+    fs::read(dir.join(key)).ok()
+}
+
+/// Stores `bytes` as `key` and makes room by dropping the oldest entries.
+pub fn write(dir: &Path, key: &str, bytes: &[u8]) -> Result<(), String> {
+    fs::create_dir_all(dir).map_err(|e| format!("{:?}: {}", dir, e))?;
+    let path = dir.join(key);
+    fs::write(&path, bytes).map_err(|e| format!("{:?}: {}", path, e))?;
+    prune(dir);
+    Ok(())
+}
+
+fn prune(dir: &Path) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    let mut files: Vec<_> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok().filter(|metadata| metadata.is_file())?;
+            Some((metadata.modified().ok(), metadata.len(), entry.path()))
+        })
+        .collect();
+    files.sort();
+
+    let mut total: u64 = files.iter().map(|(_, size, _)| size).sum();
+    for (_, size, path) in files {
+        if total <= CACHE_LIMIT {
+            break;
+        }
+        if fs::remove_file(&path).is_ok() {
+            total -= size;
+        }
+    }
+}
@@ -0,0 +1,114 @@
+//! SHA-256 checksums, to tell whether a transfer arrived intact.
+//!
+//! Servers report them in the listing and in a [`CHECKSUM_HEADER`] on downloads, as bare hex
+//! or as `sha256:<hex>`. Uploads send theirs in the same header.
+
+use std::fs;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+use ring::digest::{Context, SHA256};
+
+pub const CHECKSUM_HEADER: &str = "X-Checksum-SHA256";
+pub const CHECKSUM_MISMATCH: &str = "Checksum mismatch";
+
+/// A running SHA-256, fed as the bytes go by.
+pub struct Sha256(Context);
+
+impl Sha256 {
+    pub fn new() -> Self {
+        Sha256(Context::new(&SHA256))
+    }
+
+    pub fn update(&mut self, bytes: &[u8]) {
+        self.0.update(bytes);
+    }
+
+    /// Lowercase hex.
+    pub fn finish(self) -> String {
+        self.0.finish().as_ref().iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+}
+
+impl Default for Sha256 {
+    fn default() -> Self {
+        Sha256::new()
+    }
+}
+
+pub fn sha256_file(path: &Path) -> Result<String, String> {
+    sha256_range(path, 0, u64::MAX)
+}
+
+/// Checksum of `length` bytes of `path` from `offset` on, or up to the end if it is shorter.
+pub fn sha256_range(path: &Path, offset: u64, length: u64) -> Result<String, String> {
+    let mut file = fs::File::open(path).map_err(|e| format!("{:?}: {}", path, e))?;
+    file.seek(SeekFrom::Start(offset)).map_err(|e| e.to_string())?;
+
+    let mut file = file.take(length);
+    let mut sha256 = Sha256::new();
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer).map_err(|e| e.to_string())?;
+        if read == 0 {
+            break;
+        }
+        sha256.update(&buffer[..read]);
+    }
+    Ok(sha256.finish())
+}
+
+/// `value` as lowercase hex if it is a SHA-256 in one of the forms servers use.
+pub fn parse_sha256(value: &str) -> Option<String> {
+    let value = value.trim();
+    let hex = value.strip_prefix("sha256:").unwrap_or(value).to_lowercase();
+    (hex.len() == 64 && hex.bytes().all(|byte| byte.is_ascii_hexdigit())).then_some(hex)
+}
+
+pub fn mismatch(expected: &str, actual: &str) -> String {
+    format!("{}, expected {} but got {}", CHECKSUM_MISMATCH, &expected[..12], &actual[..12])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ABC: &str = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+
+    #[test]
+    fn parses_server_checksums() {
+        assert_eq!(parse_sha256(ABC).as_deref(), Some(ABC));
+        assert_eq!(parse_sha256(&format!("sha256:{}", ABC)).as_deref(), Some(ABC));
+        assert_eq!(parse_sha256(&format!(" {} \n", ABC.to_uppercase())).as_deref(), Some(ABC));
+        assert_eq!(parse_sha256(&ABC[1..]), None);
+        assert_eq!(parse_sha256(&format!("{}0", ABC)), None);
+        assert_eq!(parse_sha256(&format!("md5:{}", ABC)), None);
+        assert_eq!(parse_sha256(&ABC.replace('b', "g")), None);
+        assert_eq!(parse_sha256(""), None);
+    }
+
+    #[test]
+    fn hashes_bytes_and_ranges() {
+        let mut sha256 = Sha256::new();
+        sha256.update(b"a");
+        sha256.update(b"bc");
+        assert_eq!(sha256.finish(), ABC);
+
+        let path = std::env::temp_dir().join(format!("fta-checksum-{}", std::process::id()));
+        fs::write(&path, b"xxabcyy").unwrap();
+        let range = sha256_range(&path, 2, 3);
+        let past_end = sha256_range(&path, 5, 100);
+        let _ = fs::remove_file(&path);
+
+        assert_eq!(range.unwrap(), ABC);
+        let mut yy = Sha256::new();
+        yy.update(b"yy");
+        assert_eq!(past_end.unwrap(), yy.finish());
+        assert!(sha256_file(&path).is_err());
+    }
+
+    #[test]
+    fn describes_mismatches() {
+        let other = "0".repeat(64);
+        assert_eq!(mismatch(ABC, &other), "Checksum mismatch, expected ba7816bf8f01 but got 000000000000");
+    }
+}
@@ -0,0 +1,203 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use tokio::io::AsyncWriteExt;
+
+use crate::file_info::FileInfo;
+
+/// Files bigger than the server limit are uploaded as `name.chunk001of003`, `name.chunk002of003`, ...
+const CHUNK_MARKER: &str = ".chunk";
+
+pub fn chunk_name(file_name: &str, index: usize, count: usize) -> String {
+    format!("{}{}{:03}of{:03}", file_name, CHUNK_MARKER, index + 1, count)
+}
+
+/// Splits a chunk name back into the original file name, the zero-based index and the chunk count.
+pub fn parse_chunk_name(name: &str) -> Option<(&str, usize, usize)> {
+    let marker = name.rfind(CHUNK_MARKER)?;
+    let (base, suffix) = (&name[..marker], &name[marker + CHUNK_MARKER.len()..]);
+    let (index, count) = suffix.split_once("of")?;
+
+    if index.is_empty() || !index.bytes().all(|b| b.is_ascii_digit()) || !count.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let index: usize = index.parse().ok()?;
+    let count: usize = count.parse().ok()?;
+
+    if base.is_empty() || index == 0 || index > count {
+        return None;
+    }
+    Some((base, index - 1, count))
+}
+
+/// `(offset, length)` of every chunk of a `size` bytes file cut into `chunk_size` pieces.
+pub fn chunk_ranges(size: u64, chunk_size: u64) -> Vec<(u64, u64)> {
+    let chunk_size = chunk_size.max(1);
+
+    (0..size.div_ceil(chunk_size))
+        .map(|index| {
+            let offset = index * chunk_size;
+            (offset, chunk_size.min(size - offset))
+        })
+        .collect()
+}
+
+/// A server listing entry: either a plain file or a complete set of chunks shown as one file.
+#[derive(Debug, Clone)]
+pub struct ListedFile {
+    pub info: FileInfo,
+    /// Server names of the chunks in order, empty for plain files.
+    pub chunks: Vec<String>,
+}
+
+impl ListedFile {
+    /// Names to address on the server for this file.
+    pub fn server_names(&self) -> Vec<String> {
+        if self.chunks.is_empty() {
+            vec![self.info.name.clone()]
+        } else {
+            self.chunks.clone()
+        }
+    }
+}
+
+/// Folds complete chunk sets into a single entry. Incomplete sets stay visible chunk by chunk
+/// so that the user can still see and delete them.
+pub fn group_chunks(files: Vec<FileInfo>) -> Vec<ListedFile> {
+    let mut sets: BTreeMap<(String, usize), Vec<Option<FileInfo>>> = BTreeMap::new();
+    let mut listed = vec![];
+    // A set with more chunks than the listing has entries can't be complete, and its
+    // count comes from the server, so it must not size anything.
+    let limit = files.len();
+
+    for file in files {
+        let chunk = parse_chunk_name(&file.name)
+            .filter(|(_, _, count)| *count <= limit)
+            .map(|(base, index, count)| (base.to_string(), index, count));
+
+        match chunk {
+            Some((base, index, count)) => {
+                let slot = &mut sets.entry((base, count)).or_insert_with(|| vec![None; count])[index];
+                // `x.chunk1of2` and `x.chunk001of2` are the same chunk, keep the second one listed.
+                match slot {
+                    Some(_) => listed.push(ListedFile { info: file, chunks: vec![] }),
+                    None => *slot = Some(file),
+                }
+            }
+            None => listed.push(ListedFile { info: file, chunks: vec![] }),
+        }
+    }
+
+    for ((base, _), set) in sets {
+        if set.iter().all(Option::is_some) {
+            let parts: Vec<FileInfo> = set.into_iter().flatten().collect();
+            listed.push(ListedFile {
+                info: merge_chunks(base, &parts),
+                chunks: parts.into_iter().map(|part| part.name).collect(),
+            });
+        } else {
+            listed.extend(set.into_iter().flatten().map(|chunk| ListedFile { info: chunk, chunks: vec![] }));
+        }
+    }
+
+    listed
+}
+
+fn merge_chunks(name: String, parts: &[FileInfo]) -> FileInfo {
+    FileInfo {
+        name,
+        size: parts.iter().map(|part| part.size).sum(),
+        created: parts.iter().filter_map(|part| part.created).min(),
+        modified: parts.iter().filter_map(|part| part.modified).max(),
+        content_type: None,
+        owner: parts.first().and_then(|part| part.owner.clone()),
+        checksum: None,
+        is_dir: false,
+    }
+}
+
+/// Download of a chunked file: once every job in `jobs` is done, `parts` are joined into `target`.
+/// There is no checksum of the whole file to verify the result with, only each chunk's own.
+pub struct Assembly {
+    pub target: PathBuf,
+    pub parts: Vec<PathBuf>,
+    pub jobs: Vec<usize>,
+}
+
+/// Concatenates the downloaded chunks into `target` and removes them.
+pub async fn assemble(parts: Vec<PathBuf>, target: PathBuf) -> Result<PathBuf, String> {
+    let mut file = tokio::fs::File::create(&target).await.map_err(|e| e.to_string())?;
+
+    for part in &parts {
+        let mut chunk = tokio::fs::File::open(part).await.map_err(|e| format!("{:?}: {}", part, e))?;
+        tokio::io::copy(&mut chunk, &mut file).await.map_err(|e| e.to_string())?;
+    }
+    file.flush().await.map_err(|e| e.to_string())?;
+
+    for part in &parts {
+        let _ = tokio::fs::remove_file(part).await;
+    }
+
+    Ok(target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(name: &str, size: u64) -> FileInfo {
+        FileInfo {
+            name: name.to_string(),
+            size: Some(size),
+            ..Default::default()
+        }
+    }
+
+    fn names(listed: &[ListedFile]) -> Vec<&str> {
+        listed.iter().map(|file| file.info.name.as_str()).collect()
+    }
+
+    #[test]
+    fn chunk_names_round_trip() {
+        assert_eq!(chunk_name("a.bin", 0, 3), "a.bin.chunk001of003");
+        assert_eq!(parse_chunk_name("a.bin.chunk001of003"), Some(("a.bin", 0, 3)));
+        assert_eq!(parse_chunk_name("a.chunk.bin.chunk1234of1234"), Some(("a.chunk.bin", 1233, 1234)));
+        for name in ["a.bin", "a.chunk000of003", "a.chunk004of003", ".chunk001of001", "a.chunkof3", "a.chunk1of", "a.chunk+1of2", "a.chunk1of99999999999999999999999"] {
+            assert_eq!(parse_chunk_name(name), None, "{}", name);
+        }
+    }
+
+    #[test]
+    fn splits_into_ranges() {
+        assert_eq!(chunk_ranges(10, 4), vec![(0, 4), (4, 4), (8, 2)]);
+        assert_eq!(chunk_ranges(8, 4), vec![(0, 4), (4, 4)]);
+        assert_eq!(chunk_ranges(0, 4), vec![]);
+    }
+
+    #[test]
+    fn groups_complete_sets() {
+        let listed = group_chunks(vec![
+            file("big.iso.chunk002of002", 5),
+            file("plain.txt", 1),
+            file("big.iso.chunk001of002", 10),
+            file("half.bin.chunk001of002", 3),
+        ]);
+        assert_eq!(names(&listed), vec!["plain.txt", "big.iso", "half.bin.chunk001of002"]);
+        assert_eq!(listed[1].info.size, Some(15));
+        assert_eq!(listed[1].chunks, vec!["big.iso.chunk001of002", "big.iso.chunk002of002"]);
+        assert_eq!(listed[1].server_names(), listed[1].chunks);
+        assert_eq!(listed[0].server_names(), vec!["plain.txt"]);
+    }
+
+    #[test]
+    fn huge_counts_are_plain_files() {
+        let listed = group_chunks(vec![file("x.chunk001of99999999999", 1), file("y.chunk001of003", 1)]);
+        assert_eq!(names(&listed), vec!["x.chunk001of99999999999", "y.chunk001of003"]);
+    }
+
+    #[test]
+    fn duplicate_chunks_stay_listed() {
+        let listed = group_chunks(vec![file("x.chunk1of1", 1), file("x.chunk001of001", 1)]);
+        assert_eq!(names(&listed), vec!["x.chunk001of001", "x"]);
+        assert_eq!(listed[1].chunks, vec!["x.chunk1of1"]);
+    }
+}
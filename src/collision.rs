@@ -0,0 +1,87 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// What to do when a download would land on a file that already exists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Collision {
+    /// Keep both, the new file becomes `name (1).ext`.
+    Rename,
+    Skip,
+    Overwrite,
+}
+
+impl Collision {
+    pub const ALL: [Collision; 3] = [Collision::Rename, Collision::Skip, Collision::Overwrite];
+}
+
+impl fmt::Display for Collision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Collision::Rename => "Keep both",
+            Collision::Skip => "Skip existing",
+            Collision::Overwrite => "Overwrite",
+        })
+    }
+}
+
+/// Where to write `path` under `policy`, `None` to skip it. `claimed` holds the targets
+/// of the same batch, those are never overwritten: two downloads can't share a file.
+pub fn resolve(path: PathBuf, policy: Collision, claimed: &HashSet<PathBuf>) -> Option<PathBuf> {
+    if claimed.contains(&path) {
+        return Some(free_name(&path, claimed));
+    }
+    if !path.exists() {
+        return Some(path);
+    }
+
+    match policy {
+        Collision::Rename => Some(free_name(&path, claimed)),
+        Collision::Skip => None,
+        Collision::Overwrite => Some(path),
+    }
+}
+
+/// The first `name (n).ext` that is neither on disk nor claimed.
+fn free_name(path: &Path, claimed: &HashSet<PathBuf>) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = path.extension().map(|extension| format!(".{}", extension.to_string_lossy()));
+
+    (1..)
+        .map(|n| path.with_file_name(format!("{} ({}){}", stem, n, extension.as_deref().unwrap_or(""))))
+        .find(|candidate| !candidate.exists() && !claimed.contains(candidate))
+        .unwrap_or_else(|| path.to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn resolves_existing_and_claimed_files() {
+        let dir = std::env::temp_dir().join(format!("fta-collision-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let existing = dir.join("report.pdf");
+        fs::write(&existing, "old").unwrap();
+        fs::write(dir.join("report (1).pdf"), "old").unwrap();
+        let none = HashSet::new();
+
+        let fresh = dir.join("new.txt");
+        assert_eq!(resolve(fresh.clone(), Collision::Skip, &none), Some(fresh.clone()));
+        assert_eq!(resolve(existing.clone(), Collision::Rename, &none), Some(dir.join("report (2).pdf")));
+        assert_eq!(resolve(existing.clone(), Collision::Overwrite, &none), Some(existing.clone()));
+        assert_eq!(resolve(existing.clone(), Collision::Skip, &none), None);
+        assert_eq!(resolve(dir.join("README"), Collision::Rename, &none), Some(dir.join("README")));
+
+        // A target already taken by the batch is renamed whatever the policy.
+        let claimed = HashSet::from([fresh.clone(), dir.join("report (2).pdf")]);
+        assert_eq!(resolve(fresh, Collision::Overwrite, &claimed), Some(dir.join("new (1).txt")));
+        assert_eq!(resolve(existing.clone(), Collision::Rename, &claimed), Some(dir.join("report (3).pdf")));
+        assert_eq!(resolve(existing, Collision::Skip, &claimed), None);
+
+        fs::write(dir.join("README"), "").unwrap();
+        assert_eq!(resolve(dir.join("README"), Collision::Rename, &none), Some(dir.join("README (1)")));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
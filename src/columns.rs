@@ -0,0 +1,121 @@
+use std::cmp::Ordering;
+use std::fs;
+use serde::{Deserialize, Serialize};
+
+use crate::file_info::FileInfo;
+use crate::utils::data_dir;
+
+const LAYOUT_FILE: &str = "columns.json";
+const MIN_COLUMN_WIDTH: f32 = 50.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FileColumn {
+    Name,
+    Size,
+    Modified,
+    Type,
+    Owner,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SortOrder {
+    Ascending,
+    Descending,
+}
+
+/// Sort order and widths of the main file table, kept between sessions.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ColumnLayout {
+    /// `None` keeps the order the server returned.
+    pub sort_by: Option<FileColumn>,
+    pub order: SortOrder,
+    pub size_width: f32,
+    pub modified_width: f32,
+    pub type_width: f32,
+    pub owner_width: f32,
+}
+
+impl Default for ColumnLayout {
+    fn default() -> Self {
+        ColumnLayout {
+            sort_by: None,
+            order: SortOrder::Ascending,
+            size_width: 90.0,
+            modified_width: 140.0,
+            type_width: 160.0,
+            owner_width: 110.0,
+        }
+    }
+}
+
+impl ColumnLayout {
+    /// The layout older versions kept in `columns.json`, now part of the settings file.
+    pub fn load() -> Self {
+        fs::read_to_string(data_dir().join(LAYOUT_FILE))
+            .ok()
+            .and_then(|data| serde_json::from_str(&data).ok())
+            .unwrap_or_default()
+    }
+
+    /// `None` for the name column, which takes whatever room is left.
+    pub fn width(&self, column: FileColumn) -> Option<f32> {
+        match column {
+            FileColumn::Name => None,
+            FileColumn::Size => Some(self.size_width),
+            FileColumn::Modified => Some(self.modified_width),
+            FileColumn::Type => Some(self.type_width),
+            FileColumn::Owner => Some(self.owner_width),
+        }
+    }
+
+    pub fn set_width(&mut self, column: FileColumn, width: f32) {
+        let width = width.max(MIN_COLUMN_WIDTH);
+
+        match column {
+            FileColumn::Name => {}
+            FileColumn::Size => self.size_width = width,
+            FileColumn::Modified => self.modified_width = width,
+            FileColumn::Type => self.type_width = width,
+            FileColumn::Owner => self.owner_width = width,
+        }
+    }
+
+    /// Sorts by `column`, flipping the order when it already is the sort column.
+    pub fn toggle_sort(&mut self, column: FileColumn) {
+        if self.sort_by == Some(column) {
+            self.order = match self.order {
+                SortOrder::Ascending => SortOrder::Descending,
+                SortOrder::Descending => SortOrder::Ascending,
+            };
+        } else {
+            self.sort_by = Some(column);
+            self.order = SortOrder::Ascending;
+        }
+    }
+
+    pub fn compare(&self, a: &FileInfo, b: &FileInfo) -> Ordering {
+        let ordering = match self.sort_by {
+            None => Ordering::Equal,
+            Some(FileColumn::Name) => a.name.to_lowercase().cmp(&b.name.to_lowercase()),
+            Some(FileColumn::Size) => a.size.cmp(&b.size),
+            Some(FileColumn::Modified) => a.modified.or(a.created).cmp(&b.modified.or(b.created)),
+            Some(FileColumn::Type) => a.mime().cmp(&b.mime()),
+            Some(FileColumn::Owner) => a.owner.cmp(&b.owner),
+        };
+
+        match self.order {
+            SortOrder::Ascending => ordering,
+            SortOrder::Descending => ordering.reverse(),
+        }
+    }
+}
+
+/// A column border being dragged. `origin_x` is only known after the first cursor move.
+#[derive(Debug, Clone, Copy)]
+pub struct Resize {
+    pub column: FileColumn,
+    pub origin_x: Option<f32>,
+    pub origin_width: f32,
+}
+
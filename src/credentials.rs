@@ -0,0 +1,63 @@
+use std::fs;
+use std::path::PathBuf;
+use serde::{Deserialize, Serialize};
+
+use crate::crypto::Sealed;
+use crate::utils::{data_dir, write_private};
+
+const CREDENTIALS_FILE: &str = "credentials.json";
+const AAD: &[u8] = b"fta-credentials-v1";
+
+/// What "Remember me" keeps. The password is only stored for servers that hand out no refresh token.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedLogin {
+    pub server_url: String,
+    pub login: String,
+    #[serde(default)]
+    pub refresh_token: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+}
+
+pub fn is_saved() -> bool {
+    credentials_path().exists()
+}
+
+pub fn save(login: &SavedLogin, passphrase: &str) -> Result<(), String> {
+    if passphrase.is_empty() {
+        return Err(String::from("Choose a passphrase to remember the login"));
+    }
+
+    // On disk the login is JSON, sealed with a key derived from the passphrase.
+    let plaintext = serde_json::to_vec(login).map_err(|e| e.to_string())?;
+    let sealed = Sealed::new(passphrase, AAD, &plaintext)?;
+    let data = serde_json::to_string_pretty(&sealed).map_err(|e| e.to_string())?;
+
+    let path = credentials_path();
+    if let Some(dir) = path.parent() {
+        let _ = fs::create_dir_all(dir);
+    }
+    write_private(&path, data.as_bytes()).map_err(|e| format!("Failed to save login: {}", e))
+}
+
+pub fn load(passphrase: &str) -> Result<SavedLogin, String> {
+    let data = fs::read_to_string(credentials_path()).map_err(|_| String::from("No saved login"))?;
+    let sealed: Sealed = serde_json::from_str(&data).map_err(|e| format!("Saved login is damaged: {}", e))?;
+    if sealed.version != 1 {
+        return Err(format!("Unsupported saved login version {}", sealed.version));
+    }
+
+    let plaintext = sealed.open(passphrase, AAD).map_err(|_| String::from("Wrong passphrase"))?;
+
+    serde_json::from_slice(&plaintext).map_err(|e| format!("Saved login is damaged: {}", e))
+}
+
+pub fn forget() {
+    if let Err(e) = fs::remove_file(credentials_path()) {
+        eprintln!("Failed to remove saved login: {}", e);
+    }
+}
+
+fn credentials_path() -> PathBuf {
+    data_dir().join(CREDENTIALS_FILE)
+}
@@ -0,0 +1,100 @@
+use std::num::NonZeroU32;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, MAX_TAG_LEN, NONCE_LEN};
+use ring::pbkdf2;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+
+pub const KEY_LEN: usize = 32;
+pub const SALT_LEN: usize = 16;
+pub const PBKDF2_ITERATIONS: u32 = 210_000;
+/// Bytes [`seal`] adds to the plaintext.
+pub const OVERHEAD: usize = NONCE_LEN + MAX_TAG_LEN;
+
+/// Data sealed with a key derived from a passphrase, in a form that can be saved as JSON.
+#[derive(Serialize, Deserialize)]
+pub struct Sealed {
+    pub version: u32,
+    iterations: u32,
+    salt: String,
+    data: String,
+}
+
+impl Sealed {
+    pub fn new(passphrase: &str, aad: &[u8], plaintext: &[u8]) -> Result<Self, String> {
+        let salt: [u8; SALT_LEN] = random_bytes()?;
+        let key = derive_key(passphrase, &salt, PBKDF2_ITERATIONS);
+
+        Ok(Sealed {
+            version: 1,
+            iterations: PBKDF2_ITERATIONS,
+            salt: encode(&salt),
+            data: encode(&seal(&key, aad, plaintext)?),
+        })
+    }
+
+    pub fn open(&self, passphrase: &str, aad: &[u8]) -> Result<Vec<u8>, String> {
+        let key = derive_key(passphrase, &decode(&self.salt)?, self.iterations);
+        open(&key, aad, &decode(&self.data)?)
+    }
+}
+
+pub fn random_bytes<const N: usize>() -> Result<[u8; N], String> {
+    let mut bytes = [0; N];
+    SystemRandom::new()
+        .fill(&mut bytes)
+        .map_err(|_| String::from("No secure random source available"))?;
+    Ok(bytes)
+}
+
+/// PBKDF2-HMAC-SHA256, so a passphrase can stand in for a keyring.
+pub fn derive_key(passphrase: &str, salt: &[u8], iterations: u32) -> [u8; KEY_LEN] {
+    let mut key = [0; KEY_LEN];
+    let iterations = NonZeroU32::new(iterations).unwrap_or(NonZeroU32::MIN);
+    pbkdf2::derive(pbkdf2::PBKDF2_HMAC_SHA256, iterations, salt, passphrase.as_bytes(), &mut key);
+    key
+}
+
+/// AES-256-GCM with a random nonce, returned as `nonce || ciphertext || tag`.
+pub fn seal(key: &[u8; KEY_LEN], aad: &[u8], plaintext: &[u8]) -> Result<Vec<u8>, String> {
+    let key = aead_key(key)?;
+    let nonce: [u8; NONCE_LEN] = random_bytes()?;
+
+    let mut sealed = plaintext.to_vec();
+    key.seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::from(aad), &mut sealed)
+        .map_err(|_| String::from("Encryption failed"))?;
+
+    let mut output = nonce.to_vec();
+    output.append(&mut sealed);
+    Ok(output)
+}
+
+/// Reverses [`seal`]. Fails on a wrong key as well as on tampered data.
+pub fn open(key: &[u8; KEY_LEN], aad: &[u8], sealed: &[u8]) -> Result<Vec<u8>, String> {
+    if sealed.len() < NONCE_LEN {
+        return Err(String::from("Encrypted data is truncated"));
+    }
+    let key = aead_key(key)?;
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+    let nonce = Nonce::try_assume_unique_for_key(nonce).map_err(|_| String::from("Invalid nonce"))?;
+
+    let mut buffer = ciphertext.to_vec();
+    let plaintext = key.open_in_place(nonce, Aad::from(aad), &mut buffer)
+        .map_err(|_| String::from("Decryption failed, wrong key or damaged data"))?;
+    Ok(plaintext.to_vec())
+}
+
+pub fn encode(bytes: &[u8]) -> String {
+    STANDARD.encode(bytes)
+}
+
+pub fn decode(text: &str) -> Result<Vec<u8>, String> {
+    STANDARD.decode(text.trim()).map_err(|e| e.to_string())
+}
+
+fn aead_key(key: &[u8; KEY_LEN]) -> Result<LessSafeKey, String> {
+    UnboundKey::new(&AES_256_GCM, key)
+        .map(LessSafeKey::new)
+        .map_err(|_| String::from("Invalid key"))
+}
@@ -0,0 +1,413 @@
+//! End-to-end encryption of uploaded files, turned on per server profile.
+//!
+//! An encrypted file is stored under its name plus [`ENCRYPTED_SUFFIX`]. It starts with a
+//! header, `MAGIC` and a random key for this one file sealed with the server key. The name
+//! is only for people, whether a file is encrypted is told by its header. The
+//! contents follow in segments of `SEGMENT_LEN` bytes sealed with the file key, the last one
+//! shorter or empty. A segment's index and whether it is the last one go into its associated
+//! data, so segments can't be swapped, dropped or cut off unnoticed.
+//!
+//! Server keys are kept per server URL in `keys.json`, readable by the user only. Teammates
+//! get them as exports sealed with a passphrase. Keys replaced by an import stay in
+//! `keys.bak.json`.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use futures::{future, stream, Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use crate::chunks::parse_chunk_name;
+use crate::crypto::{self, Sealed, KEY_LEN, OVERHEAD};
+use crate::utils::{data_dir, write_private};
+
+pub const ENCRYPTED_SUFFIX: &str = ".fta";
+pub const NO_KEY: &str = "No key for encrypted files on this server, import one in the server's profile";
+
+const MAGIC: &[u8] = b"FTAE\x01";
+/// Bytes to read from the start of a file to tell [`has_header`].
+pub const MAGIC_LEN: usize = MAGIC.len();
+const HEADER_LEN: usize = MAGIC.len() + KEY_LEN + OVERHEAD;
+const SEGMENT_LEN: usize = 64 * 1024;
+const FILE_KEY_AAD: &[u8] = b"fta-file-key-v1";
+const EXPORT_AAD: &[u8] = b"fta-key-export-v1";
+const KEYS_FILE: &str = "keys.json";
+const REPLACED_KEYS_FILE: &str = "keys.bak.json";
+
+/// A server key, shared by everyone who may read the server's encrypted files.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct EncryptionKey([u8; KEY_LEN]);
+
+impl fmt::Debug for EncryptionKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("EncryptionKey(..)")
+    }
+}
+
+impl EncryptionKey {
+    pub fn generate() -> Result<Self, String> {
+        crypto::random_bytes().map(EncryptionKey)
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        bytes.try_into()
+            .map(EncryptionKey)
+            .map_err(|_| String::from("Not an encryption key"))
+    }
+}
+
+/// Whether `name`, or the file it is a chunk of, carries [`ENCRYPTED_SUFFIX`].
+pub fn is_encrypted(name: &str) -> bool {
+    let name = parse_chunk_name(name).map_or(name, |(base, _, _)| base);
+    name.len() > ENCRYPTED_SUFFIX.len() && name.ends_with(ENCRYPTED_SUFFIX)
+}
+
+/// The name to store an encrypted `name` under.
+pub fn encrypted_name(name: &str) -> String {
+    format!("{}{}", name, ENCRYPTED_SUFFIX)
+}
+
+/// `name` without [`ENCRYPTED_SUFFIX`], what the file is called once decrypted.
+pub fn plain_name(name: &str) -> &str {
+    match name.strip_suffix(ENCRYPTED_SUFFIX) {
+        Some(plain) if is_encrypted(name) => plain,
+        _ => name,
+    }
+}
+
+/// Whether `bytes`, the start of a stored file, begin with the header of an encrypted one.
+pub fn has_header(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+fn segment_count(size: u64) -> u64 {
+    size.div_ceil(SEGMENT_LEN as u64).max(1)
+}
+
+/// Size of a `size` bytes file once encrypted.
+pub fn encrypted_size(size: u64) -> u64 {
+    HEADER_LEN as u64 + size + segment_count(size) * OVERHEAD as u64
+}
+
+/// The biggest file that still fits into `limit` bytes once encrypted.
+pub fn plaintext_limit(limit: u64) -> u64 {
+    limit.saturating_sub(HEADER_LEN as u64 + (limit / SEGMENT_LEN as u64 + 1) * OVERHEAD as u64)
+}
+
+fn segment_aad(index: u64, last: bool) -> [u8; 9] {
+    let mut aad = [0; 9];
+    aad[..8].copy_from_slice(&index.to_be_bytes());
+    aad[8] = last as u8;
+    aad
+}
+
+/// Encrypts the `size` bytes `reader` yields. Every item is a piece of the encrypted file
+/// along with how many bytes of `reader` went into it.
+pub fn encrypt_stream<R>(reader: R, size: u64, key: &EncryptionKey) -> Result<impl Stream<Item = io::Result<(Vec<u8>, u64)>>, String>
+where
+    R: AsyncRead + Unpin,
+{
+    let file_key: [u8; KEY_LEN] = crypto::random_bytes()?;
+    let mut header = MAGIC.to_vec();
+    header.extend(crypto::seal(&key.0, FILE_KEY_AAD, &file_key)?);
+
+    let segments = segment_count(size);
+    let body = stream::try_unfold((reader, 0), move |(mut reader, index)| async move {
+        if index == segments {
+            return Ok(None);
+        }
+        let last = index + 1 == segments;
+        let length = if last { size - index * SEGMENT_LEN as u64 } else { SEGMENT_LEN as u64 };
+
+        let mut plaintext = vec![0; length as usize];
+        reader.read_exact(&mut plaintext).await?;
+        let sealed = crypto::seal(&file_key, &segment_aad(index, last), &plaintext).map_err(io::Error::other)?;
+
+        Ok(Some(((sealed, length), (reader, index + 1))))
+    });
+
+    Ok(stream::once(future::ok((header, 0))).chain(body))
+}
+
+/// Decrypts a file as it comes in, in pieces of any size.
+pub struct Decryptor {
+    key: EncryptionKey,
+    file_key: Option<[u8; KEY_LEN]>,
+    buffer: Vec<u8>,
+    index: u64,
+}
+
+impl Decryptor {
+    pub fn new(key: EncryptionKey) -> Self {
+        Decryptor {
+            key,
+            file_key: None,
+            buffer: vec![],
+            index: 0,
+        }
+    }
+
+    /// Takes the next bytes of the file and returns the plaintext they complete.
+    pub fn update(&mut self, bytes: &[u8]) -> Result<Vec<u8>, String> {
+        self.buffer.extend_from_slice(bytes);
+
+        if self.file_key.is_none() {
+            if self.buffer.len() < HEADER_LEN {
+                return Ok(vec![]);
+            }
+            if !self.buffer.starts_with(MAGIC) {
+                return Err(String::from("Not an encrypted file"));
+            }
+            let file_key = crypto::open(&self.key.0, FILE_KEY_AAD, &self.buffer[MAGIC.len()..HEADER_LEN])
+                .map_err(|_| String::from("The file was encrypted with a different key"))?;
+            self.file_key = Some(file_key.try_into().map_err(|_| String::from("Damaged encryption header"))?);
+            self.buffer.drain(..HEADER_LEN);
+        }
+
+        // A full segment is only known not to be the last one once more bytes follow it.
+        let mut plaintext = vec![];
+        while self.buffer.len() > SEGMENT_LEN + OVERHEAD {
+            let segment: Vec<u8> = self.buffer.drain(..SEGMENT_LEN + OVERHEAD).collect();
+            plaintext.extend(self.open_segment(&segment, false)?);
+        }
+        Ok(plaintext)
+    }
+
+    /// Checks the file ended where it should and returns the rest of the plaintext.
+    pub fn finish(mut self) -> Result<Vec<u8>, String> {
+        if self.file_key.is_none() {
+            return Err(String::from("The encrypted file is truncated"));
+        }
+        let segment = std::mem::take(&mut self.buffer);
+        self.open_segment(&segment, true)
+    }
+
+    fn open_segment(&mut self, segment: &[u8], last: bool) -> Result<Vec<u8>, String> {
+        let file_key = self.file_key.as_ref().ok_or("Missing encryption header")?;
+        let plaintext = crypto::open(file_key, &segment_aad(self.index, last), segment)
+            .map_err(|_| String::from("The encrypted file is damaged or truncated"))?;
+        self.index += 1;
+        Ok(plaintext)
+    }
+}
+
+/// Keys in `keys.json`, base64 by server URL.
+type KeysFile = BTreeMap<String, String>;
+
+fn load_keys() -> KeysFile {
+    fs::read_to_string(keys_path())
+        .ok()
+        .and_then(|data| serde_json::from_str(&data).ok())
+        .unwrap_or_default()
+}
+
+pub fn load_key(server_url: &str) -> Option<EncryptionKey> {
+    let encoded = load_keys().remove(server_url)?;
+    crypto::decode(&encoded)
+        .and_then(|bytes| EncryptionKey::from_bytes(&bytes))
+        .map_err(|e| eprintln!("Key for {} is damaged: {}", server_url, e))
+        .ok()
+}
+
+pub fn save_key(server_url: &str, key: &EncryptionKey) -> Result<(), String> {
+    let mut keys = load_keys();
+    keys.insert(server_url.to_string(), crypto::encode(&key.0));
+
+    let path = keys_path();
+    if let Some(dir) = path.parent() {
+        let _ = fs::create_dir_all(dir);
+    }
+    let data = serde_json::to_string_pretty(&keys).map_err(|e| e.to_string())?;
+    write_private(&path, data.as_bytes()).map_err(|e| format!("Failed to save key: {}", e))
+}
+
+/// The key of `server_url`, made up on the first encrypted upload.
+pub fn key_for_upload(server_url: &str) -> Result<EncryptionKey, String> {
+    if let Some(key) = load_key(server_url) {
+        return Ok(key);
+    }
+    let key = EncryptionKey::generate()?;
+    save_key(server_url, &key)?;
+    Ok(key)
+}
+
+#[derive(Serialize, Deserialize)]
+struct Export {
+    server_url: String,
+    key: String,
+}
+
+/// Writes the key of `server_url` to `path`, sealed with `passphrase`.
+pub fn export_key(server_url: &str, passphrase: &str, path: &Path) -> Result<(), String> {
+    if passphrase.is_empty() {
+        return Err(String::from("Choose a passphrase to protect the exported key"));
+    }
+    let key = key_for_upload(server_url)?;
+    let export = Export {
+        server_url: server_url.to_string(),
+        key: crypto::encode(&key.0),
+    };
+
+    let plaintext = serde_json::to_vec(&export).map_err(|e| e.to_string())?;
+    let sealed = Sealed::new(passphrase, EXPORT_AAD, &plaintext)?;
+    let data = serde_json::to_string_pretty(&sealed).map_err(|e| e.to_string())?;
+    write_private(&path.to_path_buf(), data.as_bytes()).map_err(|e| format!("{:?}: {}", path, e))
+}
+
+/// How [`import_key`] went.
+#[derive(Debug, Clone)]
+pub enum Import {
+    Imported(EncryptionKey),
+    /// Nothing was saved, the user has to agree to this first.
+    Confirm(String),
+}
+
+/// Reads a key written by [`export_key`] and makes it the key of `server_url`. Unless
+/// `confirmed`, a key exported for another server or one that replaces a different key is
+/// only described. A replaced key is kept in `keys.bak.json`.
+pub fn import_key(server_url: &str, passphrase: &str, path: &Path, confirmed: bool) -> Result<Import, String> {
+    let data = fs::read_to_string(path).map_err(|e| format!("{:?}: {}", path, e))?;
+    let sealed: Sealed = serde_json::from_str(&data).map_err(|_| String::from("Not an exported key"))?;
+    if sealed.version != 1 {
+        return Err(format!("Unsupported key export version {}", sealed.version));
+    }
+
+    let plaintext = sealed.open(passphrase, EXPORT_AAD).map_err(|_| String::from("Wrong passphrase"))?;
+    let export: Export = serde_json::from_slice(&plaintext).map_err(|_| String::from("Not an exported key"))?;
+    let key = EncryptionKey::from_bytes(&crypto::decode(&export.key)?)?;
+    let previous = load_key(server_url).filter(|previous| *previous != key);
+
+    if !confirmed {
+        let mut warnings = vec![];
+        if export.server_url != server_url {
+            warnings.push(format!("The key was exported for {}, not {}.", export.server_url, server_url));
+        }
+        if previous.is_some() {
+            warnings.push(String::from("It replaces this server's key, files encrypted with that one won't open until it is restored from keys.bak.json."));
+        }
+        if !warnings.is_empty() {
+            return Ok(Import::Confirm(warnings.join(" ")));
+        }
+    }
+
+    if let Some(previous) = previous {
+        keep_replaced_key(server_url, &previous)?;
+    }
+    save_key(server_url, &key)?;
+    Ok(Import::Imported(key))
+}
+
+/// Replaced keys by server URL, oldest first.
+type ReplacedKeys = BTreeMap<String, Vec<String>>;
+
+fn keep_replaced_key(server_url: &str, key: &EncryptionKey) -> Result<(), String> {
+    let path = data_dir().join(REPLACED_KEYS_FILE);
+    let mut replaced: ReplacedKeys = fs::read_to_string(&path)
+        .ok()
+        .and_then(|data| serde_json::from_str(&data).ok())
+        .unwrap_or_default();
+    replaced.entry(server_url.to_string()).or_default().push(crypto::encode(&key.0));
+
+    let data = serde_json::to_string_pretty(&replaced).map_err(|e| e.to_string())?;
+    write_private(&path, data.as_bytes()).map_err(|e| format!("Failed to keep the replaced key: {}", e))
+}
+
+fn keys_path() -> PathBuf {
+    data_dir().join(KEYS_FILE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+    use futures::TryStreamExt;
+
+    fn encrypt(plaintext: &[u8], key: &EncryptionKey) -> Vec<u8> {
+        let stream = encrypt_stream(plaintext, plaintext.len() as u64, key).unwrap();
+        let pieces: Vec<(Vec<u8>, u64)> = block_on(stream.try_collect()).unwrap();
+        assert_eq!(pieces.iter().map(|(_, read)| read).sum::<u64>(), plaintext.len() as u64);
+        pieces.into_iter().flat_map(|(sealed, _)| sealed).collect()
+    }
+
+    /// Feeds `encrypted` in pieces of `piece` bytes, as a download would.
+    fn decrypt(encrypted: &[u8], key: &EncryptionKey, piece: usize) -> Result<Vec<u8>, String> {
+        let mut decryptor = Decryptor::new(*key);
+        let mut plaintext = vec![];
+        for bytes in encrypted.chunks(piece) {
+            plaintext.extend(decryptor.update(bytes)?);
+        }
+        plaintext.extend(decryptor.finish()?);
+        Ok(plaintext)
+    }
+
+    fn plaintext(size: usize) -> Vec<u8> {
+        (0..size).map(|i| (i * 7 % 251) as u8).collect()
+    }
+
+    #[test]
+    fn round_trip() {
+        let key = EncryptionKey::generate().unwrap();
+        for size in [0, 1, SEGMENT_LEN - 1, SEGMENT_LEN, SEGMENT_LEN + 1, 3 * SEGMENT_LEN + 5] {
+            let plaintext = plaintext(size);
+            let encrypted = encrypt(&plaintext, &key);
+            assert!(has_header(&encrypted));
+            assert_eq!(encrypted.len() as u64, encrypted_size(size as u64), "{}", size);
+            for piece in [1000, SEGMENT_LEN + OVERHEAD, encrypted.len().max(1)] {
+                assert_eq!(decrypt(&encrypted, &key, piece).unwrap(), plaintext, "{} in {}", size, piece);
+            }
+        }
+    }
+
+    #[test]
+    fn truncated_files_fail() {
+        let key = EncryptionKey::generate().unwrap();
+        let encrypted = encrypt(&plaintext(2 * SEGMENT_LEN + 10), &key);
+
+        // Cut inside the header, inside the last segment and right after a whole segment.
+        for length in [HEADER_LEN - 1, encrypted.len() - 1, HEADER_LEN + SEGMENT_LEN + OVERHEAD, HEADER_LEN + 2 * (SEGMENT_LEN + OVERHEAD)] {
+            assert!(decrypt(&encrypted[..length], &key, 4096).is_err(), "{}", length);
+        }
+        // An empty file loses its only segment.
+        let empty = encrypt(&[], &key);
+        assert!(decrypt(&empty[..HEADER_LEN], &key, 4096).is_err());
+    }
+
+    #[test]
+    fn damaged_files_fail() {
+        let key = EncryptionKey::generate().unwrap();
+        let mut encrypted = encrypt(&plaintext(SEGMENT_LEN + 10), &key);
+        let last = encrypted.len() - 1;
+        encrypted[last] ^= 1;
+        assert!(decrypt(&encrypted, &key, 4096).is_err());
+
+        assert_eq!(decrypt(&plaintext(HEADER_LEN + 10), &key, 4096), Err(String::from("Not an encrypted file")));
+    }
+
+    #[test]
+    fn wrong_key_fails() {
+        let key = EncryptionKey::generate().unwrap();
+        let other = EncryptionKey::generate().unwrap();
+        let encrypted = encrypt(&plaintext(100), &key);
+        assert_eq!(
+            decrypt(&encrypted, &other, 4096),
+            Err(String::from("The file was encrypted with a different key"))
+        );
+    }
+
+    #[test]
+    fn limits_and_names() {
+        for limit in [0, 100, HEADER_LEN as u64 + 100, 10 * 1024 * 1024, 1 << 40] {
+            let size = plaintext_limit(limit);
+            assert!(size == 0 || encrypted_size(size) <= limit, "{}", limit);
+        }
+        assert!(is_encrypted("a.txt.fta"));
+        assert!(is_encrypted("a.txt.fta.chunk001of002"));
+        assert!(!is_encrypted(".fta"));
+        assert!(!is_encrypted("a.txt"));
+        assert_eq!(plain_name("a.txt.fta"), "a.txt");
+        assert_eq!(plain_name(".fta"), ".fta");
+    }
+}
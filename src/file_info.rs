@@ -0,0 +1,222 @@
+use std::path::Path;
+use chrono::{DateTime, Local, NaiveDateTime};
+use serde::{Deserialize, Deserializer};
+
+use crate::encryption::plain_name;
+
+/// What the server tells about a stored file. Older servers only send the name.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct FileInfo {
+    #[serde(alias = "filename")]
+    pub name: String,
+    #[serde(default)]
+    pub size: Option<u64>,
+    /// Unix timestamps in seconds.
+    #[serde(default, alias = "created_at", alias = "uploaded_at", deserialize_with = "timestamp")]
+    pub created: Option<i64>,
+    #[serde(default, alias = "modified_at", alias = "updated_at", deserialize_with = "timestamp")]
+    pub modified: Option<i64>,
+    #[serde(default, alias = "mime_type", alias = "mime")]
+    pub content_type: Option<String>,
+    #[serde(default)]
+    pub owner: Option<String>,
+    #[serde(default)]
+    pub checksum: Option<String>,
+    /// Set for folders on servers that list them, a trailing `/` in the name means the same.
+    #[serde(default, alias = "is_directory")]
+    pub is_dir: bool,
+}
+
+impl FileInfo {
+    pub fn new(name: String) -> Self {
+        FileInfo {
+            name,
+            ..FileInfo::default()
+        }
+    }
+
+    /// The server supplied type, or a guess from the extension, the one before
+    /// the suffix for encrypted files.
+    pub fn mime(&self) -> String {
+        match &self.content_type {
+            Some(content_type) => content_type.clone(),
+            None => mime_guess::from_path(plain_name(&self.name))
+                .first_raw()
+                .unwrap_or("application/octet-stream")
+                .to_string(),
+        }
+    }
+
+    pub fn kind(&self) -> FileKind {
+        const ARCHIVES: &[&str] = &["zip", "gz", "tgz", "tar", "7z", "rar", "bz2", "xz", "zst"];
+        const DOCUMENTS: &[&str] = &["doc", "docx", "odt", "rtf", "xls", "xlsx", "ods", "csv", "ppt", "pptx", "odp", "txt", "md"];
+
+        let mime = self.mime();
+        let extension = self.extension().to_lowercase();
+        match mime.split('/').next().unwrap_or_default() {
+            "image" => FileKind::Image,
+            "video" => FileKind::Video,
+            "audio" => FileKind::Audio,
+            _ if mime == "application/pdf" => FileKind::Pdf,
+            _ if ARCHIVES.contains(&extension.as_str()) => FileKind::Archive,
+            _ if DOCUMENTS.contains(&extension.as_str()) => FileKind::Document,
+            "text" => FileKind::Code,
+            _ if mime.ends_with("json") || mime.ends_with("xml") || mime.contains("script") => FileKind::Code,
+            _ => FileKind::Other,
+        }
+    }
+
+    /// The extension of the name without the encryption suffix, `""` if there is none.
+    pub fn extension(&self) -> String {
+        Path::new(plain_name(&self.name))
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_string())
+            .unwrap_or_default()
+    }
+}
+
+/// What a file roughly is, to pick its icon.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileKind {
+    Image,
+    Video,
+    Audio,
+    Pdf,
+    Document,
+    Code,
+    Archive,
+    Other,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ListingEntry {
+    Name(String),
+    Info(FileInfo),
+}
+
+/// Parses `GET /files/`, accepting both `["a.txt", ...]` and `[{"name": "a.txt", "size": 3, ...}, ...]`.
+pub fn parse_listing(data: &str) -> Result<Vec<FileInfo>, serde_json::Error> {
+    let entries: Vec<ListingEntry> = serde_json::from_str(data)?;
+
+    Ok(entries
+        .into_iter()
+        .map(|entry| match entry {
+            ListingEntry::Name(name) => FileInfo::new(name),
+            ListingEntry::Info(info) => info,
+        })
+        .collect())
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawTimestamp {
+    Seconds(i64),
+    Text(String),
+}
+
+/// Accepts unix seconds, RFC 3339 and `YYYY-MM-DD HH:MM:SS` (taken as UTC).
+fn timestamp<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<i64>, D::Error> {
+    let raw: Option<RawTimestamp> = Option::deserialize(deserializer)?;
+
+    Ok(match raw {
+        Some(RawTimestamp::Seconds(seconds)) => Some(seconds),
+        Some(RawTimestamp::Text(text)) => DateTime::parse_from_rfc3339(&text)
+            .map(|date| date.timestamp())
+            .or_else(|_| {
+                NaiveDateTime::parse_from_str(&text, "%Y-%m-%d %H:%M:%S")
+                    .map(|date| date.and_utc().timestamp())
+            })
+            .ok(),
+        None => None,
+    })
+}
+
+pub fn format_timestamp(timestamp: Option<i64>) -> String {
+    timestamp
+        .and_then(|seconds| DateTime::from_timestamp(seconds, 0))
+        .map(|date| date.with_timezone(&Local).format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_else(|| String::from("-"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_plain_names() {
+        let files = parse_listing(r#"["a.txt", "docs/b.pdf"]"#).unwrap();
+        assert_eq!(files, vec![FileInfo::new(String::from("a.txt")), FileInfo::new(String::from("docs/b.pdf"))]);
+        assert!(parse_listing("[]").unwrap().is_empty());
+    }
+
+    #[test]
+    fn parses_structured_entries_and_their_aliases() {
+        let files = parse_listing(
+            r#"[
+                {"name": "a.txt", "size": 3, "created": 100, "modified": 200, "content_type": "text/plain",
+                 "owner": "ann", "checksum": "sha256:ab", "is_dir": false},
+                {"filename": "photos/", "created_at": 1, "updated_at": 2, "mime_type": "inode/directory", "is_directory": true},
+                {"name": "b", "uploaded_at": 5, "modified_at": 6, "mime": "image/png", "unknown": [1, 2]},
+                "c.txt"
+            ]"#,
+        )
+        .unwrap();
+
+        assert_eq!(files[0], FileInfo {
+            name: String::from("a.txt"),
+            size: Some(3),
+            created: Some(100),
+            modified: Some(200),
+            content_type: Some(String::from("text/plain")),
+            owner: Some(String::from("ann")),
+            checksum: Some(String::from("sha256:ab")),
+            is_dir: false,
+        });
+        assert_eq!((files[1].name.as_str(), files[1].created, files[1].modified, files[1].is_dir), ("photos/", Some(1), Some(2), true));
+        assert_eq!(files[1].content_type.as_deref(), Some("inode/directory"));
+        assert_eq!((files[2].created, files[2].modified, files[2].size), (Some(5), Some(6), None));
+        assert_eq!(files[2].mime(), "image/png");
+        assert_eq!(files[3], FileInfo::new(String::from("c.txt")));
+    }
+
+    #[test]
+    fn rejects_listings_it_can_not_read() {
+        for data in [
+            "",
+            "{}",
+            r#"[{"size": 3}]"#,
+            r#"[{"name": "a", "size": "three"}]"#,
+            r#"[{"name": "a", "size": -1}]"#,
+            r#"[{"name": "a", "modified": 1.5}]"#,
+            "[1]",
+        ] {
+            assert!(parse_listing(data).is_err(), "{}", data);
+        }
+    }
+
+    #[test]
+    fn reads_each_timestamp_format() {
+        let modified = |value: &str| parse_listing(&format!(r#"[{{"name": "a", "modified": {}}}]"#, value)).unwrap()[0].modified;
+
+        assert_eq!(modified("1700000000"), Some(1700000000));
+        assert_eq!(modified("-1"), Some(-1));
+        assert_eq!(modified(r#""2023-11-14T22:13:20Z""#), Some(1700000000));
+        assert_eq!(modified(r#""2023-11-14T23:13:20+01:00""#), Some(1700000000));
+        assert_eq!(modified(r#""2023-11-14T22:13:20.250Z""#), Some(1700000000));
+        assert_eq!(modified(r#""2023-11-14 22:13:20""#), Some(1700000000));
+        assert_eq!(modified("null"), None);
+        // Text that is no date is dropped rather than failing the whole listing.
+        assert_eq!(modified(r#""yesterday""#), None);
+        assert_eq!(modified(r#""1700000000""#), None);
+        assert_eq!(modified(r#""2023-11-14""#), None);
+    }
+
+    #[test]
+    fn formats_timestamps() {
+        assert_eq!(format_timestamp(None), "-");
+        assert_eq!(format_timestamp(Some(i64::MAX)), "-");
+        let expected = DateTime::from_timestamp(1700000000, 0).unwrap().with_timezone(&Local).format("%Y-%m-%d %H:%M").to_string();
+        assert_eq!(format_timestamp(Some(1700000000)), expected);
+    }
+}
@@ -0,0 +1,114 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::folders::SEPARATOR;
+use crate::ignore::{IgnoreRules, IGNORE_FILE};
+
+#[derive(Debug, Clone)]
+pub struct ScannedFile {
+    pub path: PathBuf,
+    /// Path below the picked folder, including the folder itself: `photos/2024/a.jpg`.
+    pub relative: String,
+    pub size: u64,
+}
+
+/// What "Upload folder" found, shown to the user before anything is queued.
+#[derive(Debug, Clone)]
+pub struct FolderUpload {
+    pub root: PathBuf,
+    /// Server folder the tree goes into.
+    pub target_dir: String,
+    pub files: Vec<ScannedFile>,
+    /// Files over the server limit, they are not uploaded with the rest.
+    pub oversized: Vec<ScannedFile>,
+    pub ignored: usize,
+}
+
+impl FolderUpload {
+    pub fn total_size(&self) -> u64 {
+        self.files.iter().map(|file| file.size).sum()
+    }
+}
+
+/// Walks `root`, honouring `.ftaignore` files. Symlinks are not followed.
+pub fn scan(root: PathBuf, target_dir: String, size_limit: u64) -> Result<FolderUpload, String> {
+    let name = root
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .ok_or_else(|| format!("{:?} is not a folder", root))?;
+
+    let mut upload = FolderUpload {
+        root: root.clone(),
+        target_dir,
+        files: vec![],
+        oversized: vec![],
+        ignored: 0,
+    };
+    walk(&root, &format!("{}{}", name, SEPARATOR), "", IgnoreRules::default(), size_limit, &mut upload)?;
+
+    upload.files.sort_by(|a, b| a.relative.cmp(&b.relative));
+    Ok(upload)
+}
+
+/// `prefix` is the server side path of `dir`, `base` the same path relative to `root` for the ignore rules.
+fn walk(dir: &Path, prefix: &str, base: &str, mut rules: IgnoreRules, size_limit: u64, upload: &mut FolderUpload) -> Result<(), String> {
+    rules.load(dir, base)?;
+
+    let mut entries: Vec<_> = fs::read_dir(dir)
+        .map_err(|e| format!("{:?}: {}", dir, e))?
+        .filter_map(|entry| entry.ok())
+        .collect();
+    entries.sort_by_key(|entry| entry.file_name());
+
+    for entry in entries {
+        let name = entry.file_name().to_string_lossy().to_string();
+        let file_type = match entry.file_type() {
+            Ok(file_type) => file_type,
+            Err(e) => {
+                eprintln!("{:?}: {}", entry.path(), e);
+                continue;
+            }
+        };
+        if name == IGNORE_FILE || file_type.is_symlink() {
+            continue;
+        }
+
+        let relative = format!("{}{}", base, name);
+        if rules.is_ignored(&relative, file_type.is_dir()) {
+            upload.ignored += 1;
+            continue;
+        }
+
+        if file_type.is_dir() {
+            walk(
+                &entry.path(),
+                &format!("{}{}{}", prefix, name, SEPARATOR),
+                &format!("{}{}", relative, SEPARATOR),
+                rules.clone(),
+                size_limit,
+                upload,
+            )?;
+            continue;
+        }
+
+        let size = match entry.metadata() {
+            Ok(metadata) => metadata.len(),
+            Err(e) => {
+                eprintln!("{:?}: {}", entry.path(), e);
+                continue;
+            }
+        };
+        let file = ScannedFile {
+            path: entry.path(),
+            relative: format!("{}{}", prefix, name),
+            size,
+        };
+        if size > size_limit {
+            upload.oversized.push(file);
+        } else {
+            upload.files.push(file);
+        }
+    }
+
+    Ok(())
+}
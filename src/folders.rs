@@ -0,0 +1,86 @@
+use std::collections::BTreeSet;
+
+/// The server keeps a flat list of names, folders are just `/` separated prefixes.
+/// Folder paths always end with the separator, the root is the empty string.
+pub const SEPARATOR: char = '/';
+
+pub fn base_name(path: &str) -> &str {
+    let path = path.strip_suffix(SEPARATOR).unwrap_or(path);
+    path.rsplit(SEPARATOR).next().unwrap_or(path)
+}
+
+/// The folder containing `path`: `"a/b/c.txt"` -> `"a/b/"`, `"a/b/"` -> `"a/"`.
+pub fn parent(path: &str) -> &str {
+    let path = path.strip_suffix(SEPARATOR).unwrap_or(path);
+    match path.rfind(SEPARATOR) {
+        Some(index) => &path[..=index],
+        None => "",
+    }
+}
+
+pub fn join(dir: &str, name: &str) -> String {
+    format!("{}{}", dir, name.trim_matches(SEPARATOR))
+}
+
+pub fn folder_path(dir: &str, name: &str) -> String {
+    format!("{}{}", join(dir, name), SEPARATOR)
+}
+
+/// Whether `path` lies in `dir` itself, not in one of its subfolders.
+pub fn is_direct_child(path: &str, dir: &str) -> bool {
+    match path.strip_prefix(dir) {
+        Some(rest) => !rest.is_empty() && !rest.trim_end_matches(SEPARATOR).contains(SEPARATOR),
+        None => false,
+    }
+}
+
+/// `path` moved from under `from` to under `to`, if it was under `from` at all.
+pub fn rebase(path: &str, from: &str, to: &str) -> Option<String> {
+    path.strip_prefix(from).map(|rest| format!("{}{}", to, rest))
+}
+
+/// Checks a folder name typed by the user.
+pub fn validate_name(name: &str) -> Result<&str, String> {
+    let name = name.trim();
+
+    if name.is_empty() {
+        Err(String::from("Folder name is empty"))
+    } else if name.contains(SEPARATOR) || name.contains('\\') {
+        Err(format!("Folder name can't contain '{}'", SEPARATOR))
+    } else if name == "." || name == ".." {
+        Err(format!("'{}' is not a valid folder name", name))
+    } else {
+        Ok(name)
+    }
+}
+
+/// Folders directly inside `dir`, both the ones implied by file names and the known empty ones.
+pub fn subfolders<'a>(names: impl Iterator<Item = &'a str>, folders: &'a BTreeSet<String>, dir: &str) -> Vec<String> {
+    let mut found = BTreeSet::new();
+
+    for path in names.chain(folders.iter().map(String::as_str)) {
+        let Some(rest) = path.strip_prefix(dir) else {
+            continue;
+        };
+        if let Some(index) = rest.find(SEPARATOR) {
+            if index > 0 {
+                found.insert(format!("{}{}", dir, &rest[..=index]));
+            }
+        }
+    }
+
+    found.into_iter().collect()
+}
+
+/// `(label, path)` for every level from the root down to `dir`.
+pub fn breadcrumbs(dir: &str) -> Vec<(String, String)> {
+    let mut crumbs = vec![(String::from("Home"), String::new())];
+    let mut path = String::new();
+
+    for part in dir.split(SEPARATOR).filter(|part| !part.is_empty()) {
+        path = folder_path(&path, part);
+        crumbs.push((part.to_string(), path.clone()));
+    }
+
+    crumbs
+}
@@ -1,350 +1,2051 @@
-use std::collections::HashMap;
-use reqwest::blocking::multipart;
-use std::fs::{metadata, File};
-use std::io::{copy, Read};
-use std::path::Path;
+use std::collections::{BTreeMap, HashSet};
+use std::iter;
+use std::mem;
+use std::fs::{create_dir_all, metadata, remove_dir_all, remove_file};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use iced::{window, Command, Size};
 use native_dialog::FileDialog;
-use reqwest::blocking::multipart::Part;
 use chrono::Utc;
 
-use crate::app::{App, LoginField, Message, Page};
-use crate::ui::PackageRow;
+use crate::app::{App, DraggedFile, FolderRename, LoginField, Message, Page, PreviewPane, ProfileEditor, ProfileField, RejectedFile, Relay, SyncJob, Thumbnail, Toast, TOAST_DURATION};
+use crate::api::{create_folder_request, delete_file_request, delete_folder_request, delete_selected_request, files_request, limits_request, log_in_request, move_files_request, refresh_request, Tokens};
+use crate::file_info::FileInfo;
+use crate::columns::{ColumnLayout, Resize};
+use crate::settings::{FileView, ThemeChoice, WindowGeometry};
+use crate::query::{save_saved_searches, Query};
+use crate::folder_upload::scan;
+use crate::collision::{resolve, Collision};
+use crate::utils::{data_dir, format_size};
+use crate::zip::{entry_name, write_zip, Bundle, ZipEntry};
+use crate::credentials::{self, SavedLogin};
+use crate::profiles::{save_profiles, Profile};
+use crate::encryption::{encrypted_name, encrypted_size, export_key, import_key, key_for_upload, load_key, plain_name, plaintext_limit, EncryptionKey, Import};
+use crate::remote::Remote;
+use crate::sync::{apply, load_folders, plan, save_folders, Action, SyncFolder, SyncItem};
+use crate::permissions::{denied, Permission, PERMISSION_DENIED};
+use crate::folders::{base_name, folder_path, join, parent, rebase, validate_name, SEPARATOR};
+use crate::checksum::CHECKSUM_MISMATCH;
+use crate::chunks::{assemble, chunk_name, chunk_ranges, group_chunks, parse_chunk_name, Assembly, ListedFile};
+use crate::preview;
+use crate::cache::cache_key;
+use crate::thumbnail::{self, has_thumbnail};
+use crate::journal::{fingerprint, JournalEntry};
+use crate::transfer::{part_path, Kind, Progress};
+use crate::ui::{visible_files, PackageRow};
 
-pub fn handle_update(app: &mut App, message: Message) {
+const DOUBLE_CLICK: Duration = Duration::from_millis(400);
+/// Seconds before `token_exp` at which the session is renewed.
+const REFRESH_BEFORE_EXPIRY: i64 = 60;
+
+pub fn handle_update(app: &mut App, message: Message) -> Command<Message> {
     match message {
         Message::ToggleTheme => {
-            app.theme = if app.theme == iced::Theme::Light {
-                iced::Theme::Dark
+            app.settings.theme = if app.settings.theme == ThemeChoice::Light {
+                ThemeChoice::Dark
             } else {
-                iced::Theme::Light
+                ThemeChoice::Light
+            };
+            app.settings.save();
+            Command::none()
+        }
+        Message::OpenSettings => {
+            app.session.page = Page::Settings;
+            Command::none()
+        }
+        Message::CloseSettings => {
+            show_page(app);
+            Command::none()
+        }
+        Message::ThemeChanged(theme) => {
+            app.settings.theme = theme;
+            app.settings.save();
+            Command::none()
+        }
+        Message::ChooseDownloadDir => {
+            if let Some(dir) = pick_dir() {
+                app.settings.download_dir = Some(dir);
+                app.settings.save();
+            }
+            Command::none()
+        }
+        Message::ClearDownloadDir => {
+            app.settings.download_dir = None;
+            app.settings.always_ask = true;
+            app.settings.save();
+            Command::none()
+        }
+        Message::AlwaysAskToggled(always_ask) => {
+            app.settings.always_ask = always_ask;
+            app.settings.save();
+            Command::none()
+        }
+        Message::LastProfileChanged(name) => {
+            app.settings.last_profile = Some(name);
+            app.settings.save();
+            Command::none()
+        }
+        Message::ResetWindow => {
+            app.settings.window = WindowGeometry::default();
+            app.settings.save();
+            window::resize(window::Id::MAIN, Size::new(app.settings.window.width, app.settings.window.height))
+        }
+        Message::ResetColumns => {
+            app.settings.columns = ColumnLayout::default();
+            app.settings.save();
+            Command::none()
+        }
+        // Geometry is only written to disk when the window closes.
+        Message::WindowResized(width, height) => {
+            app.settings.window.width = width as f32;
+            app.settings.window.height = height as f32;
+            Command::none()
+        }
+        Message::WindowMoved(x, y) => {
+            app.settings.window.x = Some(x);
+            app.settings.window.y = Some(y);
+            Command::none()
+        }
+        Message::WindowCloseRequested(id) => {
+            app.settings.save();
+            window::close(id)
+        }
+        Message::ProfileSelected(name) => {
+            if let Some(index) = app.profiles.iter().position(|profile| profile.name == name) {
+                select_profile(app, index);
+            }
+            Command::none()
+        }
+        Message::NewProfile => {
+            app.profile_editor = Some(ProfileEditor::new(None, &Profile::default()));
+            Command::none()
+        }
+        Message::EditProfile => {
+            if let Some(index) = app.session.profile {
+                app.profile_editor = Some(ProfileEditor::new(Some(index), &app.profiles[index]));
+            }
+            Command::none()
+        }
+        Message::RemoveProfile => {
+            if let Some(index) = app.session.profile.take() {
+                app.profiles.remove(index);
+                save_profiles(&app.profiles);
+                for session in app.background.iter_mut() {
+                    session.profile = match session.profile {
+                        Some(other) if other == index => None,
+                        Some(other) if other > index => Some(other - 1),
+                        other => other,
+                    };
+                }
+                app.session.server.url.clear();
+                if app.profiles.is_empty() {
+                    app.profile_editor = Some(ProfileEditor::new(None, &Profile::default()));
+                } else {
+                    select_profile(app, 0);
+                }
+            }
+            Command::none()
+        }
+        Message::ProfileFieldChanged(field, value) => {
+            if let Some(editor) = &mut app.profile_editor {
+                match field {
+                    ProfileField::Name => editor.name = value,
+                    ProfileField::Url => editor.url = value,
+                    ProfileField::Username => editor.username = value,
+                    ProfileField::CaCertificate => editor.ca_certificate = value,
+                    ProfileField::Proxy => editor.proxy = value,
+                    ProfileField::KeyPassphrase => editor.key_passphrase = value,
+                }
+                editor.error = None;
+            }
+            Command::none()
+        }
+        Message::InvalidCertsToggled(accept) => {
+            if let Some(editor) = &mut app.profile_editor {
+                editor.accept_invalid_certs = accept;
+            }
+            Command::none()
+        }
+        Message::EncryptToggled(encrypt) => {
+            if let Some(editor) = &mut app.profile_editor {
+                editor.encrypt = encrypt;
+            }
+            Command::none()
+        }
+        Message::ExportKey => {
+            let Some(editor) = &mut app.profile_editor else {
+                return Command::none();
+            };
+            let url = editor.profile().url;
+            if url.is_empty() {
+                editor.key_status = Some(Err(String::from("Enter the server URL first")));
+                return Command::none();
+            }
+            let Some(path) = pick_key_file(true) else {
+                return Command::none();
+            };
+            editor.key_import = None;
+            let passphrase = editor.key_passphrase.clone();
+
+            Command::perform(
+                async move {
+                    tokio::task::spawn_blocking(move || export_key(&url, &passphrase, &path).map(|()| path))
+                        .await
+                        .unwrap_or_else(|e| Err(e.to_string()))
+                },
+                Message::KeyExported,
+            )
+        }
+        Message::KeyExported(result) => {
+            if let Some(editor) = &mut app.profile_editor {
+                // Exporting makes up the key if there was none yet.
+                let url = editor.profile().url;
+                editor.key_status = Some(result.map(|path| format!("Key saved to {}", path.display())));
+                set_key(app, &url, load_key(&url));
+            }
+            Command::none()
+        }
+        Message::ImportKey => {
+            let Some(editor) = &mut app.profile_editor else {
+                return Command::none();
+            };
+            let url = editor.profile().url;
+            if url.is_empty() {
+                editor.key_status = Some(Err(String::from("Enter the server URL first")));
+                return Command::none();
+            }
+            let Some(path) = pick_key_file(false) else {
+                return Command::none();
+            };
+            editor.key_import = None;
+            import_key_file(url, editor.key_passphrase.clone(), path, false)
+        }
+        Message::ConfirmKeyImport => {
+            let Some(editor) = &mut app.profile_editor else {
+                return Command::none();
+            };
+            let Some(path) = editor.key_import.take() else {
+                return Command::none();
             };
+            import_key_file(editor.profile().url, editor.key_passphrase.clone(), path, true)
+        }
+        Message::CancelKeyImport => {
+            if let Some(editor) = &mut app.profile_editor {
+                editor.key_import = None;
+                editor.key_status = None;
+            }
+            Command::none()
+        }
+        Message::KeyImported(url, path, result) => {
+            let (status, pending) = match result {
+                Ok(Import::Imported(key)) => {
+                    set_key(app, &url, Some(key));
+                    (Ok(String::from("Key imported, files encrypted with it can be opened now")), None)
+                }
+                Ok(Import::Confirm(warning)) => (Err(warning), Some(path)),
+                Err(e) => (Err(e), None),
+            };
+            if let Some(editor) = &mut app.profile_editor {
+                editor.key_status = Some(status);
+                editor.key_import = pending;
+            }
+            Command::none()
+        }
+        Message::SaveProfile => {
+            let Some(editor) = &mut app.profile_editor else {
+                return Command::none();
+            };
+            let profile = editor.profile();
+            let others: Vec<Profile> = app.profiles
+                .iter()
+                .enumerate()
+                .filter(|(index, _)| Some(*index) != editor.index)
+                .map(|(_, profile)| profile.clone())
+                .collect();
+            if let Err(e) = profile.validate(&others) {
+                editor.error = Some(e);
+                return Command::none();
+            }
+
+            let index = match editor.index {
+                Some(index) => {
+                    app.profiles[index] = profile;
+                    index
+                }
+                None => {
+                    app.profiles.push(profile);
+                    app.profiles.len() - 1
+                }
+            };
+            app.profile_editor = None;
+            save_profiles(&app.profiles);
+            for session in app.background.iter_mut().filter(|session| session.profile == Some(index)) {
+                session.server.encrypt = app.profiles[index].encrypt;
+            }
+            select_profile(app, index);
+            Command::none()
+        }
+        Message::CancelProfileEdit => {
+            // With no profile at all the editor is the only way forward.
+            if !app.profiles.is_empty() {
+                app.profile_editor = None;
+            }
+            Command::none()
         }
         Message::LoginSubmit => {
-            if log_in_request(app) {
-                files_request(app);
+            if app.session.profile.is_none() {
+                app.session.login_error = Some(String::from("Choose a server to log in to"));
+                return Command::none();
+            }
+            Command::perform(
+                log_in_request(
+                    app.session.client.clone(),
+                    app.session.server.url.clone(),
+                    app.session.login_field.login.clone(),
+                    app.session.login_field.password.clone(),
+                    app.verifier.clone(),
+                ),
+                Message::LoggedIn,
+            )
+        }
+        Message::LoggedIn(result) => {
+            match result {
+                Ok(tokens) => {
+                    let save = remember_login(app, &tokens);
+                    app.session.token = tokens.token;
+                    app.session.token_exp = tokens.token_exp;
+                    app.session.refresh_token = tokens.refresh_token;
+                    app.session.claims = tokens.claims;
+                    app.session.page = Page::Main;
+                    let profile = app.session.profile.map(|index| app.profiles[index].name.clone());
+                    if profile.is_some() && profile != app.settings.last_profile {
+                        app.settings.last_profile = profile;
+                        app.settings.save();
+                    }
+                    app.session.login_error = Some(String::from("JWT expired, log in again"));
+                    if app.session.sync.is_empty() {
+                        app.session.sync = load_folders(&app.session.server.url)
+                            .into_iter()
+                            .enumerate()
+                            .map(|(id, folder)| SyncJob::new(id, folder))
+                            .collect();
+                    }
+                    let ids: Vec<usize> = app.session.sync.iter().map(|job| job.id).collect();
+                    let sync = Command::batch(ids.into_iter().map(|id| start_sync(app, id)));
+                    Command::batch([files_command(app), limits_command(app), save, sync])
+                }
+                Err(e) => {
+                    app.session.login_error = Some(e);
+                    Command::none()
+                }
             }
+        }
+        Message::LimitsLoaded(result) => {
+            match result {
+                Ok(size_limit) => app.session.size_limit = size_limit,
+                Err(e) => notify(app, format!("Using the configured upload limit of {}, the server's is unknown: {}", format_size(app.session.size_limit as f64), e)),
+            }
+            Command::none()
+        }
+        Message::RememberToggled(remember) => {
+            app.session.login_field.remember = remember;
+            Command::none()
+        }
+        Message::PassphraseChanged(passphrase) => {
+            app.session.login_field.passphrase = passphrase;
+            Command::none()
+        }
+        Message::UnlockSaved => {
+            let passphrase = app.session.login_field.passphrase.clone();
+            Command::perform(
+                async move {
+                    tokio::task::spawn_blocking(move || credentials::load(&passphrase))
+                        .await
+                        .unwrap_or_else(|e| Err(e.to_string()))
+                },
+                Message::Unlocked,
+            )
+        }
+        Message::Unlocked(result) => {
+            let saved = match result {
+                Ok(saved) if saved.server_url != app.session.server.url => {
+                    match app.profiles.iter().position(|profile| profile.url == saved.server_url) {
+                        Some(index) => select_profile(app, index),
+                        None => {
+                            app.session.login_error = Some(format!("The saved login is for {}, which has no profile", saved.server_url));
+                            return Command::none();
+                        }
+                    }
+                    saved
+                }
+                Ok(saved) => saved,
+                Err(e) => {
+                    app.session.login_error = Some(e);
+                    return Command::none();
+                }
+            };
+            app.session.login_field.login = saved.login;
+            app.session.login_field.remember = true;
 
+            match (saved.refresh_token, saved.password) {
+                (Some(refresh_token), _) => Command::perform(
+                    refresh_request(app.session.client.clone(), app.session.server.url.clone(), refresh_token, app.verifier.clone()),
+                    Message::LoggedIn,
+                ),
+                (None, Some(password)) => {
+                    app.session.login_field.password = password;
+                    Command::perform(
+                        log_in_request(
+                            app.session.client.clone(),
+                            app.session.server.url.clone(),
+                            app.session.login_field.login.clone(),
+                            app.session.login_field.password.clone(),
+                            app.verifier.clone(),
+                        ),
+                        Message::LoggedIn,
+                    )
+                }
+                (None, None) => {
+                    app.session.login_error = Some(String::from("The saved login has expired, log in again"));
+                    Command::none()
+                }
+            }
+        }
+        Message::LoginSaved(result) => {
+            match result {
+                Ok(()) => app.saved_login = true,
+                Err(e) => notify(app, format!("Login not remembered: {}", e)),
+            }
+            Command::none()
+        }
+        Message::ForgetSaved => {
+            credentials::forget();
+            app.saved_login = false;
+            app.session.login_field.remember = false;
+            app.session.login_field.passphrase.clear();
+            Command::none()
+        }
+        Message::RefreshTick => {
+            let expires_in = app.session.token_exp - Utc::now().timestamp();
+            if app.session.token.is_empty() || app.session.refreshing || expires_in > REFRESH_BEFORE_EXPIRY {
+                return Command::none();
+            }
+            let Some(refresh_token) = app.session.refresh_token.clone() else {
+                return Command::none();
+            };
 
+            app.session.refreshing = true;
+            Command::perform(
+                refresh_request(app.session.client.clone(), app.session.server.url.clone(), refresh_token, app.verifier.clone()),
+                Message::SessionRefreshed,
+            )
+        }
+        Message::SessionRefreshed(result) => {
+            app.session.refreshing = false;
+            match result {
+                Ok(tokens) => {
+                    let save = remember_login(app, &tokens);
+                    app.session.token = tokens.token;
+                    app.session.token_exp = tokens.token_exp;
+                    app.session.refresh_token = tokens.refresh_token;
+                    app.session.claims = tokens.claims;
+                    save
+                }
+                // Tried again on the next tick, until the token runs out.
+                Err(e) => {
+                    eprintln!("{}", e);
+                    Command::none()
+                }
+            }
         }
         Message::LoginFieldChanged(login, password) => {
-            app.login_field.login = login;
-            app.login_field.password = password;
+            app.session.login_field.login = login;
+            app.session.login_field.password = password;
+            Command::none()
         }
         Message::DeleteFile(index) => {
-            delete_file_request(app, index);
-
-            println!("{}", index);
+            if !allowed(app, Permission::Delete, "delete files") {
+                return Command::none();
+            }
+            match app.session.packages.get(index) {
+                Some(package_row) if !package_row.chunks.is_empty() => {
+                    Command::perform(
+                        delete_selected_request(
+                            app.session.client.clone(),
+                            app.session.server.url.clone(),
+                            app.session.token.clone(),
+                            package_row.chunks.clone(),
+                        ),
+                        Message::SelectedDeleted,
+                    )
+                }
+                Some(package_row) => {
+                    Command::perform(
+                        delete_file_request(
+                            app.session.client.clone(),
+                            app.session.server.url.clone(),
+                            app.session.token.clone(),
+                            package_row.info.name.clone(),
+                        ),
+                        Message::FileDeleted,
+                    )
+                }
+                None => {
+                    eprintln!("Index out of bounds");
+                    Command::none()
+                }
+            }
+        }
+        Message::FileDeleted(result) => {
+            match result {
+                Ok(filename) => {
+                    app.session.packages.retain(|package| package.info.name != filename);
+                    if app.session.preview.as_ref().is_some_and(|pane| pane.name == filename) {
+                        app.session.preview = None;
+                    }
+                    println!("File deleted successfully");
+                }
+                Err(e) => notify(app, e),
+            }
+            Command::none()
         }
         Message::ToggleCheck(index) => {
-            if let Some(row) = app.packages.get_mut(index) {
+            if let Some(row) = app.session.packages.get_mut(index) {
                 row.checked = !row.checked;
             }
+            Command::none()
         }
         Message::SelectAll(checked) => {
-            select_all(&mut app.packages, checked);
+            select_all(&mut app.session.packages, checked);
+            Command::none()
         }
         Message::DeleteSelected => {
-            if delete_selected(app) {
-                files_request(app);
+            if !allowed(app, Permission::Delete, "delete files") {
+                return Command::none();
             }
-            else {
+            let file_list: Vec<String> = app.session.packages
+                .iter()
+                .filter(|package| package.checked)
+                .flat_map(|package| package.server_names())
+                .collect();
+
+            if file_list.is_empty() {
                 println!("Delete files error");
+                return Command::none();
+            }
+
+            Command::perform(
+                delete_selected_request(
+                    app.session.client.clone(),
+                    app.session.server.url.clone(),
+                    app.session.token.clone(),
+                    file_list,
+                ),
+                Message::SelectedDeleted,
+            )
+        }
+        Message::SelectedDeleted(result) => {
+            match result {
+                Ok(()) => files_command(app),
+                Err(e) => {
+                    notify(app, e);
+                    files_command(app)
+                }
             }
         }
         Message::Refresh => {
-            files_request(app);
+            files_command(app)
+        }
+        Message::FilesLoaded(result) => {
+            match result {
+                Ok(files) => {
+                    let (dirs, files): (Vec<FileInfo>, Vec<FileInfo>) = files
+                        .into_iter()
+                        .partition(|file| file.is_dir || file.name.ends_with(SEPARATOR));
+                    app.session.folders.extend(dirs.iter().map(|dir| folder_path("", &dir.name)));
+
+                    app.session.packages = group_chunks(files)
+                        .into_iter()
+                        .map(|file| PackageRow::chunked(file.info, file.chunks))
+                        .collect();
+                    if let Some(pane) = &app.session.preview {
+                        if !app.session.packages.iter().any(|package| package.info.name == pane.name) {
+                            app.session.preview = None;
+                        }
+                    }
+                }
+                Err(e) => {
+                    notify(app, e);
+                    app.session.packages = vec![];
+                }
+            }
+            Command::none()
         }
         Message::DownloadFile(filename) => {
-            download_request(app, filename);
+            if !allowed(app, Permission::Download, "download files") {
+                return Command::none();
+            }
+            let chunks = app.session.packages
+                .iter()
+                .find(|package| package.info.name == filename)
+                .map(|package| package.chunks.clone())
+                .unwrap_or_default();
+
+            if is_downloading(app, &filename, &chunks) {
+                return Command::none();
+            }
+
+            if let Some(dir_path) = download_dir(app) {
+                match resolve(dir_path.join(plain_name(base_name(&filename))), app.collision, &HashSet::new()) {
+                    Some(target) => {
+                        let assembly = queue_download(app, &filename, &chunks, target);
+                        if !chunks.is_empty() {
+                            app.session.assemblies.push(assembly);
+                        }
+                    }
+                    None => notify(app, format!("{} already exists, skipped", plain_name(base_name(&filename)))),
+                }
+            }
+            Command::none()
+        }
+        Message::DownloadSelected(as_zip) => {
+            if !allowed(app, Permission::Download, "download files") {
+                return Command::none();
+            }
+            let selected: Vec<(String, Vec<String>)> = app.session.packages
+                .iter()
+                .filter(|package| package.checked)
+                .map(|package| (package.info.name.clone(), package.chunks.clone()))
+                .filter(|(filename, chunks)| !is_downloading(app, filename, chunks))
+                .collect();
+
+            if selected.is_empty() {
+                notify(app, String::from("Nothing selected to download"));
+                return Command::none();
+            }
+            let Some(dir_path) = download_dir(app) else {
+                return Command::none();
+            };
+
+            if as_zip {
+                queue_bundle(app, dir_path, selected);
+            } else {
+                let mut claimed = HashSet::new();
+                for (filename, chunks) in selected {
+                    let Some(target) = resolve(dir_path.join(plain_name(base_name(&filename))), app.collision, &claimed) else {
+                        notify(app, format!("{} already exists, skipped", plain_name(base_name(&filename))));
+                        continue;
+                    };
+                    claimed.insert(target.clone());
+
+                    let assembly = queue_download(app, &filename, &chunks, target);
+                    if !chunks.is_empty() {
+                        app.session.assemblies.push(assembly);
+                    }
+                }
+            }
+            select_all(&mut app.session.packages, false);
+            Command::none()
+        }
+        Message::CollisionChanged(collision) => {
+            app.collision = collision;
+            Command::none()
+        }
+        Message::Zipped(result) => {
+            match result {
+                Ok(path) => notify(app, format!("Archive written to {}", path.display())),
+                Err(e) => notify(app, format!("Failed to write archive: {}", e)),
+            }
+            Command::none()
+        }
+        Message::Assembled(result) => {
+            match result {
+                Ok(path) => {
+                    notify(app, format!("File assembled into {}", path.display()));
+                    relay_downloaded(app, &path);
+                }
+                Err(e) => notify(app, format!("Failed to assemble chunks: {}", e)),
+            }
+            Command::none()
         }
         Message::UploadFiles => {
-            if upload_request(app) {
-                files_request(app);
+            if !allowed(app, Permission::Upload, "upload files") {
+                return Command::none();
+            }
+            if let Some(file_paths) = pick_upload_files() {
+                queue_uploads(app, file_paths);
+            }
+            Command::none()
+        }
+        Message::UploadFolder => {
+            if !allowed(app, Permission::Upload, "upload files") {
+                return Command::none();
+            }
+            match pick_dir() {
+                Some(root) => scan_command(app, root),
+                None => Command::none(),
+            }
+        }
+        Message::FileHovered => {
+            app.drop_hover = app.session.page == Page::Main && app.session.claims.can(Permission::Upload);
+            Command::none()
+        }
+        Message::FilesHoveredLeft => {
+            app.drop_hover = false;
+            Command::none()
+        }
+        Message::FileDropped(path) => {
+            app.drop_hover = false;
+            if app.session.page != Page::Main || !allowed(app, Permission::Upload, "upload files") {
+                return Command::none();
+            }
+
+            if path.is_dir() {
+                scan_command(app, path)
+            } else {
+                queue_uploads(app, vec![path]);
+                Command::none()
+            }
+        }
+        Message::FolderScanned(result) => {
+            match result {
+                Ok(upload) if upload.files.is_empty() && upload.oversized.is_empty() => {
+                    app.session.folder_error = Some(format!("Nothing to upload in {:?}", upload.root));
+                }
+                Ok(upload) => app.session.folder_uploads.push(upload),
+                Err(e) => {
+                    eprintln!("Folder scan error: {}", e);
+                    app.session.folder_error = Some(e);
+                }
+            }
+            Command::none()
+        }
+        Message::ConfirmFolderUpload(index) => {
+            if index < app.session.folder_uploads.len() && allowed(app, Permission::Upload, "upload files") {
+                let upload = app.session.folder_uploads.remove(index);
+                // Oversized files end up in the rejected list, where they can still be split.
+                for file in upload.files.into_iter().chain(upload.oversized) {
+                    let file_name = format!("{}{}", upload.target_dir, file.relative);
+                    queue_upload(app, file.path, file_name, file.size);
+                }
+            }
+            Command::none()
+        }
+        Message::CancelFolderUpload(index) => {
+            if index < app.session.folder_uploads.len() {
+                app.session.folder_uploads.remove(index);
+            }
+            Command::none()
+        }
+        Message::TransferProgress(id, progress) => {
+            let Some(job) = app.session.transfers.get_mut(id) else {
+                return Command::none();
+            };
+
+            match progress {
+                Progress::Resumable(value) => {
+                    match job.kind {
+                        Kind::Upload => job.upload_id = Some(value),
+                        Kind::Download => job.checksum = Some(value),
+                    }
+                    record_transfer(app, id);
+                    Command::none()
+                }
+                Progress::Hashed(_) => {
+                    job.update(&progress);
+                    record_transfer(app, id);
+                    Command::none()
+                }
+                Progress::Finished => {
+                    job.update(&progress);
+                    let job = job.clone();
+                    app.journal.remove(&app.session.server.url, &job);
+                    app.session.transfers.schedule();
+
+                    match job.kind {
+                        Kind::Upload => {
+                            remove_relayed(app, &job.path);
+                            files_command(app)
+                        }
+                        Kind::Download => {
+                            println!("File {} downloaded to {:?}", job.file_name, job.path);
+                            for bundle in &mut app.session.bundles {
+                                bundle.pending.retain(|pending| *pending != id);
+                            }
+                            relay_downloaded(app, &job.path);
+                            Command::batch([assemble_finished(app), bundles_ready(app)])
+                        }
+                    }
+                }
+                Progress::Errored(ref e) => {
+                    eprintln!("Transfer of {} failed: {}", job.file_name, e);
+                    // Other failures stay in the transfer list, where they can be retried.
+                    if e.starts_with(CHECKSUM_MISMATCH) && job.kind == Kind::Upload {
+                        // The server dropped what it received, a retry reads and uploads everything again.
+                        job.upload_id = None;
+                        job.sha256 = None;
+                    }
+                    if e.starts_with(PERMISSION_DENIED) || e.starts_with(CHECKSUM_MISMATCH) {
+                        let message = format!("{}: {}", job.file_name, e);
+                        job.update(&progress);
+                        notify(app, message);
+                    } else {
+                        job.update(&progress);
+                    }
+                    record_transfer(app, id);
+                    app.session.transfers.schedule();
+                    Command::none()
+                }
+                _ => {
+                    job.update(&progress);
+                    Command::none()
+                }
+            }
+        }
+        Message::CancelTransfer(id) => {
+            // Cancelling one chunk of a split download cancels the whole file.
+            let mut ids = match app.session.assemblies.iter().position(|assembly| assembly.jobs.contains(&id)) {
+                Some(index) => app.session.assemblies.remove(index).jobs,
+                None => vec![id],
+            };
+            // A cancelled file is left out of its archive.
+            for bundle in &mut app.session.bundles {
+                if let Some(index) = bundle.entries.iter().position(|entry| entry.jobs.contains(&id)) {
+                    ids = bundle.entries.remove(index).jobs;
+                    bundle.pending.retain(|pending| !ids.contains(pending));
+                }
             }
-            else {
-                println!("Upload files error");
+
+            for id in ids {
+                if let Some(job) = app.session.transfers.remove(id) {
+                    app.journal.remove(&app.session.server.url, &job);
+                    if job.kind == Kind::Download && !job.is_finished() {
+                        let _ = remove_file(part_path(&job.path));
+                    }
+                }
+            }
+            bundles_ready(app)
+        }
+        Message::PauseTransfer(id) => {
+            app.session.transfers.pause(id);
+            record_transfer(app, id);
+            Command::none()
+        }
+        Message::ResumeTransfer(id) => {
+            app.session.transfers.requeue(id);
+            Command::none()
+        }
+        Message::MoveTransfer(id, offset) => {
+            app.session.transfers.move_by(id, offset);
+            Command::none()
+        }
+        Message::ClearFinishedTransfers => {
+            app.session.transfers.clear_finished();
+            Command::none()
+        }
+        Message::SetMaxConcurrent(max_concurrent) => {
+            app.session.transfers.set_max_concurrent(max_concurrent);
+            Command::none()
+        }
+        Message::ResumeTransfers => {
+            let entries = app.journal.take_pending(&app.session.server.url, app.session.transfers.jobs());
+            for entry in entries {
+                resume_transfer(app, entry);
+            }
+            Command::none()
+        }
+        Message::DiscardTransfers => {
+            let entries = app.journal.take_pending(&app.session.server.url, app.session.transfers.jobs());
+            for entry in entries {
+                if entry.kind == Kind::Download {
+                    let _ = remove_file(part_path(&entry.local_path));
+                }
+            }
+            Command::none()
+        }
+        Message::SplitUpload(index) => {
+            if index < app.session.rejected.len() && allowed(app, Permission::Upload, "upload files") {
+                let rejected = app.session.rejected.remove(index);
+                queue_chunks(app, rejected);
             }
+            Command::none()
+        }
+        Message::DismissRejected(index) => {
+            if index < app.session.rejected.len() {
+                app.session.rejected.remove(index);
+            }
+            Command::none()
         }
         Message::SearchFieldChanged(search) => {
-            app.search_text = search;
-            select_all(&mut app.packages, false);
+            set_search(app, search);
+            Command::none()
         }
-    }
-}
+        Message::SaveSearch => {
+            let search = app.session.search_text.trim().to_string();
+            if app.session.search.is_ok() && !search.is_empty() && !app.saved_searches.contains(&search) {
+                app.saved_searches.push(search);
+                save_saved_searches(&app.saved_searches);
+            }
+            Command::none()
+        }
+        Message::ApplySavedSearch(index) => {
+            if let Some(search) = app.saved_searches.get(index).cloned() {
+                set_search(app, search);
+            }
+            Command::none()
+        }
+        Message::RemoveSavedSearch(index) => {
+            if index < app.saved_searches.len() {
+                app.saved_searches.remove(index);
+                save_saved_searches(&app.saved_searches);
+            }
+            Command::none()
+        }
+        Message::SortBy(column) => {
+            app.settings.columns.toggle_sort(column);
+            app.settings.save();
+            Command::none()
+        }
+        Message::ResizeColumn(column) => {
+            if let Some(width) = app.settings.columns.width(column) {
+                app.resizing = Some(Resize {
+                    column,
+                    origin_x: None,
+                    origin_width: width,
+                });
+            }
+            Command::none()
+        }
+        Message::ColumnDragged(x) => {
+            if let Some(resize) = &mut app.resizing {
+                match resize.origin_x {
+                    Some(origin_x) => app.settings.columns.set_width(resize.column, resize.origin_width + x - origin_x),
+                    None => resize.origin_x = Some(x),
+                }
+            }
+            Command::none()
+        }
+        Message::ColumnResizeEnd => {
+            if app.resizing.take().is_some() {
+                app.settings.save();
+            }
+            Command::none()
+        }
+        Message::OpenFolder(path) => {
+            open_folder(app, path);
+            Command::none()
+        }
+        Message::FolderClicked(path) => {
+            let now = Instant::now();
+            match app.session.last_click.take() {
+                Some((last, at)) if last == path && now.duration_since(at) < DOUBLE_CLICK => open_folder(app, path),
+                _ => app.session.last_click = Some((path, now)),
+            }
+            Command::none()
+        }
+        Message::NewFolderNameChanged(name) => {
+            app.session.new_folder_name = name;
+            app.session.folder_error = None;
+            Command::none()
+        }
+        Message::CreateFolder => {
+            if !allowed(app, Permission::ManageFolders, "create folders") {
+                return Command::none();
+            }
+            let name = match validate_name(&app.session.new_folder_name) {
+                Ok(name) => name,
+                Err(e) => {
+                    app.session.folder_error = Some(e);
+                    return Command::none();
+                }
+            };
+            let path = folder_path(&app.session.current_dir, name);
+            if folder_exists(app, &path) {
+                app.session.folder_error = Some(format!("Folder '{}' already exists", name));
+                return Command::none();
+            }
 
+            Command::perform(
+                create_folder_request(app.session.client.clone(), app.session.server.url.clone(), app.session.token.clone(), path),
+                Message::FolderCreated,
+            )
+        }
+        Message::FolderCreated(result) => {
+            match result {
+                Ok(path) => {
+                    app.session.folders.insert(path);
+                    app.session.new_folder_name.clear();
+                }
+                Err(e) if e.starts_with(PERMISSION_DENIED) => notify(app, e),
+                Err(e) => app.session.folder_error = Some(e),
+            }
+            Command::none()
+        }
+        Message::StartRename(path) => {
+            if !allowed(app, Permission::ManageFolders, "rename folders") {
+                return Command::none();
+            }
+            app.session.renaming = Some(FolderRename {
+                name: base_name(&path).to_string(),
+                path,
+            });
+            Command::none()
+        }
+        Message::RenameChanged(name) => {
+            if let Some(renaming) = &mut app.session.renaming {
+                renaming.name = name;
+            }
+            Command::none()
+        }
+        Message::SubmitRename => {
+            let Some(renaming) = app.session.renaming.take() else {
+                return Command::none();
+            };
+            let name = match validate_name(&renaming.name) {
+                Ok(name) => name,
+                Err(e) => {
+                    app.session.folder_error = Some(e);
+                    app.session.renaming = Some(renaming);
+                    return Command::none();
+                }
+            };
+            let from = renaming.path.clone();
+            let to = folder_path(parent(&from), name);
+            if from == to {
+                return Command::none();
+            }
+            if folder_exists(app, &to) {
+                app.session.folder_error = Some(format!("Folder '{}' already exists", name));
+                app.session.renaming = Some(renaming);
+                return Command::none();
+            }
+            rename_folder(app, &from, &to)
+        }
+        Message::CancelRename => {
+            app.session.renaming = None;
+            app.session.folder_error = None;
+            Command::none()
+        }
+        Message::DeleteFolder(path) => {
+            if !allowed(app, Permission::ManageFolders, "delete folders") || !allowed(app, Permission::Delete, "delete files") {
+                return Command::none();
+            }
+            let files: Vec<String> = app.session.packages
+                .iter()
+                .filter(|package| package.info.name.starts_with(&path))
+                .flat_map(|package| package.server_names())
+                .collect();
 
-pub fn is_token_expired(token_exp: i64) -> bool {
-    let cur_time = Utc::now().timestamp();
+            app.session.folders.retain(|folder| !folder.starts_with(&path));
+            if app.session.current_dir.starts_with(&path) {
+                app.session.current_dir = parent(&path).to_string();
+            }
 
-    token_exp < cur_time
-}
-fn select_all(packages: &mut Vec<PackageRow>, checked: bool) {
-    for package_row in packages {
-        package_row.checked = checked;
-    }
-}
+            Command::perform(
+                delete_folder_request(app.session.client.clone(), app.session.server.url.clone(), app.session.token.clone(), path, files),
+                Message::SelectedDeleted,
+            )
+        }
+        Message::MoveSelectedHere => {
+            if !allowed(app, Permission::ManageFolders, "move files") {
+                return Command::none();
+            }
+            let existing: HashSet<String> = app.session.packages
+                .iter()
+                .flat_map(|package| package.server_names())
+                .collect();
+            let mut moves = vec![];
+            let mut skipped = vec![];
 
-fn upload_request(app: &App) -> bool {
-    let result = FileDialog::new()
-        .set_location("~")
-        .show_open_multiple_file();
+            for package in app.session.packages.iter().filter(|package| package.checked) {
+                for name in package.server_names() {
+                    let target = join(&app.session.current_dir, base_name(&name));
+                    if target == name {
+                        continue;
+                    }
+                    if existing.contains(&target) {
+                        skipped.push(base_name(&name).to_string());
+                        continue;
+                    }
+                    moves.push((name, target));
+                }
+            }
+
+            app.session.folder_error = if skipped.is_empty() {
+                None
+            } else {
+                Some(format!("Already here, not moved: {}", skipped.join(", ")))
+            };
+            if moves.is_empty() {
+                return Command::none();
+            }
+            select_all(&mut app.session.packages, false);
 
-    match result
-    {
-        Ok(file_paths) => {
-            let mut form = multipart::Form::new();
+            Command::perform(
+                move_files_request(app.session.client.clone(), app.session.server.url.clone(), app.session.token.clone(), moves),
+                Message::Moved,
+            )
+        }
+        Message::Moved(result) => {
+            match result {
+                Ok(()) => {}
+                Err(e) if e.starts_with(PERMISSION_DENIED) => notify(app, e),
+                Err(e) => {
+                    eprintln!("Move error: {}", e);
+                    app.session.folder_error = Some(e);
+                }
+            }
+            files_command(app)
+        }
+        Message::DismissToast(index) => {
+            if index < app.toasts.len() {
+                app.toasts.remove(index);
+            }
+            Command::none()
+        }
+        Message::ExpireToasts => {
+            app.toasts.retain(|toast| toast.shown_at.elapsed() < TOAST_DURATION);
+            Command::none()
+        }
+        Message::NewSession => {
+            app.open_session();
+            match app.session.profile.or((!app.profiles.is_empty()).then_some(0)) {
+                Some(index) => select_profile(app, index),
+                None => app.profile_editor = Some(ProfileEditor::new(None, &Profile::default())),
+            }
+            clear_password(&mut app.session.login_field);
+            app.session.page = Page::Login;
+            Command::none()
+        }
+        Message::ShowSession(id) => {
+            if app.show_session(id) {
+                return_to_tab(app);
+            }
+            Command::none()
+        }
+        Message::CloseSession(id) => {
+            close_session(app, id);
+            Command::none()
+        }
+        Message::BackToSessions => {
+            if let Some(last) = app.background.iter().map(|session| session.id).max() {
+                if app.session.token.is_empty() {
+                    close_session(app, app.session.id);
+                } else {
+                    app.show_session(last);
+                    return_to_tab(app);
+                }
+            }
+            Command::none()
+        }
+        Message::StartDrag(index) => {
+            if !allowed(app, Permission::Download, "download files") {
+                return Command::none();
+            }
+            let Some(package) = app.session.packages.get(index) else {
+                return Command::none();
+            };
+            // Dragging a selected row takes the whole selection along.
+            let files = if package.checked {
+                app.session.packages.iter().filter(|package| package.checked).collect()
+            } else {
+                vec![package]
+            };
+            app.dragging = Some(files
+                .into_iter()
+                .map(|package| DraggedFile {
+                    name: package.info.name.clone(),
+                    chunks: package.chunks.clone(),
+                })
+                .collect());
+            Command::none()
+        }
+        Message::CancelDrag => {
+            app.dragging = None;
+            Command::none()
+        }
+        Message::DropOnSession(to) => {
+            let Some(files) = app.dragging.take() else {
+                return Command::none();
+            };
+            if to == app.session.id {
+                return Command::none();
+            }
+            match app.with_session(to, |app| (app.session.is_logged_in(), app.session.claims.can(Permission::Upload))) {
+                Some((true, true)) => relay_files(app, to, files),
+                Some((true, false)) => notify(app, denied("upload files to that server")),
+                Some((false, _)) => notify(app, String::from("Log in to that server before copying files to it")),
+                None => {}
+            }
+            Command::none()
+        }
+        Message::OpenSync => {
+            app.session.page = Page::Sync;
+            Command::none()
+        }
+        Message::CloseSync => {
+            show_page(app);
+            Command::none()
+        }
+        Message::AddSyncFolder => {
+            if !allowed(app, Permission::Upload, "upload files") || !allowed(app, Permission::Download, "download files") {
+                return Command::none();
+            }
+            if app.session.server.encrypt {
+                notify(app, String::from(SYNC_ENCRYPTED));
+                return Command::none();
+            }
+            let Some(local) = pick_dir() else {
+                return Command::none();
+            };
+            if app.session.sync.iter().any(|job| job.folder.local == local) {
+                notify(app, format!("{:?} is already synced", local));
+                return Command::none();
+            }
 
-            for file_path in file_paths {
-                let path = Path::new(&file_path);
+            let name = local.file_name().unwrap_or_default().to_string_lossy().to_string();
+            let folder = SyncFolder {
+                remote_dir: folder_path(&app.session.current_dir, &name),
+                local,
+                base: BTreeMap::new(),
+            };
+            let id = app.session.sync.iter().map(|job| job.id + 1).max().unwrap_or(0);
+            app.session.sync.push(SyncJob::new(id, folder));
+            save_sync(app);
+            start_sync(app, id)
+        }
+        Message::RemoveSyncFolder(id) => {
+            // Files stay where they are on both sides.
+            app.session.sync.retain(|job| job.id != id);
+            save_sync(app);
+            Command::none()
+        }
+        Message::SyncNow(id) | Message::SyncLocalChanged(id) => start_sync(app, id),
+        Message::SyncWatchFailed(id, e) => {
+            eprintln!("{}", e);
+            if let Some(job) = app.session.sync.iter_mut().find(|job| job.id == id) {
+                job.errors.push((String::new(), e));
+            }
+            Command::none()
+        }
+        Message::SyncPoll => {
+            let ids: Vec<usize> = app.session.sync.iter().map(|job| job.id).collect();
+            Command::batch(ids.into_iter().map(|id| start_sync(app, id)))
+        }
+        Message::SyncPlanned(id, result) => {
+            let remote = remote(app);
+            let size_limit = app.session.size_limit;
+            let Some(job) = app.session.sync.iter_mut().find(|job| job.id == id) else {
+                return Command::none();
+            };
 
-                let file_data = metadata(path).unwrap();
-                if file_data.len() > 524288000 {
-                    println!("File {:?} size is bigger than 500MB", file_path);
-                    continue;
+            match result {
+                Ok(items) => {
+                    let (conflicts, pending): (Vec<SyncItem>, Vec<SyncItem>) = items
+                        .into_iter()
+                        .partition(|item| matches!(item.action, Action::Conflict(_)));
+                    job.conflicts = conflicts;
+                    job.pending = pending;
+                    job.errors.clear();
+                    if job.pending.is_empty() {
+                        return finish_sync(app, id);
+                    }
+                    Command::perform(
+                        apply(remote, job.folder.clone(), job.pending.clone(), size_limit),
+                        move |results| Message::SyncApplied(id, results),
+                    )
+                }
+                Err(e) => {
+                    eprintln!("Sync of {:?} failed: {}", job.folder.local, e);
+                    job.errors = vec![(String::new(), e)];
+                    finish_sync(app, id)
                 }
-                let file_name = path.file_name().unwrap_or_default().to_str().unwrap_or_default();
+            }
+        }
+        Message::SyncApplied(id, results) => {
+            let Some(job) = app.session.sync.iter_mut().find(|job| job.id == id) else {
+                return Command::none();
+            };
 
-                let mut file = File::open(path).unwrap();
-                let mut file_content = Vec::new();
+            for (relative, result) in results {
+                job.pending.retain(|item| item.relative != relative);
+                match result {
+                    Ok(base) => {
+                        match base {
+                            Some(base) => job.folder.base.insert(relative.clone(), base),
+                            None => job.folder.base.remove(&relative),
+                        };
+                        job.synced.retain(|synced| *synced != relative);
+                        job.synced.insert(0, relative);
+                    }
+                    Err(e) => job.errors.push((relative, e)),
+                }
+            }
+            job.synced.truncate(SYNCED_SHOWN);
+            save_sync(app);
+            finish_sync(app, id)
+        }
+        Message::ResolveConflict(id, relative, resolution) => {
+            let remote = remote(app);
+            let size_limit = app.session.size_limit;
+            let Some(job) = app.session.sync.iter_mut().find(|job| job.id == id) else {
+                return Command::none();
+            };
+            if job.running {
+                notify(app, String::from("Wait for the running sync to finish"));
+                return Command::none();
+            }
+            let Some(index) = job.conflicts.iter().position(|item| item.relative == relative) else {
+                return Command::none();
+            };
 
-                file.read_to_end(&mut file_content).unwrap();
+            let item = job.conflicts.remove(index).resolve(resolution);
+            job.pending.push(item.clone());
+            job.running = true;
+            Command::perform(
+                apply(remote, job.folder.clone(), vec![item], size_limit),
+                move |results| Message::SyncApplied(id, results),
+            )
+        }
+        Message::OpenPreview(name) => {
+            if app.session.preview.as_ref().is_some_and(|pane| pane.name == name) {
+                app.session.preview = None;
+                return Command::none();
+            }
+            if !allowed(app, Permission::Download, "preview files") {
+                return Command::none();
+            }
+            let Some(package) = app.session.packages.iter().find(|package| package.info.name == name) else {
+                return Command::none();
+            };
 
-                let part = Part::bytes(file_content).file_name(file_name.to_string());
-                form = form.part("files", part);
+            let file = ListedFile {
+                info: package.info.clone(),
+                chunks: package.chunks.clone(),
+            };
+            app.session.preview = Some(PreviewPane::new(name.clone()));
+            Command::perform(preview::load(remote(app), file), move |result| Message::PreviewLoaded(name, result))
+        }
+        Message::PreviewLoaded(name, result) => {
+            // Another file may have been picked in the meantime.
+            if let Some(pane) = app.session.preview.as_mut().filter(|pane| pane.name == name) {
+                if let Err(e) = &result {
+                    eprintln!("Preview of {} failed: {}", name, e);
+                }
+                pane.loaded(result);
             }
+            Command::none()
+        }
+        Message::ClosePreview => {
+            app.session.preview = None;
+            Command::none()
+        }
+        Message::ViewChanged(view) => {
+            app.settings.view = view;
+            app.settings.save();
+            Command::none()
+        }
+        Message::ThumbnailLoaded(key, result) => {
+            let thumbnail = match result {
+                Ok(png) => Thumbnail::Ready(iced::widget::image::Handle::from_memory(png)),
+                Err(e) => {
+                    eprintln!("No thumbnail: {}", e);
+                    Thumbnail::Failed
+                }
+            };
+            app.session.thumbnails.insert(key, thumbnail);
+            Command::none()
+        }
+        Message::Session(_, message) => handle_update(app, *message),
+    }
+}
 
-            let url = format!("{}/files/upload", app.server.url);
-            let response = app.client.post(&url)
-                .multipart(form)
-                .header("Authorization", format!("Bearer {}", app.token))
-                .send();
+/// Thumbnails made at once, the rest wait for one of them to finish.
+const THUMBNAIL_LOADS: usize = 4;
 
-            println!("{:?}", response);
+/// Starts on the thumbnails of the files the grid shows that don't have one yet.
+pub fn load_thumbnails(app: &mut App) -> Command<Message> {
+    let shown = app.session.page == Page::Main && app.settings.view == FileView::Grid;
+    if !shown || !app.session.is_logged_in() || !app.session.claims.can(Permission::Download) {
+        return Command::none();
+    }
 
-            response.unwrap().status().is_success()
+    let loading = app.session.thumbnails.values().filter(|thumbnail| matches!(thumbnail, Thumbnail::Loading)).count();
+    let mut commands = vec![];
+    for index in visible_files(app) {
+        if loading + commands.len() >= THUMBNAIL_LOADS {
+            break;
         }
-        Err(e) => {
-            eprintln!("Error {}", e);
-            false
+        let package = &app.session.packages[index];
+        let file = ListedFile {
+            info: package.info.clone(),
+            chunks: package.chunks.clone(),
+        };
+        let key = cache_key(&app.session.server.url, &file.info);
+        if !has_thumbnail(&file) || app.session.thumbnails.contains_key(&key) {
+            continue;
         }
 
+        app.session.thumbnails.insert(key.clone(), Thumbnail::Loading);
+        commands.push(Command::perform(thumbnail::load(remote(app), file), move |result| Message::ThumbnailLoaded(key, result)));
+    }
+    Command::batch(commands)
+}
+
+/// How many synced files the sync page lists per folder.
+const SYNCED_SHOWN: usize = 50;
+
+/// Synced files keep their names on the server, which encrypted files can't.
+const SYNC_ENCRYPTED: &str = "Folders can't be synced with a server that encrypts uploads";
+
+fn remote(app: &App) -> Remote {
+    Remote {
+        client: app.session.client.clone(),
+        server_url: app.session.server.url.clone(),
+        token: app.session.token.clone(),
+        key: app.session.server.key,
+        encrypt: app.session.server.encrypt,
     }
+}
 
+fn save_sync(app: &App) {
+    let folders: Vec<SyncFolder> = app.session.sync.iter().map(|job| job.folder.clone()).collect();
+    save_folders(&app.session.server.url, &folders);
 }
 
+/// Compares both sides of the sync job `id`, or asks for another look if it is running already.
+fn start_sync(app: &mut App, id: usize) -> Command<Message> {
+    let remote = remote(app);
+    let encrypt = app.session.server.encrypt;
+    let Some(job) = app.session.sync.iter_mut().find(|job| job.id == id) else {
+        return Command::none();
+    };
+    if job.running {
+        job.rerun = true;
+        return Command::none();
+    }
+    if encrypt {
+        job.errors = vec![(String::new(), String::from(SYNC_ENCRYPTED))];
+        return Command::none();
+    }
 
-fn download_request(app: &App, filename: String) -> bool {
-    let result = FileDialog::new().set_location("~")
-        .show_open_single_dir();
+    job.running = true;
+    Command::perform(plan(remote, job.folder.clone()), move |result| Message::SyncPlanned(id, result))
+}
 
-    match result {
-        Ok(dir_path) => {
-            if let Some(dir_path) = dir_path {
-                let file_path = Path::new(&dir_path).join(filename.clone());
-
-                let file = File::create(file_path);
-                match file
-                {
-                    Ok(mut file) => {
-                        let url = format!("{}/files/", app.server.url);
-
-                        let response = app.client.get(&url)
-                            .json(&filename)
-                            .header("Authorization", format!("Bearer {}", app.token))
-                            .send();
-
-                        match response
-                        {
-                            Ok(response) => {
-                                let content = response.bytes();
-
-                                match content
-                                {
-                                    Ok(content) => {
-                                        match copy(&mut content.as_ref(), &mut file)
-                                        {
-                                            Ok(_) => {
-                                                true
-                                            }
-                                            Err(e) => {
-                                                eprintln!("{}", e);
-                                                false
-                                            }
-                                        }
-                                    }
-                                    Err(e) => {
-                                        eprintln!("{}", e);
-                                        false
-                                    }
-                                }
+fn finish_sync(app: &mut App, id: usize) -> Command<Message> {
+    let Some(job) = app.session.sync.iter_mut().find(|job| job.id == id) else {
+        return Command::none();
+    };
+    job.running = false;
+    job.last_run = Some(Utc::now().timestamp());
 
-                            }
+    if mem::take(&mut job.rerun) {
+        start_sync(app, id)
+    } else {
+        Command::none()
+    }
+}
 
-                            Err(e) => {
-                                eprintln!("{}", e);
-                                false
-                            }
-                        }
+/// Shows `message` as a toast, and logs it like the other errors.
+fn notify(app: &mut App, message: String) {
+    eprintln!("{}", message);
+    app.toasts.push(Toast {
+        message,
+        shown_at: Instant::now(),
+    });
+}
 
-                    }
+/// The UI hides what the claims don't allow, this catches the rest (drops, stale rows).
+fn allowed(app: &mut App, permission: Permission, action: &str) -> bool {
+    let allowed = app.session.claims.can(permission);
+    if !allowed {
+        notify(app, denied(action));
+    }
+    allowed
+}
 
-                    Err(e) => {
-                        eprintln!("{}", e);
-                        false
-                    }
-                }
+
+/// Switches to `profiles[index]`, with a client built from its TLS and proxy settings.
+pub fn select_profile(app: &mut App, index: usize) {
+    let profile = &app.profiles[index];
+    match profile.client() {
+        Ok(client) => {
+            app.session.client = client;
+            app.session.login_error = None;
+        }
+        Err(e) => app.session.login_error = Some(e),
+    }
+    app.session.server.url = profile.url.clone();
+    app.session.server.encrypt = profile.encrypt;
+    app.session.server.key = load_key(&profile.url);
+    if !profile.username.is_empty() {
+        app.session.login_field.login = profile.username.clone();
+    }
+    app.session.profile = Some(index);
+}
+
+/// Login page for a tab whose token is missing or expired.
+fn show_page(app: &mut App) {
+    app.dragging = None;
+    if app.session.is_logged_in() {
+        app.session.page = Page::Main;
+    } else {
+        app.session.page = Page::Login;
+        clear_password(&mut app.session.login_field);
+    }
+}
+
+/// Shows a tab on the page it was left on, or the login page if its session ran out since.
+fn return_to_tab(app: &mut App) {
+    app.dragging = None;
+    if !app.session.is_logged_in() && matches!(app.session.page, Page::Main | Page::Sync) {
+        app.session.page = Page::Login;
+        clear_password(&mut app.session.login_field);
+    }
+}
+
+/// Closes the tab `id`, its running transfers stop with it. Closing the last tab leaves a blank one.
+fn close_session(app: &mut App, id: usize) {
+    if app.session.id == id {
+        match app.background.iter().map(|session| session.id).max() {
+            Some(next) => {
+                app.show_session(next);
+                return_to_tab(app);
             }
-            else {
-                eprintln!("Dir path error");
-                false
+            None => {
+                app.open_session();
+                if let Some(index) = app.background.first().and_then(|session| session.profile) {
+                    select_profile(app, index);
+                }
+                app.session.page = Page::Login;
             }
         }
-        Err(e) => {
-            eprintln!("{}", e);
-            false
+    }
+    app.background.retain(|session| session.id != id);
+
+    let (dropped, kept) = app.relays.drain(..).partition(|relay: &Relay| relay.from == id || relay.to == id);
+    app.relays = kept;
+    for relay in dropped {
+        let _ = remove_file(&relay.path);
+    }
+}
+
+/// Where files copied between servers wait between their download and upload.
+pub fn relay_dir() -> PathBuf {
+    data_dir().join("relay")
+}
+
+/// Downloads `files` from the current session, to be uploaded by the session `to`
+/// into the folder it is browsing.
+fn relay_files(app: &mut App, to: usize, files: Vec<DraggedFile>) {
+    let dir = relay_dir().join(app.session.id.to_string());
+    if let Err(e) = create_dir_all(&dir) {
+        notify(app, format!("{:?}: {}", dir, e));
+        return;
+    }
+    let target_dir = app.with_session(to, |app| app.session.current_dir.clone()).unwrap_or_default();
+
+    let mut claimed = HashSet::new();
+    for file in files {
+        if is_downloading(app, &file.name, &file.chunks) {
+            continue;
+        }
+        let Some(path) = resolve(dir.join(plain_name(base_name(&file.name))), Collision::Rename, &claimed) else {
+            continue;
+        };
+        claimed.insert(path.clone());
+
+        let assembly = queue_download(app, &file.name, &file.chunks, path.clone());
+        if !file.chunks.is_empty() {
+            app.session.assemblies.push(assembly);
         }
+        app.relays.push(Relay {
+            from: app.session.id,
+            to,
+            path,
+            name: join(&target_dir, plain_name(base_name(&file.name))),
+        });
     }
 }
-fn delete_selected(app: &App) -> bool {
-    let mut file_list: Vec<String> = vec![];
 
-    for package in &app.packages {
-        if package.checked {
-            file_list.push(package.filename.clone());
+/// Hands a file downloaded for another session over to its upload queue.
+fn relay_downloaded(app: &mut App, path: &PathBuf) {
+    let from = app.session.id;
+    let Some(index) = app.relays.iter().position(|relay| relay.from == from && relay.path == *path) else {
+        return;
+    };
+    let relay = app.relays.remove(index);
+
+    let size = match metadata(&relay.path) {
+        Ok(metadata) => metadata.len(),
+        Err(e) => {
+            notify(app, format!("{:?}: {}", relay.path, e));
+            return;
         }
+    };
+    let queued = app.with_session(relay.to, |app| queue_upload(app, relay.path.clone(), relay.name.clone(), size));
+    if queued.is_none() {
+        let _ = remove_file(&relay.path);
+    }
+}
+
+/// Removes a relayed file once nothing is left to upload from it.
+fn remove_relayed(app: &App, path: &PathBuf) {
+    let pending = app.session.transfers
+        .jobs()
+        .iter()
+        .any(|job| job.path == *path && !job.is_finished());
+    if path.starts_with(relay_dir()) && !pending {
+        let _ = remove_file(path);
+    }
+}
+
+fn open_folder(app: &mut App, path: String) {
+    app.session.current_dir = path;
+    app.session.renaming = None;
+    app.session.folder_error = None;
+    app.session.last_click = None;
+}
+
+fn folder_exists(app: &App, path: &str) -> bool {
+    app.session.folders.contains(path) || app.session.packages.iter().any(|package| package.info.name.starts_with(path))
+}
+
+/// Moves everything under `from` to `to`. Empty folders only exist as markers,
+/// so those are recreated under the new name and the old marker is dropped.
+fn rename_folder(app: &mut App, from: &str, to: &str) -> Command<Message> {
+    let moves: Vec<(String, String)> = app.session.packages
+        .iter()
+        .flat_map(|package| package.server_names())
+        .filter_map(|name| rebase(&name, from, to).map(|target| (name, target)))
+        .collect();
+
+    let markers: Vec<String> = app.session.folders
+        .iter()
+        .filter(|folder| folder.starts_with(from))
+        .cloned()
+        .collect();
+    for marker in &markers {
+        app.session.folders.remove(marker);
+    }
+
+    if let Some(current_dir) = rebase(&app.session.current_dir, from, to) {
+        app.session.current_dir = current_dir;
     }
-    if file_list.len() > 0
-    {
-        let url = format!("{}/files/", app.server.url);
-        let response = app.client.delete(&url)
-            .json(&file_list)
-            .header("Authorization", format!("Bearer {}", app.token))
-            .send();
 
-        println!("{:?}", response);
-        response.unwrap().status().is_success()
+    let mut commands = vec![];
+    if !moves.is_empty() {
+        commands.push(Command::perform(
+            move_files_request(app.session.client.clone(), app.session.server.url.clone(), app.session.token.clone(), moves),
+            Message::Moved,
+        ));
     }
-    else {
-        false
+    for marker in markers {
+        let target = rebase(&marker, from, to).unwrap_or_default();
+        commands.push(Command::perform(
+            create_folder_request(app.session.client.clone(), app.session.server.url.clone(), app.session.token.clone(), target),
+            Message::FolderCreated,
+        ));
+        commands.push(Command::perform(
+            delete_folder_request(app.session.client.clone(), app.session.server.url.clone(), app.session.token.clone(), marker, vec![]),
+            Message::Moved,
+        ));
     }
+
+    Command::batch(commands)
+}
+
+fn set_search(app: &mut App, search: String) {
+    app.session.search = Query::parse(&search);
+    app.session.search_text = search;
+    select_all(&mut app.session.packages, false);
 }
 
+fn select_all(packages: &mut Vec<PackageRow>, checked: bool) {
+    for package_row in packages {
+        package_row.checked = checked;
+    }
+}
 
+fn limits_command(app: &App) -> Command<Message> {
+    Command::perform(
+        limits_request(app.session.client.clone(), app.session.server.url.clone(), app.session.token.clone()),
+        Message::LimitsLoaded,
+    )
+}
+
+fn files_command(app: &App) -> Command<Message> {
+    Command::perform(
+        files_request(app.session.client.clone(), app.session.server.url.clone(), app.session.token.clone()),
+        Message::FilesLoaded,
+    )
+}
+
+fn pick_upload_files() -> Option<Vec<PathBuf>> {
+    let result = FileDialog::new()
+        .set_location("~")
+        .show_open_multiple_file();
 
+    match result {
+        Ok(file_paths) if !file_paths.is_empty() => Some(file_paths),
+        Ok(_) => None,
+        Err(e) => {
+            eprintln!("Error {}", e);
+            None
+        }
+    }
+}
 
-pub fn log_in_request(app: &mut App) -> bool {
-    let params = [
-        ("username", app.login_field.login.as_str()),
-        ("password", app.login_field.password.as_str())
-    ];
+/// The download folder from the settings, or the one the user picks when there is none
+/// or "always ask" is on.
+fn download_dir(app: &App) -> Option<PathBuf> {
+    match &app.settings.download_dir {
+        Some(dir) if !app.settings.always_ask => Some(dir.clone()),
+        Some(dir) => pick_dir_in(dir),
+        None => pick_dir(),
+    }
+}
 
-    let url = format!("{}/login", app.server.url);
+fn import_key_file(url: String, passphrase: String, path: PathBuf, confirmed: bool) -> Command<Message> {
+    Command::perform(
+        async move {
+            let (key_url, key_path) = (url.clone(), path.clone());
+            let result = tokio::task::spawn_blocking(move || import_key(&key_url, &passphrase, &key_path, confirmed))
+                .await
+                .unwrap_or_else(|e| Err(e.to_string()));
+            (url, path, result)
+        },
+        |(url, path, result)| Message::KeyImported(url, path, result),
+    )
+}
 
-    let response = app.client.post(&url)
-        .form(&params)
-        .send();
+/// Where to write an exported key, or which one to import.
+fn pick_key_file(save: bool) -> Option<PathBuf> {
+    let dialog = FileDialog::new()
+        .set_location("~")
+        .add_filter("Exported key", &["json"]);
+    let result = if save {
+        dialog.set_filename("fta-key.json").show_save_single_file()
+    } else {
+        dialog.show_open_single_file()
+    };
 
-    match response
-    {
-        Ok(response) => {
-            let json_result: Result<HashMap<String, String>, _> = response.json();
-            if let Ok(json) = json_result {
-                if let Some(token) = json.get("token") {
-                    app.token = token.clone();
-                    app.page = Page::Main;
-                    if let Some(seconds) = json.get("jwt_exp_seconds") {
-                        app.token_exp = Utc::now().timestamp() + seconds.parse::<i64>().expect("jwt seconds parse error");
-                        app.login_error = Some(String::from("JWT expired, log in again"));
-                    }
-                    return true;
-                }
-            }
+    result.unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        None
+    })
+}
 
-            app.login_error = Some(String::from("Wrong username or password"));
-            false
+/// Hands a new key of `url` to every tab on that server.
+fn set_key(app: &mut App, url: &str, key: Option<EncryptionKey>) {
+    for session in iter::once(&mut app.session).chain(app.background.iter_mut()) {
+        if session.server.url == url {
+            session.server.key = key;
+        }
+    }
+}
+
+fn pick_dir() -> Option<PathBuf> {
+    pick_dir_in(Path::new("~"))
+}
+
+fn pick_dir_in(location: &Path) -> Option<PathBuf> {
+    let result = FileDialog::new()
+        .set_location(location)
+        .show_open_single_dir();
+
+    match result {
+        Ok(Some(dir_path)) => Some(dir_path),
+        Ok(None) => {
+            eprintln!("Dir path error");
+            None
         }
         Err(e) => {
-            app.login_error = Some(String::from("Server connection error"));
-            eprintln!("Error sending request: {}", e);
-            false
+            eprintln!("{}", e);
+            None
         }
     }
 }
 
-pub fn files_request(app: &mut App) {
-    let response = app.client
-        .get(format!("{}/files/", app.server.url))
-        .header("Authorization", format!("Bearer {}", app.token))
-        .send();
+/// Stores the login when "Remember me" is on. The refresh token is kept rather than
+/// the password whenever the server hands one out.
+fn remember_login(app: &App, tokens: &Tokens) -> Command<Message> {
+    if !app.session.login_field.remember {
+        return Command::none();
+    }
 
-    match response {
+    let saved = SavedLogin {
+        server_url: app.session.server.url.clone(),
+        login: app.session.login_field.login.clone(),
+        refresh_token: tokens.refresh_token.clone(),
+        password: match tokens.refresh_token {
+            Some(_) => None,
+            None => Some(app.session.login_field.password.clone()),
+        },
+    };
+    let passphrase = app.session.login_field.passphrase.clone();
 
-        Ok(resp) => {
-            match resp.text()
-            {
-                Ok(data) => {
-                    match serde_json::from_str::<Vec<String>>(&data)
-                    {
-                        Ok(files) => {
-                            app.packages = files
-                                .into_iter()
-                                .map(PackageRow::new)
-                                .collect();
-                        }
-                        Err(err) => {
-                            eprintln!("Ошибка при разборе JSON: {}", err);
-                            app.packages = vec![];
-                        }
-                    }
-                }
-                Err(err) => {
-                    eprintln!("Ошибка при получении текста ответа: {}", err);
-                    app.packages = vec![];
-                }
+    Command::perform(
+        async move {
+            tokio::task::spawn_blocking(move || credentials::save(&saved, &passphrase))
+                .await
+                .unwrap_or_else(|e| Err(e.to_string()))
+        },
+        Message::LoginSaved,
+    )
+}
+
+/// Walks `root` in the background, the result is shown as a summary to confirm.
+fn scan_command(app: &App, root: PathBuf) -> Command<Message> {
+    let target_dir = app.session.current_dir.clone();
+    let size_limit = app.session.size_limit;
+
+    Command::perform(
+        async move {
+            tokio::task::spawn_blocking(move || scan(root, target_dir, size_limit))
+                .await
+                .unwrap_or_else(|e| Err(e.to_string()))
+        },
+        Message::FolderScanned,
+    )
+}
 
+fn queue_uploads(app: &mut App, file_paths: Vec<PathBuf>) {
+    for file_path in file_paths {
+        let file_data = match metadata(&file_path) {
+            Ok(file_data) => file_data,
+            Err(e) => {
+                eprintln!("{:?}: {}", file_path, e);
+                continue;
+            }
+        };
+        let file_name = join(&app.session.current_dir, &file_path.file_name().unwrap_or_default().to_string_lossy());
+        queue_upload(app, file_path, file_name, file_data.len());
+    }
+}
+
+/// Queues one upload, or sets it aside for splitting when it is over the server limit.
+/// Servers set to encrypt get the file under its encrypted name.
+fn queue_upload(app: &mut App, path: PathBuf, file_name: String, size: u64) {
+    let encrypt = app.session.server.encrypt;
+    let (file_name, sent) = if encrypt {
+        match key_for_upload(&app.session.server.url) {
+            Ok(key) => {
+                let url = app.session.server.url.clone();
+                set_key(app, &url, Some(key));
+                (encrypted_name(&file_name), encrypted_size(size))
+            }
+            Err(e) => {
+                notify(app, format!("{}: {}", file_name, e));
+                return;
             }
         }
-        Err(err) => {
-            eprintln!("Ошибка при выполнении запроса: {}", err);
-            app.packages = vec![];
+    } else {
+        (file_name, size)
+    };
+    if sent > app.session.size_limit {
+        app.session.rejected.push(RejectedFile { path, file_name, size, encrypt });
+        return;
+    }
+    let checksum = fingerprint(&path);
+
+    let id = app.session.transfers.push_with(Kind::Upload, file_name, path, size, |upload| {
+        upload.checksum = checksum;
+        upload.encrypt = encrypt;
+    });
+    record_transfer(app, id);
+}
+
+fn queue_chunks(app: &mut App, rejected: RejectedFile) {
+    // Every chunk of an encrypted file is encrypted on its own.
+    let limit = match rejected.encrypt {
+        true => plaintext_limit(app.session.size_limit),
+        false => app.session.size_limit,
+    };
+    let ranges = chunk_ranges(rejected.size, limit);
+    let checksum = fingerprint(&rejected.path);
+
+    for (index, (offset, length)) in ranges.iter().enumerate() {
+        let name = chunk_name(&rejected.file_name, index, ranges.len());
+        let id = app.session.transfers.push_with(Kind::Upload, name, rejected.path.clone(), *length, |upload| {
+            upload.source_offset = *offset;
+            upload.checksum = checksum.clone();
+            upload.encrypt = rejected.encrypt;
+        });
+        record_transfer(app, id);
+    }
+}
+
+/// Joins every chunked download whose pieces have all arrived.
+fn assemble_finished(app: &mut App) -> Command<Message> {
+    let (ready, waiting) = app.session.assemblies
+        .drain(..)
+        .partition(|assembly: &Assembly| {
+            assembly.jobs
+                .iter()
+                .all(|id| app.session.transfers.get(*id).is_some_and(|job| job.is_finished()))
+        });
+    app.session.assemblies = waiting;
+
+    Command::batch(
+        ready
+            .into_iter()
+            .map(|assembly| Command::perform(assemble(assembly.parts, assembly.target), Message::Assembled))
+    )
+}
+
+/// Writes every archive whose downloads are all done.
+fn bundles_ready(app: &mut App) -> Command<Message> {
+    let (ready, waiting) = app.session.bundles
+        .drain(..)
+        .partition(|bundle: &Bundle| bundle.pending.is_empty());
+    app.session.bundles = waiting;
+
+    Command::batch(ready.into_iter().filter_map(|bundle| {
+        if bundle.entries.is_empty() {
+            let _ = remove_dir_all(&bundle.staging);
+            return None;
         }
+        let entries = bundle.entries
+            .into_iter()
+            .map(|entry| (entry.name, entry.parts))
+            .collect();
+        Some(Command::perform(write_zip(bundle.target, bundle.staging, entries), Message::Zipped))
+    }))
+}
 
+fn is_downloading(app: &App, filename: &str, chunks: &[String]) -> bool {
+    if chunks.is_empty() {
+        app.session.transfers.download_of(filename).is_some()
+    } else {
+        chunks.iter().any(|chunk| app.session.transfers.download_of(chunk).is_some())
     }
 }
 
+/// Queues `filename` for download into `target`. A chunked file gets one job per chunk,
+/// each written next to `target`, and the returned assembly says how to join them.
+///
+/// Plain files are checked against the listing's checksum. The listing has none for a
+/// chunked file as a whole, so its chunks are only checked against the checksum header
+/// of their own responses, and the joined file is not verified again.
+fn queue_download(app: &mut App, filename: &str, chunks: &[String], target: PathBuf) -> Assembly {
+    let mut assembly = Assembly {
+        target: target.clone(),
+        parts: vec![],
+        jobs: vec![],
+    };
 
-pub fn delete_file_request(app: &mut App, index: usize) {
-    if index < app.packages.len() {
-        let package_row = &app.packages[index];
-        let filename = &package_row.filename;
+    if chunks.is_empty() {
+        let sha256 = app.session.packages
+            .iter()
+            .find(|row| row.info.name == filename)
+            .and_then(|row| row.info.checksum.clone());
+        let id = app.session.transfers.push_with(Kind::Download, filename.to_string(), target.clone(), 0, |download| {
+            download.sha256 = sha256;
+        });
+        record_transfer(app, id);
+        assembly.parts.push(target);
+        assembly.jobs.push(id);
+        return assembly;
+    }
 
-        let response = app.client
-            .delete(format!("{}/files/{}", app.server.url, filename))
-            .header("Authorization", format!("Bearer {}", app.token))
-            .send();
+    let target_name = target.file_name().unwrap_or_default().to_string_lossy().to_string();
+    for (index, chunk) in chunks.iter().enumerate() {
+        let part = target.with_file_name(chunk_name(&target_name, index, chunks.len()));
+        let id = app.session.transfers.push(Kind::Download, chunk.clone(), part.clone(), 0);
+        record_transfer(app, id);
+        assembly.parts.push(part);
+        assembly.jobs.push(id);
+    }
+    assembly
+}
 
-        match response
-        {
-            Ok(response) => {
-                if response.status().is_success() {
-                    app.packages.remove(index);
-                    println!("File deleted successfully");
-                } else {
-                    eprintln!("Failed to delete file. Status: {}", response.status());
-                }
-            }
-            Err(err) => {
-                eprintln!("Error sending delete request: {}", err);
-            }
+/// Downloads `selected` into a staging folder in `dir_path`, they are zipped once all are done.
+fn queue_bundle(app: &mut App, dir_path: PathBuf, selected: Vec<(String, Vec<String>)>) {
+    let name = match base_name(&app.session.current_dir) {
+        "" => String::from("files.zip"),
+        folder => format!("{}.zip", folder),
+    };
+    let Some(target) = resolve(dir_path.join(&name), app.collision, &HashSet::new()) else {
+        notify(app, format!("{} already exists, skipped", name));
+        return;
+    };
+    let staging = dir_path.join(format!(".{}.parts", target.file_name().unwrap_or_default().to_string_lossy()));
+    if let Err(e) = create_dir_all(&staging) {
+        notify(app, format!("{:?}: {}", staging, e));
+        return;
+    }
+
+    let mut bundle = Bundle {
+        target,
+        staging,
+        entries: vec![],
+        pending: vec![],
+    };
+    for (index, (filename, chunks)) in selected.iter().enumerate() {
+        // Paths inside the archive are relative to the folder being browsed.
+        let Some(entry) = entry_name(plain_name(filename.strip_prefix(&app.session.current_dir).unwrap_or(filename))) else {
+            notify(app, format!("{} has a name that can't go in a zip, left out", filename));
+            continue;
+        };
+        let part = bundle.staging.join(format!("{:04}-{}", index, plain_name(base_name(filename))));
+        let assembly = queue_download(app, filename, chunks, part);
+
+        bundle.pending.extend(&assembly.jobs);
+        bundle.entries.push(ZipEntry {
+            name: entry,
+            parts: assembly.parts,
+            jobs: assembly.jobs,
+        });
+    }
+    if bundle.entries.is_empty() {
+        let _ = remove_dir_all(&bundle.staging);
+        return;
+    }
+    app.session.bundles.push(bundle);
+}
+
+fn resume_transfer(app: &mut App, mut entry: JournalEntry) {
+    if entry.kind == Kind::Upload {
+        let Ok(file_data) = metadata(&entry.local_path) else {
+            eprintln!("File {:?} no longer exists, dropping its upload", entry.local_path);
+            return;
+        };
+
+        let current = fingerprint(&entry.local_path);
+        if current != entry.checksum && parse_chunk_name(&entry.server_name).is_some() {
+            eprintln!("File {:?} changed since it was split, dropping {}", entry.local_path, entry.server_name);
+            return;
+        }
+        if current != entry.checksum {
+            println!("File {:?} changed since the upload started, starting over", entry.local_path);
+            entry.total = file_data.len();
+            entry.checksum = current;
+            entry.upload_id = None;
+            entry.sha256 = None;
         }
     }
-    else {
-        eprintln!("Index out of bounds");
+
+    let id = app.session.transfers.push_with(entry.kind, entry.server_name, entry.local_path, entry.total, |job| {
+        job.resume = true;
+        job.source_offset = entry.source_offset;
+        job.checksum = entry.checksum;
+        job.upload_id = entry.upload_id;
+        job.sha256 = entry.sha256;
+        job.encrypt = entry.encrypt;
+    });
+    record_transfer(app, id);
+}
+
+fn record_transfer(app: &mut App, id: usize) {
+    if let Some(job) = app.session.transfers.get(id) {
+        app.journal.record(JournalEntry::from_transfer(&app.session.server.url, job));
     }
 }
 
 
-pub fn clear_login_field(login_field: &mut LoginField) {
-    login_field.login = String::from("");
+/// Keeps the login name, so after an expired session only the password has to be typed again.
+pub fn clear_password(login_field: &mut LoginField) {
     login_field.password = String::from("");
-}
\ No newline at end of file
+}
@@ -0,0 +1,270 @@
+//! `.ftaignore` files, with the same syntax as `.gitignore`.
+//!
+//! Every `.ftaignore` applies to the folder it is in and everything below it,
+//! rules further down the tree and further down a file win.
+
+use std::fs;
+use std::path::Path;
+
+use crate::pattern::Regex;
+
+pub const IGNORE_FILE: &str = ".ftaignore";
+
+#[derive(Debug, Clone)]
+struct Rule {
+    regex: Regex,
+    negated: bool,
+    dir_only: bool,
+    /// Folder of the `.ftaignore` the rule comes from, relative to the upload root, `""` or ending with `/`.
+    base: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct IgnoreRules {
+    rules: Vec<Rule>,
+}
+
+impl IgnoreRules {
+    /// Adds the rules of `dir/.ftaignore`, if there is one. `base` is `dir` relative to the upload root.
+    pub fn load(&mut self, dir: &Path, base: &str) -> Result<(), String> {
+        let path = dir.join(IGNORE_FILE);
+        let Ok(data) = fs::read_to_string(&path) else {
+            return Ok(());
+        };
+
+        for (number, line) in data.lines().enumerate() {
+            if let Some(rule) = parse_line(line, base).map_err(|e| format!("{:?}:{}: {}", path, number + 1, e))? {
+                self.rules.push(rule);
+            }
+        }
+        Ok(())
+    }
+
+    /// `path` is relative to the upload root and uses `/` separators.
+    pub fn is_ignored(&self, path: &str, is_dir: bool) -> bool {
+        let mut ignored = false;
+
+        for rule in &self.rules {
+            if rule.dir_only && !is_dir {
+                continue;
+            }
+            let Some(relative) = path.strip_prefix(&rule.base) else {
+                continue;
+            };
+            if rule.regex.is_match(relative) {
+                ignored = !rule.negated;
+            }
+        }
+
+        ignored
+    }
+}
+
+fn parse_line(line: &str, base: &str) -> Result<Option<Rule>, String> {
+    // Trailing spaces are ignored unless escaped.
+    let mut line = line.trim_end_matches(' ').to_string();
+    if line.ends_with('\\') {
+        line.push(' ');
+    }
+
+    if line.is_empty() || line.starts_with('#') {
+        return Ok(None);
+    }
+
+    let (negated, mut pattern) = match line.strip_prefix('!') {
+        Some(rest) => (true, rest),
+        None => (false, line.as_str()),
+    };
+    if pattern.starts_with("\\#") || pattern.starts_with("\\!") {
+        pattern = &pattern[1..];
+    }
+
+    let dir_only = pattern.ends_with('/');
+    let pattern = pattern.trim_end_matches('/');
+    if pattern.is_empty() {
+        return Ok(None);
+    }
+
+    // A slash anywhere but at the end ties the pattern to the folder of the ignore file.
+    let anchored = pattern.contains('/');
+    let pattern = pattern.strip_prefix('/').unwrap_or(pattern);
+
+    let mut regex = String::from(if anchored { "^" } else { "^(.*/)?" });
+    regex.push_str(&translate(pattern)?);
+    regex.push('$');
+
+    Ok(Some(Rule {
+        regex: Regex::new(&regex, false)?,
+        negated,
+        dir_only,
+        base: base.to_string(),
+    }))
+}
+
+/// Glob to regex, where `*` and `?` stop at `/` and `**` crosses folders.
+fn translate(pattern: &str) -> Result<String, String> {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut regex = String::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        match chars[i] {
+            '*' if chars.get(i + 1) == Some(&'*') => {
+                let at_start = i == 0 || chars[i - 1] == '/';
+                i += 2;
+                match chars.get(i) {
+                    Some('/') if at_start => {
+                        regex.push_str("(.*/)?");
+                        i += 1;
+                    }
+                    None if at_start => regex.push_str(".*"),
+                    _ => regex.push_str("[^/]*"),
+                }
+                continue;
+            }
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            '[' => {
+                let end = chars[i + 1..]
+                    .iter()
+                    .skip(1)
+                    .position(|c| *c == ']')
+                    .map(|end| i + 2 + end)
+                    .ok_or_else(|| String::from("unterminated '['"))?;
+                regex.push('[');
+                let mut class = &chars[i + 1..end];
+                if let Some(('!' | '^', rest)) = class.split_first() {
+                    regex.push('^');
+                    class = rest;
+                }
+                for c in class {
+                    if *c == '\\' || *c == ']' {
+                        regex.push('\\');
+                    }
+                    regex.push(*c);
+                }
+                regex.push(']');
+                i = end;
+            }
+            '\\' => {
+                i += 1;
+                if let Some(c) = chars.get(i) {
+                    if !c.is_alphanumeric() {
+                        regex.push('\\');
+                    }
+                    regex.push(*c);
+                }
+            }
+            c => {
+                if "\\.+()|{}^$".contains(c) {
+                    regex.push('\\');
+                }
+                regex.push(c);
+            }
+        }
+        i += 1;
+    }
+
+    Ok(regex)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Rules from `(base, line)` pairs, in order.
+    fn rules(lines: &[(&str, &str)]) -> IgnoreRules {
+        IgnoreRules {
+            rules: lines
+                .iter()
+                .filter_map(|(base, line)| parse_line(line, base).unwrap())
+                .collect(),
+        }
+    }
+
+    fn ignored(lines: &[&str], path: &str) -> bool {
+        let lines: Vec<(&str, &str)> = lines.iter().map(|line| ("", *line)).collect();
+        rules(&lines).is_ignored(path, false)
+    }
+
+    #[test]
+    fn names_match_at_any_depth() {
+        assert!(ignored(&["*.log"], "a.log"));
+        assert!(ignored(&["*.log"], "dir/sub/b.log"));
+        assert!(!ignored(&["*.log"], "a.log.txt"));
+        assert!(ignored(&["file?.txt"], "file1.txt"));
+        assert!(!ignored(&["file?.txt"], "file10.txt"));
+        assert!(ignored(&["[ab].txt"], "b.txt"));
+        assert!(!ignored(&["[!ab].txt"], "b.txt"));
+        assert!(ignored(&["a+b(1).txt"], "a+b(1).txt"));
+    }
+
+    #[test]
+    fn slashes_anchor_patterns() {
+        assert!(ignored(&["/root.txt"], "root.txt"));
+        assert!(!ignored(&["/root.txt"], "dir/root.txt"));
+        assert!(ignored(&["doc/*.md"], "doc/a.md"));
+        assert!(!ignored(&["doc/*.md"], "x/doc/a.md"));
+        assert!(!ignored(&["doc/*.md"], "doc/sub/a.md"));
+    }
+
+    #[test]
+    fn double_stars_cross_folders() {
+        assert!(ignored(&["**/cache"], "cache"));
+        assert!(ignored(&["**/cache"], "a/b/cache"));
+        assert!(ignored(&["logs/**"], "logs/a/b.txt"));
+        assert!(!ignored(&["logs/**"], "other/logs/a"));
+        assert!(ignored(&["a/**/b"], "a/b"));
+        assert!(ignored(&["a/**/b"], "a/x/y/b"));
+        assert!(!ignored(&["a/**/b"], "a/x/c"));
+    }
+
+    #[test]
+    fn folders_only_and_negation() {
+        let build = rules(&[("", "build/")]);
+        assert!(build.is_ignored("build", true));
+        assert!(build.is_ignored("src/build", true));
+        assert!(!build.is_ignored("build", false));
+
+        assert!(!ignored(&["*.log", "!keep.log"], "keep.log"));
+        assert!(ignored(&["!keep.log", "*.log"], "keep.log"));
+    }
+
+    #[test]
+    fn rules_apply_below_their_folder() {
+        let nested = rules(&[("", "*.bak"), ("sub/", "*.tmp"), ("sub/", "!x.bak")]);
+        assert!(nested.is_ignored("sub/a/x.tmp", false));
+        assert!(!nested.is_ignored("x.tmp", false));
+        assert!(nested.is_ignored("x.bak", false));
+        assert!(!nested.is_ignored("sub/x.bak", false));
+    }
+
+    #[test]
+    fn comments_escapes_and_spaces() {
+        assert!(parse_line("# comment", "").unwrap().is_none());
+        assert!(parse_line("   ", "").unwrap().is_none());
+        assert!(parse_line("/", "").unwrap().is_none());
+        assert!(ignored(&["\\#notes"], "#notes"));
+        assert!(ignored(&["\\!important"], "!important"));
+        assert!(ignored(&["a.txt   "], "a.txt"));
+        assert!(ignored(&["b\\ "], "b "));
+        assert!(!ignored(&["b\\ "], "b"));
+        assert!(parse_line("[abc", "").is_err());
+    }
+
+    #[test]
+    fn loads_ignore_files() {
+        let dir = std::env::temp_dir().join(format!("fta-ignore-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join(IGNORE_FILE), "# generated\n*.o\n\n!main.o\n").unwrap();
+
+        let mut rules = IgnoreRules::default();
+        rules.load(&dir, "").unwrap();
+        rules.load(&dir.join("missing"), "missing/").unwrap();
+        let _ = fs::remove_dir_all(&dir);
+
+        assert!(rules.is_ignored("lib.o", false));
+        assert!(!rules.is_ignored("main.o", false));
+        assert!(!rules.is_ignored("main.c", false));
+    }
+}
@@ -0,0 +1,148 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+
+use crate::transfer::{Kind, Transfer};
+use crate::utils::data_dir;
+
+const JOURNAL_FILE: &str = "transfers.json";
+
+/// A transfer that has been started but not finished yet.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub kind: Kind,
+    pub server_url: String,
+    pub server_name: String,
+    pub local_path: PathBuf,
+    pub total: u64,
+    pub offset: u64,
+    /// ETag of the remote file for downloads, size/mtime fingerprint of the local file for uploads.
+    pub checksum: Option<String>,
+    pub upload_id: Option<String>,
+    #[serde(default)]
+    pub source_offset: u64,
+    /// Expected SHA-256 of a download.
+    #[serde(default)]
+    pub sha256: Option<String>,
+    /// An upload encrypted on the way.
+    #[serde(default)]
+    pub encrypt: bool,
+}
+
+impl JournalEntry {
+    pub fn from_transfer(server_url: &str, transfer: &Transfer) -> Self {
+        JournalEntry {
+            kind: transfer.kind,
+            server_url: server_url.to_string(),
+            server_name: transfer.file_name.clone(),
+            local_path: transfer.path.clone(),
+            total: transfer.total,
+            offset: transfer.done,
+            checksum: transfer.checksum.clone(),
+            upload_id: transfer.upload_id.clone(),
+            source_offset: transfer.source_offset,
+            sha256: transfer.sha256.clone(),
+            encrypt: transfer.encrypt,
+        }
+    }
+
+    fn same_transfer(&self, other: &JournalEntry) -> bool {
+        self.kind == other.kind
+            && self.server_url == other.server_url
+            && self.server_name == other.server_name
+            && self.local_path == other.local_path
+    }
+
+    pub fn matches(&self, transfer: &Transfer) -> bool {
+        self.kind == transfer.kind
+            && self.server_name == transfer.file_name
+            && self.local_path == transfer.path
+    }
+
+    fn is_pending(&self, server_url: &str, jobs: &[Transfer]) -> bool {
+        self.server_url == server_url && !jobs.iter().any(|transfer| self.matches(transfer))
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Journal {
+    pub entries: Vec<JournalEntry>,
+}
+
+impl Journal {
+    pub fn load() -> Self {
+        let path = journal_path();
+
+        match fs::read_to_string(&path) {
+            Ok(data) => serde_json::from_str(&data).unwrap_or_else(|e| {
+                eprintln!("Transfer journal {:?} is corrupted: {}", path, e);
+                Journal::default()
+            }),
+            Err(_) => Journal::default(),
+        }
+    }
+
+    pub fn save(&self) {
+        let path = journal_path();
+
+        if let Some(dir) = path.parent() {
+            if let Err(e) = fs::create_dir_all(dir) {
+                eprintln!("Failed to create {:?}: {}", dir, e);
+                return;
+            }
+        }
+
+        match serde_json::to_string_pretty(self) {
+            Ok(data) => {
+                if let Err(e) = fs::write(&path, data) {
+                    eprintln!("Failed to save transfer journal: {}", e);
+                }
+            }
+            Err(e) => eprintln!("Failed to serialize transfer journal: {}", e),
+        }
+    }
+
+    pub fn record(&mut self, entry: JournalEntry) {
+        match self.entries.iter_mut().find(|old| old.same_transfer(&entry)) {
+            Some(old) => *old = entry,
+            None => self.entries.push(entry),
+        }
+        self.save();
+    }
+
+    pub fn remove(&mut self, server_url: &str, transfer: &Transfer) {
+        self.entries.retain(|entry| !(entry.server_url == server_url && entry.matches(transfer)));
+        self.save();
+    }
+
+    /// Entries left over from a previous run that nothing in this session is working on.
+    pub fn pending(&self, server_url: &str, jobs: &[Transfer]) -> Vec<&JournalEntry> {
+        self.entries
+            .iter()
+            .filter(|entry| entry.is_pending(server_url, jobs))
+            .collect()
+    }
+
+    pub fn take_pending(&mut self, server_url: &str, jobs: &[Transfer]) -> Vec<JournalEntry> {
+        let (taken, kept) = self.entries
+            .drain(..)
+            .partition(|entry| entry.is_pending(server_url, jobs));
+        self.entries = kept;
+        self.save();
+        taken
+    }
+}
+
+fn journal_path() -> PathBuf {
+    data_dir().join(JOURNAL_FILE)
+}
+
+/// Cheap identity of a local file, used to refuse resuming an upload whose source changed.
+pub fn fingerprint(path: &Path) -> Option<String> {
+    let metadata = fs::metadata(path).ok()?;
+    let modified = metadata.modified().ok()?
+        .duration_since(std::time::UNIX_EPOCH).ok()?
+        .as_secs();
+
+    Some(format!("{}-{}", metadata.len(), modified))
+}
@@ -0,0 +1,205 @@
+//! Reading the session token.
+//!
+//! The claims are always decoded locally. The signature is checked only when a key is
+//! configured: `JWT_PUBLIC_KEY` (PEM file, with `JWT_ALGORITHM`, RS256 by default) or
+//! `JWT_JWKS` (JWKS file, the key picked by the token's `kid`).
+
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::str::FromStr;
+use jsonwebtoken::jwk::{AlgorithmParameters, EllipticCurve, Jwk, JwkSet};
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use serde::{Deserialize, Deserializer};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct Claims {
+    #[serde(default)]
+    pub exp: Option<i64>,
+    #[serde(default)]
+    pub sub: Option<String>,
+    #[serde(default, alias = "role", deserialize_with = "one_or_many")]
+    pub roles: Vec<String>,
+    /// `"permissions": [...]`, or an OAuth style `"scope": "files:read files:write"`.
+    #[serde(default, alias = "scope", alias = "scopes", deserialize_with = "one_or_many")]
+    pub permissions: Vec<String>,
+}
+
+#[derive(Clone)]
+pub enum Verifier {
+    /// Claims are trusted as they are, the server checks the token on every request anyway.
+    None,
+    Key(DecodingKey, Algorithm),
+    Jwks(JwkSet),
+}
+
+impl Verifier {
+    pub fn from_env() -> Result<Self, String> {
+        if let Ok(path) = env::var("JWT_JWKS") {
+            let data = fs::read_to_string(&path).map_err(|e| format!("{}: {}", path, e))?;
+            let jwks: JwkSet = serde_json::from_str(&data).map_err(|e| format!("{}: {}", path, e))?;
+            return Ok(Verifier::Jwks(jwks));
+        }
+
+        if let Ok(path) = env::var("JWT_PUBLIC_KEY") {
+            let algorithm = match env::var("JWT_ALGORITHM") {
+                Ok(name) => Algorithm::from_str(&name).map_err(|_| format!("Unknown JWT_ALGORITHM {}", name))?,
+                Err(_) => Algorithm::RS256,
+            };
+            let pem = fs::read(&path).map_err(|e| format!("{}: {}", path, e))?;
+            let key = match algorithm {
+                Algorithm::RS256 | Algorithm::RS384 | Algorithm::RS512
+                | Algorithm::PS256 | Algorithm::PS384 | Algorithm::PS512 => DecodingKey::from_rsa_pem(&pem),
+                Algorithm::ES256 | Algorithm::ES384 => DecodingKey::from_ec_pem(&pem),
+                Algorithm::EdDSA => DecodingKey::from_ed_pem(&pem),
+                _ => return Err(format!("JWT_ALGORITHM {:?} needs a shared secret, not a public key", algorithm)),
+            }
+                .map_err(|e| format!("{}: {}", path, e))?;
+            return Ok(Verifier::Key(key, algorithm));
+        }
+
+        Ok(Verifier::None)
+    }
+
+    pub fn decode(&self, token: &str) -> Result<Claims, String> {
+        let header = decode_header(token).map_err(|e| format!("Malformed token: {}", e))?;
+
+        let (key, mut validation) = match self {
+            Verifier::None => {
+                let mut validation = Validation::new(header.alg);
+                validation.insecure_disable_signature_validation();
+                (DecodingKey::from_secret(&[]), validation)
+            }
+            Verifier::Key(key, algorithm) => (key.clone(), Validation::new(*algorithm)),
+            Verifier::Jwks(jwks) => {
+                let jwk = match &header.kid {
+                    Some(kid) => jwks.find(kid).ok_or_else(|| format!("No key with id {} in JWT_JWKS", kid))?,
+                    None if jwks.keys.len() == 1 => &jwks.keys[0],
+                    None => return Err(String::from("Token has no key id and JWT_JWKS has several keys")),
+                };
+                let algorithm = key_algorithm(jwk, header.alg)?;
+                if header.alg != algorithm {
+                    return Err(format!("Invalid token: signed with {:?}, its key is for {:?}", header.alg, algorithm));
+                }
+                let key = DecodingKey::from_jwk(jwk).map_err(|e| format!("Unusable key in JWT_JWKS: {}", e))?;
+                (key, Validation::new(algorithm))
+            }
+        };
+        // Only the expiry is checked here, the audience and the rest are the server's business.
+        validation.validate_aud = false;
+        validation.required_spec_claims = HashSet::new();
+
+        decode::<Claims>(token, &key, &validation)
+            .map(|data| data.claims)
+            .map_err(|e| format!("Invalid token: {}", e))
+    }
+}
+
+/// The key's `alg`. Keys without one take the token's algorithm only when it fits the
+/// key type, so the untrusted header can't pick, say, HMAC for an RSA key.
+fn key_algorithm(jwk: &Jwk, token_algorithm: Algorithm) -> Result<Algorithm, String> {
+    if let Some(algorithm) = jwk.common.key_algorithm {
+        return Algorithm::from_str(&algorithm.to_string())
+            .map_err(|_| format!("Key {} in JWT_JWKS is not a signing key", algorithm));
+    }
+
+    let fits = match &jwk.algorithm {
+        AlgorithmParameters::RSA(_) => matches!(
+            token_algorithm,
+            Algorithm::RS256 | Algorithm::RS384 | Algorithm::RS512 | Algorithm::PS256 | Algorithm::PS384 | Algorithm::PS512
+        ),
+        AlgorithmParameters::EllipticCurve(params) => matches!(
+            (&params.curve, token_algorithm),
+            (EllipticCurve::P256, Algorithm::ES256) | (EllipticCurve::P384, Algorithm::ES384)
+        ),
+        AlgorithmParameters::OctetKeyPair(_) => token_algorithm == Algorithm::EdDSA,
+        AlgorithmParameters::OctetKey(_) => matches!(token_algorithm, Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512),
+    };
+    if fits {
+        Ok(token_algorithm)
+    } else {
+        Err(format!("Invalid token: signed with {:?}, which does not fit its key in JWT_JWKS", token_algorithm))
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum OneOrMany {
+    One(String),
+    Many(Vec<String>),
+}
+
+/// `"roles": "admin uploader"`, `"roles": ["admin", "uploader"]` or `"roles": null`.
+fn one_or_many<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    Ok(match Option::<OneOrMany>::deserialize(deserializer)? {
+        Some(OneOrMany::One(roles)) => roles.split_whitespace().map(String::from).collect(),
+        Some(OneOrMany::Many(roles)) => roles,
+        None => vec![],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jsonwebtoken::{encode, EncodingKey, Header};
+    use serde_json::{json, Value};
+
+    fn token(algorithm: Algorithm, secret: &[u8], claims: Value) -> String {
+        encode(&Header::new(algorithm), &claims, &EncodingKey::from_secret(secret)).unwrap()
+    }
+
+    fn jwks(key: Value) -> Verifier {
+        Verifier::Jwks(serde_json::from_value(json!({ "keys": [key] })).unwrap())
+    }
+
+    #[test]
+    fn reads_single_and_listed_roles_and_scopes() {
+        let claims = Verifier::None
+            .decode(&token(Algorithm::HS256, b"any", json!({ "sub": "ann", "role": "admin uploader", "scope": "files:read files:write" })))
+            .unwrap();
+        assert_eq!(claims.sub.as_deref(), Some("ann"));
+        assert_eq!(claims.roles, ["admin", "uploader"]);
+        assert_eq!(claims.permissions, ["files:read", "files:write"]);
+        assert_eq!(claims.exp, None);
+
+        let claims = Verifier::None
+            .decode(&token(Algorithm::HS256, b"any", json!({ "roles": ["viewer"], "permissions": ["download"] })))
+            .unwrap();
+        assert_eq!(claims.roles, ["viewer"]);
+        assert_eq!(claims.permissions, ["download"]);
+    }
+
+    #[test]
+    fn rejects_malformed_and_expired_tokens() {
+        for malformed in ["", "not a token", "a.b.c", "eyJhbGciOiJIUzI1NiJ9.bm90IGpzb24.x"] {
+            assert!(Verifier::None.decode(malformed).is_err(), "{}", malformed);
+        }
+
+        let now = chrono::Utc::now().timestamp();
+        let expired = token(Algorithm::HS256, b"any", json!({ "exp": now - 3600 }));
+        assert!(Verifier::None.decode(&expired).unwrap_err().contains("ExpiredSignature"));
+        let valid = token(Algorithm::HS256, b"any", json!({ "exp": now + 3600 }));
+        assert_eq!(Verifier::None.decode(&valid).unwrap().exp, Some(now + 3600));
+    }
+
+    #[test]
+    fn checks_the_signature_against_the_key() {
+        let verifier = Verifier::Key(DecodingKey::from_secret(b"right"), Algorithm::HS256);
+        assert!(verifier.decode(&token(Algorithm::HS256, b"right", json!({ "sub": "ann" }))).is_ok());
+        assert!(verifier.decode(&token(Algorithm::HS256, b"wrong", json!({ "sub": "ann" }))).is_err());
+        assert!(verifier.decode(&token(Algorithm::HS384, b"right", json!({ "sub": "ann" }))).is_err());
+    }
+
+    #[test]
+    fn takes_the_algorithm_from_the_jwk() {
+        // "secret", base64url encoded.
+        let signed = token(Algorithm::HS256, b"secret", json!({ "sub": "ann" }));
+        assert!(jwks(json!({ "kty": "oct", "k": "c2VjcmV0", "alg": "HS256" })).decode(&signed).is_ok());
+        assert!(jwks(json!({ "kty": "oct", "k": "c2VjcmV0" })).decode(&signed).is_ok());
+        assert!(jwks(json!({ "kty": "oct", "k": "c2VjcmV0", "alg": "HS384" })).decode(&signed).is_err());
+
+        // An HMAC token must not be checked against an RSA key's public bytes.
+        assert!(jwks(json!({ "kty": "RSA", "n": "AQAB", "e": "AQAB" })).decode(&signed).is_err());
+        assert!(jwks(json!({ "kty": "RSA", "n": "AQAB", "e": "AQAB", "alg": "RS256" })).decode(&signed).is_err());
+    }
+}
@@ -0,0 +1,30 @@
+//! Everything that doesn't need a window: server requests, transfers, file listings,
+//! folders, tokens and the files kept between runs. The desktop client and `fta-cli`
+//! are both built on top of it.
+
+pub mod api;
+pub mod remote;
+pub mod sync;
+pub mod utils;
+pub mod transfer;
+pub mod journal;
+pub mod queue;
+pub mod chunks;
+pub mod checksum;
+pub mod file_info;
+pub mod pattern;
+pub mod query;
+pub mod folders;
+pub mod ignore;
+pub mod folder_upload;
+pub mod collision;
+pub mod zip;
+pub mod crypto;
+pub mod encryption;
+pub mod cache;
+pub mod preview;
+pub mod thumbnail;
+pub mod credentials;
+pub mod jwt;
+pub mod permissions;
+pub mod profiles;
@@ -1,23 +1,37 @@
 mod app;
-mod utils;
 mod handlers;
 mod ui;
 mod styles;
+mod icon;
+mod subscriptions;
+mod columns;
+mod settings;
 
-use iced::{window, Sandbox, Size};
+use fta::{api, remote, sync, utils, transfer, journal, queue, cache, chunks, checksum, file_info, query, folders, folder_upload, collision, zip, credentials, encryption, jwt, permissions, preview, profiles, thumbnail};
+
+use iced::{window, Application, Point, Size};
 use iced::{Settings};
 
 
 use crate::app::App;
-use crate::utils::load_icon;
+use crate::icon::load_icon;
 
 fn main() -> iced::Result {
+    let user_settings = settings::Settings::load();
+    let geometry = user_settings.window;
 
-    let mut settings = Settings::default();
+    let mut settings = Settings::with_flags(user_settings);
 
     let window_settings = window::Settings {
+        size: Size::new(geometry.width, geometry.height),
+        position: match (geometry.x, geometry.y) {
+            (Some(x), Some(y)) => window::Position::Specific(Point::new(x as f32, y as f32)),
+            _ => window::Position::default(),
+        },
         min_size: Some(Size::new(700.0, 600.0)),
         icon: Some(load_icon("src/resources/icon.ico")),
+        // The window geometry is saved before closing, see `Message::WindowCloseRequested`.
+        exit_on_close_request: false,
         ..window::Settings::default()
     };
 
@@ -25,8 +39,3 @@ fn main() -> iced::Result {
     settings.window = window_settings;
     App::run(settings)
 }
-
-
-
-
-
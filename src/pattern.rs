@@ -0,0 +1,586 @@
+//! A small regular expression matcher for file name searches.
+//!
+//! Supports literals, `.`, `[...]` / `[^...]` classes with ranges, the `\d \w \s` escapes
+//! (and their negations), `^` / `$` anchors, groups with `|` alternation and the
+//! `* + ? {n} {n,} {n,m}` greedy quantifiers.
+//!
+//! Patterns are compiled to a Thompson NFA that is run over all its states at once, so
+//! matching takes time linear in the name whatever the pattern. Searches are matched on
+//! every redraw, a backtracking matcher would let one pattern freeze the window.
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Char(char),
+    Any,
+    Class { items: Vec<ClassItem>, negated: bool },
+    Start,
+    End,
+    Concat(Vec<Node>),
+    Alt(Vec<Node>),
+    Repeat { node: Box<Node>, min: usize, max: Option<usize> },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum ClassItem {
+    Range(char, char),
+    Digit(bool),
+    Word(bool),
+    Space(bool),
+}
+
+impl ClassItem {
+    fn matches(&self, c: char) -> bool {
+        match self {
+            ClassItem::Range(from, to) => *from <= c && c <= *to,
+            ClassItem::Digit(negated) => c.is_ascii_digit() != *negated,
+            ClassItem::Word(negated) => (c.is_alphanumeric() || c == '_') != *negated,
+            ClassItem::Space(negated) => c.is_whitespace() != *negated,
+        }
+    }
+}
+
+/// Instructions of the compiled NFA.
+#[derive(Debug, Clone, PartialEq)]
+enum Inst {
+    Char(char),
+    Any,
+    Class { items: Vec<ClassItem>, negated: bool },
+    Start,
+    End,
+    /// Continue at both.
+    Split(usize, usize),
+    Jump(usize),
+    Match,
+}
+
+/// Instructions a pattern may compile to, `{n,m}` copies its operand so it can grow fast.
+const MAX_PROGRAM: usize = 10_000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Regex {
+    program: Vec<Inst>,
+    case_insensitive: bool,
+}
+
+impl Regex {
+    pub fn new(pattern: &str, case_insensitive: bool) -> Result<Self, String> {
+        let mut parser = Parser {
+            chars: pattern.chars().collect(),
+            pos: 0,
+            case_insensitive,
+        };
+
+        let root = parser.alternation()?;
+        if parser.pos < parser.chars.len() {
+            return Err(format!("unmatched ')' at position {}", parser.pos + 1));
+        }
+
+        let mut program = vec![];
+        compile(&root, &mut program)?;
+        program.push(Inst::Match);
+        Ok(Regex { program, case_insensitive })
+    }
+
+    /// Shell style pattern matched against the whole name: `*`, `?` and `[...]`, ignoring case.
+    pub fn glob(pattern: &str) -> Result<Self, String> {
+        let mut regex = String::from("^");
+        let mut in_class = false;
+
+        for c in pattern.chars() {
+            match c {
+                '*' if !in_class => regex.push_str(".*"),
+                '?' if !in_class => regex.push('.'),
+                '[' if !in_class => {
+                    in_class = true;
+                    regex.push('[');
+                }
+                ']' if in_class => {
+                    in_class = false;
+                    regex.push(']');
+                }
+                '!' if in_class && regex.ends_with('[') => regex.push('^'),
+                c if in_class => regex.push(c),
+                c => {
+                    if "\\.+()|{}^$".contains(c) {
+                        regex.push('\\');
+                    }
+                    regex.push(c);
+                }
+            }
+        }
+        if in_class {
+            return Err(String::from("unterminated '[' in pattern"));
+        }
+        regex.push('$');
+
+        Regex::new(&regex, true)
+    }
+
+    /// Whether the pattern matches anywhere in `text`.
+    pub fn is_match(&self, text: &str) -> bool {
+        let input: Vec<char> = if self.case_insensitive {
+            text.to_lowercase().chars().collect()
+        } else {
+            text.chars().collect()
+        };
+
+        let mut current = Threads::new(self.program.len());
+        let mut next = Threads::new(self.program.len());
+        for pos in 0..=input.len() {
+            // A new attempt starts at every position, that's what makes the search unanchored.
+            if self.add(&mut current, 0, pos, input.len()) {
+                return true;
+            }
+            let Some(&c) = input.get(pos) else {
+                break;
+            };
+
+            next.clear();
+            for index in 0..current.list.len() {
+                let pc = current.list[index];
+                let step = match &self.program[pc] {
+                    Inst::Char(expected) => *expected == c,
+                    Inst::Any => true,
+                    Inst::Class { items, negated } => items.iter().any(|item| self.class_matches(item, c)) != *negated,
+                    _ => false,
+                };
+                if step && self.add(&mut next, pc + 1, pos + 1, input.len()) {
+                    return true;
+                }
+            }
+            std::mem::swap(&mut current, &mut next);
+        }
+        false
+    }
+
+    /// Adds the thread at `pc` and everything it reaches without reading, returns true
+    /// when that includes a match.
+    fn add(&self, threads: &mut Threads, pc: usize, pos: usize, len: usize) -> bool {
+        let mut stack = vec![pc];
+        while let Some(pc) = stack.pop() {
+            if !threads.insert(pc) {
+                continue;
+            }
+            match self.program[pc] {
+                Inst::Match => return true,
+                Inst::Jump(to) => stack.push(to),
+                Inst::Split(first, second) => stack.extend([second, first]),
+                Inst::Start if pos == 0 => stack.push(pc + 1),
+                Inst::End if pos == len => stack.push(pc + 1),
+                Inst::Start | Inst::End => {}
+                _ => threads.list.push(pc),
+            }
+        }
+        false
+    }
+
+    fn class_matches(&self, item: &ClassItem, c: char) -> bool {
+        if item.matches(c) {
+            return true;
+        }
+        // The input is already lowercased, give uppercase ranges a chance too.
+        self.case_insensitive && c.to_uppercase().any(|upper| item.matches(upper))
+    }
+}
+
+/// The NFA states reached at one position, each only once.
+struct Threads {
+    seen: Vec<bool>,
+    /// States that read the next character.
+    list: Vec<usize>,
+    /// Every state marked in `seen`, to reset it without going over all of them.
+    marked: Vec<usize>,
+}
+
+impl Threads {
+    fn new(size: usize) -> Self {
+        Threads {
+            seen: vec![false; size],
+            list: vec![],
+            marked: vec![],
+        }
+    }
+
+    fn insert(&mut self, pc: usize) -> bool {
+        if self.seen[pc] {
+            return false;
+        }
+        self.seen[pc] = true;
+        self.marked.push(pc);
+        true
+    }
+
+    fn clear(&mut self) {
+        for pc in self.marked.drain(..) {
+            self.seen[pc] = false;
+        }
+        self.list.clear();
+    }
+}
+
+fn compile(node: &Node, program: &mut Vec<Inst>) -> Result<(), String> {
+    if program.len() > MAX_PROGRAM {
+        return Err(String::from("pattern is too large"));
+    }
+
+    match node {
+        Node::Char(c) => program.push(Inst::Char(*c)),
+        Node::Any => program.push(Inst::Any),
+        Node::Class { items, negated } => program.push(Inst::Class { items: items.clone(), negated: *negated }),
+        Node::Start => program.push(Inst::Start),
+        Node::End => program.push(Inst::End),
+        Node::Concat(nodes) => {
+            for node in nodes {
+                compile(node, program)?;
+            }
+        }
+        Node::Alt(branches) => {
+            // split L1, next; L1: branch; jump end; next: split L2, ...
+            let mut jumps = vec![];
+            for (index, branch) in branches.iter().enumerate() {
+                if index + 1 < branches.len() {
+                    let split = program.len();
+                    program.push(Inst::Split(split + 1, 0));
+                    compile(branch, program)?;
+                    jumps.push(program.len());
+                    program.push(Inst::Jump(0));
+                    program[split] = Inst::Split(split + 1, program.len());
+                } else {
+                    compile(branch, program)?;
+                }
+            }
+            let end = program.len();
+            for jump in jumps {
+                program[jump] = Inst::Jump(end);
+            }
+        }
+        Node::Repeat { node, min, max } => {
+            let start = program.len();
+            for _ in 0..*min {
+                compile(node, program)?;
+                // Copies of an empty group add nothing, don't make `(){1000000000}` count them.
+                if program.len() == start {
+                    break;
+                }
+            }
+            match max {
+                None => {
+                    // loop: split body, end; body; jump loop
+                    let split = program.len();
+                    program.push(Inst::Split(split + 1, 0));
+                    compile(node, program)?;
+                    program.push(Inst::Jump(split));
+                    program[split] = Inst::Split(split + 1, program.len());
+                }
+                Some(max) => {
+                    let mut splits = vec![];
+                    for _ in *min..*max {
+                        splits.push(program.len());
+                        program.push(Inst::Split(0, 0));
+                        compile(node, program)?;
+                    }
+                    let end = program.len();
+                    for split in splits {
+                        program[split] = Inst::Split(split + 1, end);
+                    }
+                }
+            }
+        }
+    }
+
+    if program.len() > MAX_PROGRAM {
+        return Err(String::from("pattern is too large"));
+    }
+    Ok(())
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    case_insensitive: bool,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek();
+        self.pos += 1;
+        c
+    }
+
+    fn literal(&self, c: char) -> Node {
+        if self.case_insensitive {
+            Node::Char(c.to_lowercase().next().unwrap_or(c))
+        } else {
+            Node::Char(c)
+        }
+    }
+
+    fn alternation(&mut self) -> Result<Node, String> {
+        let mut branches = vec![self.concatenation()?];
+        while self.peek() == Some('|') {
+            self.pos += 1;
+            branches.push(self.concatenation()?);
+        }
+
+        Ok(if branches.len() == 1 { branches.remove(0) } else { Node::Alt(branches) })
+    }
+
+    fn concatenation(&mut self) -> Result<Node, String> {
+        let mut nodes = vec![];
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            nodes.push(self.repetition()?);
+        }
+        Ok(Node::Concat(nodes))
+    }
+
+    fn repetition(&mut self) -> Result<Node, String> {
+        let mut node = self.atom()?;
+
+        loop {
+            let (min, max) = match self.peek() {
+                Some('*') => (0, None),
+                Some('+') => (1, None),
+                Some('?') => (0, Some(1)),
+                Some('{') => {
+                    self.pos += 1;
+                    let bounds = self.braces()?;
+                    self.pos -= 1;
+                    bounds
+                }
+                _ => return Ok(node),
+            };
+            self.pos += 1;
+            // Lazy quantifiers find the same matches, only the match length differs.
+            if self.peek() == Some('?') {
+                self.pos += 1;
+            }
+            if matches!(node, Node::Start | Node::End) {
+                return Err(String::from("nothing to repeat"));
+            }
+            node = Node::Repeat { node: Box::new(node), min, max };
+        }
+    }
+
+    /// Parses `{n}`, `{n,}` or `{n,m}` up to and including the closing brace,
+    /// the opening brace already consumed.
+    fn braces(&mut self) -> Result<(usize, Option<usize>), String> {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c != '}') {
+            self.pos += 1;
+        }
+        let inside: String = self.chars[start..self.pos].iter().collect();
+        if self.bump() != Some('}') {
+            return Err(String::from("unterminated '{'"));
+        }
+
+        let invalid = || format!("invalid repetition '{{{}}}'", inside);
+        let bounds = match inside.split_once(',') {
+            None => {
+                let n = inside.trim().parse().map_err(|_| invalid())?;
+                (n, Some(n))
+            }
+            Some((min, "")) => (min.trim().parse().map_err(|_| invalid())?, None),
+            Some((min, max)) => {
+                let min = min.trim().parse().map_err(|_| invalid())?;
+                let max = max.trim().parse().map_err(|_| invalid())?;
+                if max < min {
+                    return Err(invalid());
+                }
+                (min, Some(max))
+            }
+        };
+        Ok(bounds)
+    }
+
+    fn atom(&mut self) -> Result<Node, String> {
+        let position = self.pos + 1;
+
+        match self.bump() {
+            None => Err(String::from("unexpected end of pattern")),
+            Some('(') => {
+                if self.chars[self.pos..].starts_with(&['?', ':']) {
+                    self.pos += 2;
+                }
+                let inner = self.alternation()?;
+                if self.bump() != Some(')') {
+                    return Err(format!("unclosed '(' at position {}", position));
+                }
+                Ok(inner)
+            }
+            Some('[') => self.class(position),
+            Some('.') => Ok(Node::Any),
+            Some('^') => Ok(Node::Start),
+            Some('$') => Ok(Node::End),
+            Some('\\') => match self.escape()? {
+                Escape::Char(c) => Ok(self.literal(c)),
+                Escape::Class(item) => Ok(Node::Class { items: vec![item], negated: false }),
+            },
+            Some(c) if "*+?{".contains(c) => Err(format!("nothing to repeat at position {}", position)),
+            Some(c) => Ok(self.literal(c)),
+        }
+    }
+
+    fn class(&mut self, position: usize) -> Result<Node, String> {
+        let negated = self.peek() == Some('^');
+        if negated {
+            self.pos += 1;
+        }
+
+        let mut items = vec![];
+        let mut first = true;
+        loop {
+            let c = match self.bump() {
+                None => return Err(format!("unterminated '[' at position {}", position)),
+                Some(']') if !first => break,
+                Some('\\') => match self.escape()? {
+                    Escape::Char(c) => c,
+                    Escape::Class(item) => {
+                        items.push(item);
+                        first = false;
+                        continue;
+                    }
+                },
+                Some(c) => c,
+            };
+            first = false;
+
+            if self.peek() == Some('-') && self.chars.get(self.pos + 1).is_some_and(|c| *c != ']') {
+                self.pos += 1;
+                let to = match self.bump() {
+                    Some('\\') => match self.escape()? {
+                        Escape::Char(c) => c,
+                        Escape::Class(_) => return Err(String::from("invalid range in class")),
+                    },
+                    Some(to) => to,
+                    None => return Err(format!("unterminated '[' at position {}", position)),
+                };
+                if to < c {
+                    return Err(format!("invalid range '{}-{}'", c, to));
+                }
+                items.push(ClassItem::Range(c, to));
+            } else {
+                items.push(ClassItem::Range(c, c));
+            }
+        }
+
+        Ok(Node::Class { items, negated })
+    }
+
+    fn escape(&mut self) -> Result<Escape, String> {
+        match self.bump() {
+            None => Err(String::from("trailing '\\'")),
+            Some('d') => Ok(Escape::Class(ClassItem::Digit(false))),
+            Some('D') => Ok(Escape::Class(ClassItem::Digit(true))),
+            Some('w') => Ok(Escape::Class(ClassItem::Word(false))),
+            Some('W') => Ok(Escape::Class(ClassItem::Word(true))),
+            Some('s') => Ok(Escape::Class(ClassItem::Space(false))),
+            Some('S') => Ok(Escape::Class(ClassItem::Space(true))),
+            Some('t') => Ok(Escape::Char('\t')),
+            Some('n') => Ok(Escape::Char('\n')),
+            Some(c) => Ok(Escape::Char(c)),
+        }
+    }
+}
+
+enum Escape {
+    Char(char),
+    Class(ClassItem),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    fn matches(pattern: &str, text: &str) -> bool {
+        Regex::new(pattern, false).unwrap().is_match(text)
+    }
+
+    #[test]
+    fn literals_and_classes() {
+        assert!(matches("port", "report.pdf"));
+        assert!(!matches("port", "rep.ort"));
+        assert!(matches(r"\d{4}-\d\d", "log 2024-05.txt"));
+        assert!(!matches(r"\d{4}-\d\d", "log 202-05.txt"));
+        assert!(matches("[a-c]x[^0-9]", "bxy"));
+        assert!(!matches("[a-c]x[^0-9]", "bx1"));
+        assert!(matches(r"\w+\s\W", "a b !"));
+        assert!(matches("a.c", "abc"));
+        assert!(!matches("a.c", "ac"));
+    }
+
+    #[test]
+    fn anchors() {
+        assert!(matches("^rep", "report"));
+        assert!(!matches("^port", "report"));
+        assert!(matches(r"\.pdf$", "report.pdf"));
+        assert!(!matches(r"\.pdf$", "report.pdf.bak"));
+        assert!(matches("^$", ""));
+        assert!(!matches("^$", "a"));
+    }
+
+    #[test]
+    fn alternation_and_repetition() {
+        assert!(matches("^(jpe?g|png)$", "jpg"));
+        assert!(matches("^(jpe?g|png)$", "png"));
+        assert!(!matches("^(jpe?g|png)$", "gif"));
+        assert!(matches("^a{2,3}$", "aaa"));
+        assert!(!matches("^a{2,3}$", "aaaa"));
+        assert!(matches("^a{2,}$", "aaaaa"));
+        assert!(!matches("^a{2}$", "a"));
+        assert!(matches("^(?:ab)+c*?$", "ababcc"));
+        assert!(matches("^(a*)*$", "aaa"));
+    }
+
+    #[test]
+    fn case_insensitive() {
+        let regex = Regex::new("^Report[A-Z]$", true).unwrap();
+        assert!(regex.is_match("REPORTx"));
+        assert!(regex.is_match("reportX"));
+        assert!(!Regex::new("^Report$", false).unwrap().is_match("report"));
+    }
+
+    #[test]
+    fn errors() {
+        assert!(Regex::new("(ab", false).is_err());
+        assert!(Regex::new("ab)", false).is_err());
+        assert!(Regex::new("[ab", false).is_err());
+        assert!(Regex::new("*a", false).is_err());
+        assert!(Regex::new("^*", false).is_err());
+        assert!(Regex::new("a{3,1}", false).is_err());
+        assert!(Regex::new("a{1000}{1000}", false).is_err());
+        assert!(Regex::new("(){1000000000}", false).is_ok());
+    }
+
+    #[test]
+    fn globs() {
+        let glob = |pattern: &str, text: &str| Regex::glob(pattern).unwrap().is_match(text);
+        assert!(glob("*.PDF", "report.pdf"));
+        assert!(!glob("*.pdf", "report.pdf.bak"));
+        assert!(glob("rep?rt.*", "Report.txt"));
+        assert!(!glob("rep?rt.*", "reprt.txt"));
+        assert!(glob("file[0-9].txt", "file7.txt"));
+        assert!(!glob("file[!0-9].txt", "file7.txt"));
+        assert!(glob("a+b(c).txt", "a+b(c).txt"));
+        assert!(!glob("a.txt", "abtxt"));
+        assert!(Regex::glob("file[0-9").is_err());
+    }
+
+    #[test]
+    fn pathological_patterns_are_fast() {
+        let start = Instant::now();
+        assert!(!matches("(a+)+$", &format!("{}b", "a".repeat(24))));
+        assert!(!matches("^(a+)+$", &format!("{}b", "a".repeat(1000))));
+        assert!(!matches("^(a|a)*$", &format!("{}b", "a".repeat(1000))));
+        assert!(!Regex::glob("*a*a*a*a*a*a*b").unwrap().is_match(&"a".repeat(1000)));
+        assert!(Regex::glob("*a*a*a*a*a*a*b").unwrap().is_match(&format!("{}b", "a".repeat(1000))));
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
+}
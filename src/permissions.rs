@@ -0,0 +1,69 @@
+use reqwest::StatusCode;
+
+use crate::jwt::Claims;
+
+/// Actions the UI offers. Claims only decide what is shown, the server has the final say.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permission {
+    Upload,
+    Download,
+    Delete,
+    ManageFolders,
+}
+
+pub const PERMISSION_DENIED: &str = "You do not have permission";
+pub const SESSION_EXPIRED: &str = "The session has expired, log in again";
+
+impl Permission {
+    /// Names accepted in the `permissions` / `scope` claim.
+    fn names(self) -> &'static [&'static str] {
+        match self {
+            Permission::Upload => &["upload", "files:upload", "files:write"],
+            Permission::Download => &["download", "files:download", "files:read"],
+            Permission::Delete => &["delete", "files:delete", "files:write"],
+            Permission::ManageFolders => &["folders", "folders:write", "files:write"],
+        }
+    }
+
+    /// `None` for roles this client doesn't know.
+    fn granted_by_role(self, role: &str) -> Option<bool> {
+        match role {
+            "admin" | "owner" | "editor" => Some(true),
+            "uploader" => Some(matches!(self, Permission::Upload | Permission::Download)),
+            "viewer" | "reader" | "guest" => Some(self == Permission::Download),
+            _ => None,
+        }
+    }
+}
+
+impl Claims {
+    /// A token that says nothing this client understands allows everything,
+    /// as it did before claims were looked at.
+    pub fn can(&self, permission: Permission) -> bool {
+        let roles: Vec<Option<bool>> = self.roles
+            .iter()
+            .map(|role| permission.granted_by_role(&role.to_lowercase()))
+            .collect();
+        if self.permissions.is_empty() && roles.iter().all(Option::is_none) {
+            return true;
+        }
+
+        roles.contains(&Some(true))
+            || self.permissions
+                .iter()
+                .any(|granted| permission.names().contains(&granted.to_lowercase().as_str()))
+    }
+}
+
+/// `action` reads like "delete files".
+pub fn denied(action: &str) -> String {
+    format!("{} to {}", PERMISSION_DENIED, action)
+}
+
+pub fn status_error(action: &str, status: StatusCode) -> String {
+    match status {
+        StatusCode::FORBIDDEN => denied(action),
+        StatusCode::UNAUTHORIZED => String::from(SESSION_EXPIRED),
+        _ => format!("Failed to {}. Status: {}", action, status),
+    }
+}
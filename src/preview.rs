@@ -0,0 +1,645 @@
+//! What the preview pane shows of a stored file, made from its first bytes or, for images
+//! and PDFs, the whole file up to [`WHOLE_LIMIT`].
+//!
+//! The bytes are kept as fetched in the [`PREVIEW_CACHE`], so encrypted files stay encrypted
+//! on disk and are only decrypted for showing. Highlighting and markdown are line based and
+//! only meant to make a file recognisable, not to be exact.
+
+use std::path::Path;
+
+use crate::cache::{self, cache_dir, cache_key, PREVIEW_CACHE};
+use crate::chunks::ListedFile;
+use crate::encryption::{encrypted_size, has_header, plain_name, Decryptor, NO_KEY};
+use crate::remote::Remote;
+
+/// Bytes fetched of files shown as text or hex.
+pub const HEAD_LIMIT: u64 = 256 * 1024;
+/// Images and PDFs are only previewed when they are at most this big.
+pub const WHOLE_LIMIT: u64 = 16 * 1024 * 1024;
+const TEXT_LINES: usize = 1000;
+const HEX_BYTES: usize = 4096;
+const PDF_THUMBNAILS: usize = 12;
+
+#[derive(Debug, Clone)]
+pub struct Preview {
+    pub content: Content,
+    /// Only the start of the file is shown.
+    pub truncated: bool,
+}
+
+#[derive(Debug, Clone)]
+pub enum Content {
+    /// The encoded image, decoded by the window.
+    Image(Vec<u8>),
+    Pdf(PdfInfo),
+    Markdown(Vec<Block>),
+    /// Highlighted lines.
+    Text(Vec<Vec<Span>>),
+    /// Lines of 16 bytes with their offset and printable characters.
+    Hex(Vec<String>),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PdfInfo {
+    pub version: String,
+    /// Counted from the page objects, unknown when they are compressed.
+    pub pages: Option<usize>,
+    pub title: Option<String>,
+    /// JPEG images in the file, which for scanned documents are the pages themselves.
+    /// Drawing any other page would need a PDF renderer.
+    pub thumbnails: Vec<Vec<u8>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Block {
+    Heading(u8, String),
+    Paragraph(String),
+    /// A list item, its nesting depth and text with the number or bullet.
+    Item(usize, String),
+    Quote(String),
+    Code(Vec<Vec<Span>>),
+    Rule,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Span {
+    pub text: String,
+    pub token: Token,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Token {
+    Plain,
+    Keyword,
+    String,
+    Comment,
+    Number,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Image,
+    Pdf,
+    Markdown,
+    Other,
+}
+
+fn kind_of(name: &str, mime: &str) -> Kind {
+    let extension = extension(name);
+    if extension == "md" || extension == "markdown" {
+        Kind::Markdown
+    } else if mime == "application/pdf" || extension == "pdf" {
+        Kind::Pdf
+    } else if mime.starts_with("image/") && !mime.starts_with("image/svg") {
+        Kind::Image
+    } else {
+        Kind::Other
+    }
+}
+
+fn extension(name: &str) -> String {
+    Path::new(name)
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+}
+
+/// Fetches what is needed of `file`, or takes it from the cache, and makes its preview.
+/// Chunked files are previewed from their first chunk.
+pub async fn load(remote: Remote, file: ListedFile) -> Result<Preview, String> {
+    let name = plain_name(&file.info.name).to_string();
+    let kind = kind_of(&name, &file.info.mime());
+    let too_big = file.info.size.is_some_and(|size| size > encrypted_size(WHOLE_LIMIT));
+    let limit = match kind {
+        Kind::Image | Kind::Pdf if !too_big => WHOLE_LIMIT,
+        _ => HEAD_LIMIT,
+    };
+
+    let fetched = fetch(&remote, &file, limit).await?;
+    let (bytes, truncated) = (fetched.bytes, !fetched.complete);
+
+    tokio::task::spawn_blocking(move || make_preview(&name, kind, bytes, truncated))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// The start of a file, see [`fetch`].
+pub struct Fetched {
+    /// Decrypted if the file is stored encrypted.
+    pub bytes: Vec<u8>,
+    /// `bytes` are the whole file.
+    pub complete: bool,
+    pub encrypted: bool,
+}
+
+/// Up to `limit` bytes from the start of `file`, decrypted if its header says so.
+/// The bytes come from the [`PREVIEW_CACHE`] when they were fetched before.
+pub async fn fetch(remote: &Remote, file: &ListedFile, limit: u64) -> Result<Fetched, String> {
+    let server_name = file.server_names().remove(0);
+    // One byte more than is shown tells whether there is more. Whether the file is
+    // encrypted is only known once it is here, so with a key ask for enough either way.
+    let wanted = if remote.key.is_some() { encrypted_size(limit) + 1 } else { limit + 1 };
+
+    let dir = cache_dir(PREVIEW_CACHE);
+    let cache_key = cache_key(&remote.server_url, &file.info);
+    let head_key = format!("{}.head", cache_key);
+    let (bytes, complete) = match (cache::read(&dir, &cache_key), cache::read(&dir, &head_key)) {
+        (Some(bytes), _) => (bytes, true),
+        (None, Some(bytes)) if bytes.len() as u64 >= wanted => (bytes, false),
+        _ => {
+            let (bytes, complete) = remote.head(&server_name, wanted).await?;
+            let stored = cache::write(&dir, if complete { &cache_key } else { &head_key }, &bytes);
+            if let Err(e) = stored {
+                eprintln!("Failed to cache the preview of {}: {}", file.info.name, e);
+            }
+            (bytes, complete)
+        }
+    };
+
+    let encrypted = has_header(&bytes);
+    let mut bytes = match remote.key {
+        Some(key) if encrypted => {
+            let mut decryptor = Decryptor::new(key);
+            let mut plaintext = decryptor.update(&bytes)?;
+            if complete {
+                plaintext.extend(decryptor.finish()?);
+            }
+            plaintext
+        }
+        None if encrypted => return Err(String::from(NO_KEY)),
+        _ => bytes,
+    };
+    let complete = complete && bytes.len() as u64 <= limit && file.chunks.len() <= 1;
+    bytes.truncate(limit as usize);
+    Ok(Fetched { bytes, complete, encrypted })
+}
+
+fn make_preview(name: &str, kind: Kind, bytes: Vec<u8>, truncated: bool) -> Preview {
+    if kind == Kind::Image && !truncated && image::guess_format(&bytes).is_ok() {
+        return Preview { content: Content::Image(bytes), truncated };
+    }
+    if kind == Kind::Pdf && bytes.starts_with(b"%PDF-") {
+        return Preview { content: Content::Pdf(parse_pdf(&bytes)), truncated };
+    }
+
+    match as_text(&bytes) {
+        Some(text) if kind == Kind::Markdown => Preview {
+            content: Content::Markdown(parse_markdown(text)),
+            truncated,
+        },
+        Some(text) => {
+            let lines = highlight(text, syntax_for(&extension(name)));
+            Preview {
+                truncated: truncated || lines.len() > TEXT_LINES,
+                content: Content::Text(lines.into_iter().take(TEXT_LINES).collect()),
+            }
+        }
+        None => Preview {
+            content: Content::Hex(hex_dump(&bytes[..bytes.len().min(HEX_BYTES)])),
+            truncated: truncated || bytes.len() > HEX_BYTES,
+        },
+    }
+}
+
+/// `bytes` as UTF-8 without NULs, allowing a character cut off at the end.
+fn as_text(bytes: &[u8]) -> Option<&str> {
+    if bytes.contains(&0) {
+        return None;
+    }
+    let text = match std::str::from_utf8(bytes) {
+        Ok(text) => text,
+        Err(e) if e.error_len().is_none() => std::str::from_utf8(&bytes[..e.valid_up_to()]).ok()?,
+        Err(_) => return None,
+    };
+    Some(text.strip_prefix('\u{feff}').unwrap_or(text))
+}
+
+fn hex_dump(bytes: &[u8]) -> Vec<String> {
+    bytes
+        .chunks(16)
+        .enumerate()
+        .map(|(row, chunk)| {
+            let hex: Vec<String> = chunk.iter().map(|byte| format!("{:02x}", byte)).collect();
+            let printable: String = chunk
+                .iter()
+                .map(|&byte| if byte.is_ascii_graphic() || byte == b' ' { byte as char } else { '.' })
+                .collect();
+            format!("{:08x}  {:<47}  {}", row * 16, hex.join(" "), printable)
+        })
+        .collect()
+}
+
+/// Enough of a language to tell keywords, strings and comments apart.
+struct Syntax {
+    keywords: &'static [&'static str],
+    line_comments: &'static [&'static str],
+    block_comment: Option<(&'static str, &'static str)>,
+    ignore_case: bool,
+}
+
+const RUST: Syntax = Syntax {
+    keywords: &[
+        "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern", "false",
+        "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref", "return",
+        "self", "Self", "static", "struct", "super", "trait", "true", "type", "unsafe", "use", "where", "while",
+    ],
+    line_comments: &["//"],
+    block_comment: Some(("/*", "*/")),
+    ignore_case: false,
+};
+
+const PYTHON: Syntax = Syntax {
+    keywords: &[
+        "and", "as", "assert", "async", "await", "break", "class", "continue", "def", "del", "elif", "else",
+        "except", "False", "finally", "for", "from", "global", "if", "import", "in", "is", "lambda", "None",
+        "nonlocal", "not", "or", "pass", "raise", "return", "self", "True", "try", "while", "with", "yield",
+    ],
+    line_comments: &["#"],
+    block_comment: None,
+    ignore_case: false,
+};
+
+const JAVASCRIPT: Syntax = Syntax {
+    keywords: &[
+        "async", "await", "break", "case", "catch", "class", "const", "continue", "default", "delete", "do",
+        "else", "enum", "export", "extends", "false", "finally", "for", "from", "function", "if", "import", "in",
+        "instanceof", "interface", "let", "new", "null", "return", "super", "switch", "this", "throw", "true",
+        "try", "type", "typeof", "undefined", "var", "void", "while", "yield",
+    ],
+    line_comments: &["//"],
+    block_comment: Some(("/*", "*/")),
+    ignore_case: false,
+};
+
+/// C and the languages that look like it closely enough.
+const C_LIKE: Syntax = Syntax {
+    keywords: &[
+        "auto", "bool", "break", "case", "catch", "char", "class", "const", "continue", "default", "defer",
+        "delete", "do", "double", "else", "enum", "extends", "extern", "false", "final", "float", "for", "func",
+        "go", "if", "implements", "import", "int", "interface", "long", "namespace", "new", "null", "nullptr",
+        "package", "private", "protected", "public", "return", "short", "signed", "sizeof", "static", "struct",
+        "switch", "template", "this", "throw", "true", "try", "typedef", "union", "unsigned", "using", "var",
+        "virtual", "void", "volatile", "while",
+    ],
+    line_comments: &["//"],
+    block_comment: Some(("/*", "*/")),
+    ignore_case: false,
+};
+
+const SHELL: Syntax = Syntax {
+    keywords: &[
+        "case", "do", "done", "echo", "elif", "else", "esac", "exit", "export", "fi", "for", "function", "if",
+        "in", "local", "return", "then", "until", "while",
+    ],
+    line_comments: &["#"],
+    block_comment: None,
+    ignore_case: false,
+};
+
+const SQL: Syntax = Syntax {
+    keywords: &[
+        "and", "as", "by", "create", "delete", "desc", "distinct", "drop", "from", "group", "having", "in",
+        "insert", "into", "is", "join", "left", "limit", "not", "null", "on", "or", "order", "primary", "select",
+        "set", "table", "union", "update", "values", "where",
+    ],
+    line_comments: &["--"],
+    block_comment: Some(("/*", "*/")),
+    ignore_case: true,
+};
+
+/// Config files: only comments, strings and a few literals stand out.
+const CONFIG: Syntax = Syntax {
+    keywords: &["true", "false", "null", "yes", "no", "on", "off"],
+    line_comments: &["#", ";"],
+    block_comment: None,
+    ignore_case: false,
+};
+
+const JSON: Syntax = Syntax {
+    keywords: &["true", "false", "null"],
+    line_comments: &[],
+    block_comment: None,
+    ignore_case: false,
+};
+
+const MARKUP: Syntax = Syntax {
+    keywords: &[],
+    line_comments: &[],
+    block_comment: Some(("<!--", "-->")),
+    ignore_case: false,
+};
+
+const CSS: Syntax = Syntax {
+    keywords: &["important"],
+    line_comments: &[],
+    block_comment: Some(("/*", "*/")),
+    ignore_case: false,
+};
+
+/// The syntax for a file extension or a markdown code fence, `None` for plain text.
+fn syntax_for(extension: &str) -> Option<&'static Syntax> {
+    match extension {
+        "rs" | "rust" => Some(&RUST),
+        "py" | "pyw" | "python" => Some(&PYTHON),
+        "js" | "mjs" | "cjs" | "jsx" | "ts" | "tsx" | "javascript" | "typescript" => Some(&JAVASCRIPT),
+        "c" | "h" | "cc" | "cpp" | "cxx" | "hpp" | "java" | "kt" | "cs" | "go" | "swift" | "dart" => Some(&C_LIKE),
+        "sh" | "bash" | "zsh" | "shell" => Some(&SHELL),
+        "sql" => Some(&SQL),
+        "toml" | "ini" | "cfg" | "conf" | "yaml" | "yml" | "properties" | "env" => Some(&CONFIG),
+        "json" => Some(&JSON),
+        "html" | "htm" | "xml" | "svg" => Some(&MARKUP),
+        "css" | "scss" => Some(&CSS),
+        _ => None,
+    }
+}
+
+/// Splits `text` into lines of highlighted spans. Block comments may span lines, strings
+/// are taken to end on the line they start on.
+fn highlight(text: &str, syntax: Option<&Syntax>) -> Vec<Vec<Span>> {
+    let Some(syntax) = syntax else {
+        return text.lines().map(|line| vec![span(&line.replace('\t', "    "), Token::Plain)]).collect();
+    };
+
+    let mut in_comment = false;
+    text.lines()
+        .map(|line| {
+            let line = line.replace('\t', "    ");
+            let mut spans: Vec<Span> = vec![];
+            let mut rest = line.as_str();
+            while !rest.is_empty() {
+                let (token, length) = next_token(rest, syntax, &mut in_comment);
+                push_span(&mut spans, &rest[..length], token);
+                rest = &rest[length..];
+            }
+            spans
+        })
+        .collect()
+}
+
+/// The token `rest` starts with and its length in bytes.
+fn next_token(rest: &str, syntax: &Syntax, in_comment: &mut bool) -> (Token, usize) {
+    if let Some((start, end)) = syntax.block_comment {
+        let from = if *in_comment {
+            Some(0)
+        } else {
+            rest.starts_with(start).then_some(start.len())
+        };
+        if let Some(from) = from {
+            *in_comment = true;
+            return match rest[from..].find(end) {
+                Some(position) => {
+                    *in_comment = false;
+                    (Token::Comment, from + position + end.len())
+                }
+                None => (Token::Comment, rest.len()),
+            };
+        }
+    }
+    if syntax.line_comments.iter().any(|comment| rest.starts_with(comment)) {
+        return (Token::Comment, rest.len());
+    }
+
+    let first = rest.chars().next().unwrap_or_default();
+    if matches!(first, '"' | '\'' | '`') {
+        if let Some(length) = string_length(rest, first) {
+            return (Token::String, length);
+        }
+    }
+    if first.is_ascii_digit() {
+        let length = rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '.' || c == '_')).unwrap_or(rest.len());
+        return (Token::Number, length);
+    }
+    if first.is_alphabetic() || first == '_' {
+        let length = rest.find(|c: char| !(c.is_alphanumeric() || c == '_')).unwrap_or(rest.len());
+        let word = &rest[..length];
+        let keyword = syntax.keywords
+            .iter()
+            .any(|keyword| if syntax.ignore_case { keyword.eq_ignore_ascii_case(word) } else { *keyword == word });
+        return (if keyword { Token::Keyword } else { Token::Plain }, length);
+    }
+    (Token::Plain, first.len_utf8())
+}
+
+/// Length of the string literal `rest` starts with, if it is closed on this line.
+fn string_length(rest: &str, quote: char) -> Option<usize> {
+    let mut escaped = false;
+    for (index, c) in rest.char_indices().skip(1) {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            c if c == quote => return Some(index + c.len_utf8()),
+            _ => {}
+        }
+    }
+    None
+}
+
+fn span(text: &str, token: Token) -> Span {
+    Span {
+        text: text.to_string(),
+        token,
+    }
+}
+
+fn push_span(spans: &mut Vec<Span>, text: &str, token: Token) {
+    match spans.last_mut() {
+        Some(last) if last.token == token => last.text.push_str(text),
+        _ => spans.push(span(text, token)),
+    }
+}
+
+/// Headings, paragraphs, lists, quotes, fenced code and rules. Inline markup is dropped
+/// rather than styled, links keep their text.
+fn parse_markdown(text: &str) -> Vec<Block> {
+    let mut blocks = vec![];
+    let mut paragraph: Vec<&str> = vec![];
+    let mut code: Option<(Option<&Syntax>, Vec<&str>)> = None;
+
+    let flush = |paragraph: &mut Vec<&str>, blocks: &mut Vec<Block>| {
+        if !paragraph.is_empty() {
+            blocks.push(Block::Paragraph(inline(&paragraph.join(" "))));
+            paragraph.clear();
+        }
+    };
+
+    for line in text.lines() {
+        let trimmed = line.trim();
+        if let Some((syntax, lines)) = &mut code {
+            if trimmed.starts_with("```") {
+                blocks.push(Block::Code(highlight(&lines.join("\n"), *syntax)));
+                code = None;
+            } else {
+                lines.push(line);
+            }
+            continue;
+        }
+
+        if let Some(language) = trimmed.strip_prefix("```") {
+            flush(&mut paragraph, &mut blocks);
+            code = Some((syntax_for(&language.trim().to_lowercase()), vec![]));
+            continue;
+        }
+        if trimmed.is_empty() {
+            flush(&mut paragraph, &mut blocks);
+            continue;
+        }
+
+        let level = trimmed.bytes().take_while(|&byte| byte == b'#').count();
+        let is_rule = trimmed.len() >= 3
+            && ['-', '*', '_'].iter().any(|&mark| trimmed.chars().all(|c| c == mark || c == ' '));
+        let indent = (line.len() - line.trim_start().len()) / 2;
+
+        let block = if (1..=6).contains(&level) && trimmed[level..].starts_with(' ') {
+            Block::Heading(level as u8, inline(trimmed[level..].trim()))
+        } else if is_rule {
+            Block::Rule
+        } else if let Some(quote) = trimmed.strip_prefix('>') {
+            Block::Quote(inline(quote.trim()))
+        } else if let Some(item) = ["- ", "* ", "+ "].iter().find_map(|bullet| trimmed.strip_prefix(bullet)) {
+            Block::Item(indent, format!("• {}", inline(item)))
+        } else if let Some((number, item)) = trimmed.split_once(". ").filter(|(number, _)| {
+            !number.is_empty() && number.len() <= 3 && number.bytes().all(|byte| byte.is_ascii_digit())
+        }) {
+            Block::Item(indent, format!("{}. {}", number, inline(item)))
+        } else {
+            paragraph.push(trimmed);
+            continue;
+        };
+        flush(&mut paragraph, &mut blocks);
+        blocks.push(block);
+    }
+
+    flush(&mut paragraph, &mut blocks);
+    if let Some((syntax, lines)) = code {
+        blocks.push(Block::Code(highlight(&lines.join("\n"), syntax)));
+    }
+    blocks
+}
+
+/// Drops emphasis and code markers and turns `[text](url)` and `![alt](url)` into their text.
+fn inline(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        if rest.starts_with("**") || rest.starts_with("__") {
+            rest = &rest[2..];
+            continue;
+        }
+        if c == '`' {
+            rest = &rest[1..];
+            continue;
+        }
+        let link = rest.strip_prefix('!').unwrap_or(rest);
+        if let Some((label, after)) = link.strip_prefix('[').and_then(|link| link.split_once("](")) {
+            if let Some(end) = after.find(')') {
+                result.push_str(label);
+                rest = &after[end + 1..];
+                continue;
+            }
+        }
+        result.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+    result
+}
+
+/// What can be told about a PDF without rendering it.
+fn parse_pdf(bytes: &[u8]) -> PdfInfo {
+    let version = bytes[5..]
+        .iter()
+        .take_while(|byte| byte.is_ascii_digit() || **byte == b'.')
+        .map(|&byte| byte as char)
+        .collect();
+
+    let mut pages = 0;
+    let mut from = 0;
+    while let Some(position) = find(&bytes[from..], b"/Type") {
+        from += position + b"/Type".len();
+        let rest = &bytes[from..];
+        let rest = &rest[rest.iter().take_while(|byte| byte.is_ascii_whitespace()).count()..];
+        if rest.starts_with(b"/Page") && !rest[b"/Page".len()..].first().is_some_and(u8::is_ascii_alphanumeric) {
+            pages += 1;
+        }
+    }
+
+    PdfInfo {
+        version,
+        pages: (pages > 0).then_some(pages),
+        title: find(bytes, b"/Title").and_then(|position| pdf_string(&bytes[position + b"/Title".len()..])),
+        thumbnails: jpeg_streams(bytes),
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+/// A literal `(...)` string at the start of `bytes`, also in UTF-16 as PDFs write non-ASCII titles.
+fn pdf_string(bytes: &[u8]) -> Option<String> {
+    let bytes = &bytes[bytes.iter().take_while(|byte| byte.is_ascii_whitespace()).count()..];
+    let bytes = bytes.strip_prefix(b"(")?;
+
+    let mut value = vec![];
+    let mut depth = 0;
+    let mut escaped = false;
+    for &byte in bytes.iter().take(1024) {
+        match byte {
+            _ if escaped => {
+                escaped = false;
+                value.push(match byte {
+                    b'n' => b'\n',
+                    b'r' => b'\r',
+                    b't' => b'\t',
+                    byte => byte,
+                });
+            }
+            b'\\' => escaped = true,
+            b'(' => {
+                depth += 1;
+                value.push(byte);
+            }
+            b')' if depth == 0 => break,
+            b')' => {
+                depth -= 1;
+                value.push(byte);
+            }
+            byte => value.push(byte),
+        }
+    }
+
+    let title = match value.strip_prefix(&[0xfe, 0xff]) {
+        Some(utf16) => {
+            let units: Vec<u16> = utf16.chunks_exact(2).map(|pair| u16::from_be_bytes([pair[0], pair[1]])).collect();
+            String::from_utf16_lossy(&units)
+        }
+        None => value.iter().map(|&byte| byte as char).collect(),
+    };
+    Some(title.trim().to_string()).filter(|title| !title.is_empty())
+}
+
+/// The contents of `DCTDecode` streams, JPEG images stored as they are.
+fn jpeg_streams(bytes: &[u8]) -> Vec<Vec<u8>> {
+    let mut images = vec![];
+    let mut from = 0;
+    while images.len() < PDF_THUMBNAILS {
+        let Some(position) = find(&bytes[from..], b"/DCTDecode") else {
+            break;
+        };
+        from += position;
+        let Some(start) = find(&bytes[from..], b"stream") else {
+            break;
+        };
+        let start = from + start + b"stream".len();
+        let start = start + bytes[start..].iter().take(2).take_while(|&&byte| byte == b'\r' || byte == b'\n').count();
+        let Some(end) = find(&bytes[start..], b"endstream") else {
+            break;
+        };
+
+        let image = &bytes[start..start + end];
+        if image.starts_with(&[0xff, 0xd8]) {
+            images.push(image.to_vec());
+        }
+        from = start + end;
+    }
+    images
+}
@@ -0,0 +1,108 @@
+//! Named servers to log in to, kept in `profiles.json`.
+//!
+//! `SERVER_URL` from the environment or `.env` still works: when no saved profile
+//! points at it, it shows up as an extra, unsaved profile.
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use reqwest::{Certificate, Client, Proxy};
+use serde::{Deserialize, Serialize};
+
+use crate::utils::data_dir;
+
+const PROFILES_FILE: &str = "profiles.json";
+const ENV_PROFILE_NAME: &str = "SERVER_URL";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Profile {
+    pub name: String,
+    pub url: String,
+    /// Filled into the login field when the profile is picked.
+    #[serde(default)]
+    pub username: String,
+    /// PEM file with an extra root certificate, for servers with a private CA.
+    #[serde(default)]
+    pub ca_certificate: Option<PathBuf>,
+    /// Skips certificate checks altogether. Only for test servers.
+    #[serde(default)]
+    pub accept_invalid_certs: bool,
+    /// `http://`, `https://` or `socks5://` proxy for every request to this server.
+    #[serde(default)]
+    pub proxy: Option<String>,
+    /// Encrypts uploads end to end, see [`crate::encryption`].
+    #[serde(default)]
+    pub encrypt: bool,
+}
+
+impl Profile {
+    pub fn client(&self) -> Result<Client, String> {
+        let mut builder = Client::builder().danger_accept_invalid_certs(self.accept_invalid_certs);
+
+        if let Some(path) = &self.ca_certificate {
+            let pem = fs::read(path).map_err(|e| format!("{:?}: {}", path, e))?;
+            let certificate = Certificate::from_pem(&pem).map_err(|e| format!("{:?}: {}", path, e))?;
+            builder = builder.add_root_certificate(certificate);
+        }
+        if let Some(proxy) = &self.proxy {
+            builder = builder.proxy(Proxy::all(proxy).map_err(|e| format!("Proxy {}: {}", proxy, e))?);
+        }
+
+        builder.build().map_err(|e| e.to_string())
+    }
+
+    /// Checks what the editor can't enforce on its own.
+    pub fn validate(&self, others: &[Profile]) -> Result<(), String> {
+        if self.name.trim().is_empty() {
+            return Err(String::from("The profile needs a name"));
+        }
+        if others.iter().any(|other| other.name == self.name) {
+            return Err(format!("There already is a profile named '{}'", self.name));
+        }
+        if !self.url.starts_with("http://") && !self.url.starts_with("https://") {
+            return Err(String::from("The URL must start with http:// or https://"));
+        }
+        self.client().map(|_| ())
+    }
+}
+
+/// Saved profiles, plus one for `SERVER_URL` if it is set and not saved yet.
+pub fn load_profiles() -> Vec<Profile> {
+    let mut profiles: Vec<Profile> = fs::read_to_string(profiles_path())
+        .ok()
+        .and_then(|data| serde_json::from_str(&data).ok())
+        .unwrap_or_default();
+
+    if let Ok(url) = env::var("SERVER_URL") {
+        let url = url.trim_end_matches('/').to_string();
+        if !profiles.iter().any(|profile| profile.url == url) {
+            profiles.insert(0, Profile {
+                name: String::from(ENV_PROFILE_NAME),
+                url,
+                ..Profile::default()
+            });
+        }
+    }
+
+    profiles
+}
+
+pub fn save_profiles(profiles: &[Profile]) {
+    let path = profiles_path();
+
+    if let Some(dir) = path.parent() {
+        let _ = fs::create_dir_all(dir);
+    }
+    match serde_json::to_string_pretty(profiles) {
+        Ok(data) => {
+            if let Err(e) = fs::write(&path, data) {
+                eprintln!("Failed to save profiles: {}", e);
+            }
+        }
+        Err(e) => eprintln!("Failed to serialize profiles: {}", e),
+    }
+}
+
+fn profiles_path() -> PathBuf {
+    data_dir().join(PROFILES_FILE)
+}
@@ -0,0 +1,418 @@
+//! The search box mini-language.
+//!
+//! A query is a list of space separated terms that all have to match:
+//!
+//! * `report` - the name contains the word, ignoring case;
+//! * `*.pdf`, `img_??.png` - glob over the whole name;
+//! * `/^draft.*\.docx?$/`, `/Draft/i` - regular expression over the name;
+//! * `size>10MB`, `size<=512KB` - size with `<`, `<=`, `>`, `>=` or `=` and B/KB/MB/GB/TB;
+//! * `modified<7d`, `created>2024-01-31` - newer (`<`) or older (`>`) than an age in
+//!   s/m/h/d/w, or before (`<`) / after (`>`) a date;
+//! * `type:image`, `type:pdf` - content type or extension;
+//! * `owner:alice`;
+//! * `-term` - negates any of the above.
+//!
+//! Terms with spaces can be quoted: `"annual report"`.
+
+use std::fs;
+use std::path::PathBuf;
+use chrono::{NaiveDate, Utc};
+
+use crate::file_info::FileInfo;
+use crate::pattern::Regex;
+use crate::utils::data_dir;
+
+const SAVED_SEARCHES_FILE: &str = "searches.json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Less,
+    LessOrEqual,
+    Equal,
+    GreaterOrEqual,
+    Greater,
+}
+
+impl Comparison {
+    fn holds<T: PartialOrd>(self, left: T, right: T) -> bool {
+        match self {
+            Comparison::Less => left < right,
+            Comparison::LessOrEqual => left <= right,
+            Comparison::Equal => left == right,
+            Comparison::GreaterOrEqual => left >= right,
+            Comparison::Greater => left > right,
+        }
+    }
+
+    fn flipped(self) -> Self {
+        match self {
+            Comparison::Less => Comparison::Greater,
+            Comparison::LessOrEqual => Comparison::GreaterOrEqual,
+            Comparison::Equal => Comparison::Equal,
+            Comparison::GreaterOrEqual => Comparison::LessOrEqual,
+            Comparison::Greater => Comparison::Less,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DateField {
+    Created,
+    Modified,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DateValue {
+    /// That many seconds before now.
+    Ago(i64),
+    /// Unix timestamp.
+    At(i64),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Filter {
+    Contains(String),
+    Glob(Regex),
+    Regex(Regex),
+    Size(Comparison, u64),
+    Date(DateField, Comparison, DateValue),
+    Type(String),
+    Owner(String),
+    Not(Box<Filter>),
+}
+
+impl Filter {
+    pub fn matches(&self, info: &FileInfo, now: i64) -> bool {
+        match self {
+            Filter::Contains(word) => info.name.to_lowercase().contains(word),
+            Filter::Glob(glob) => glob.is_match(&info.name),
+            Filter::Regex(regex) => regex.is_match(&info.name),
+            Filter::Size(comparison, size) => info.size.is_some_and(|actual| comparison.holds(actual, *size)),
+            Filter::Date(field, comparison, value) => {
+                let timestamp = match field {
+                    DateField::Created => info.created,
+                    DateField::Modified => info.modified.or(info.created),
+                };
+                let Some(timestamp) = timestamp else {
+                    return false;
+                };
+
+                match value {
+                    // "modified<7d" reads as "younger than 7 days", i.e. a later timestamp.
+                    DateValue::Ago(seconds) => {
+                        let then = now.checked_sub(*seconds).unwrap_or(i64::MIN);
+                        comparison.flipped().holds(timestamp, then)
+                    }
+                    DateValue::At(at) => comparison.holds(timestamp, *at),
+                }
+            }
+            Filter::Type(kind) => {
+                info.mime().to_lowercase().contains(kind)
+                    || info.name.to_lowercase().ends_with(&format!(".{}", kind))
+            }
+            Filter::Owner(owner) => info.owner
+                .as_ref()
+                .is_some_and(|actual| actual.to_lowercase().contains(owner)),
+            Filter::Not(filter) => !filter.matches(info, now),
+        }
+    }
+}
+
+/// A parsed search: every filter has to match.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Query {
+    pub filters: Vec<Filter>,
+}
+
+impl Query {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let filters = split_terms(text)?
+            .iter()
+            .map(|term| parse_term(term))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Query { filters })
+    }
+
+    pub fn matches(&self, info: &FileInfo) -> bool {
+        let now = Utc::now().timestamp();
+        self.filters.iter().all(|filter| filter.matches(info, now))
+    }
+}
+
+/// Splits on whitespace, keeping `"quoted terms"` and `/regex with spaces/` together.
+fn split_terms(text: &str) -> Result<Vec<String>, String> {
+    let mut terms = vec![];
+    let mut current = String::new();
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {
+                if !current.is_empty() {
+                    terms.push(std::mem::take(&mut current));
+                }
+            }
+            '"' => {
+                let mut closed = false;
+                for c in chars.by_ref() {
+                    if c == '"' {
+                        closed = true;
+                        break;
+                    }
+                    current.push(c);
+                }
+                if !closed {
+                    return Err(String::from("unclosed quote"));
+                }
+            }
+            '/' if current.is_empty() || current == "-" => {
+                current.push('/');
+                let mut closed = false;
+                while let Some(c) = chars.next() {
+                    current.push(c);
+                    if c == '\\' {
+                        if let Some(escaped) = chars.next() {
+                            current.push(escaped);
+                        }
+                    } else if c == '/' {
+                        closed = true;
+                        break;
+                    }
+                }
+                if !closed {
+                    return Err(String::from("unclosed regular expression, add a '/'"));
+                }
+            }
+            c => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        terms.push(current);
+    }
+
+    Ok(terms)
+}
+
+fn parse_term(term: &str) -> Result<Filter, String> {
+    if let Some(rest) = term.strip_prefix('-').filter(|rest| !rest.is_empty()) {
+        return Ok(Filter::Not(Box::new(parse_term(rest)?)));
+    }
+
+    if let Some(body) = term.strip_prefix('/') {
+        let (pattern, flags) = body
+            .rsplit_once('/')
+            .ok_or_else(|| String::from("unclosed regular expression, add a '/'"))?;
+        if let Some(flag) = flags.chars().find(|flag| *flag != 'i') {
+            return Err(format!("unknown regular expression flag '{}'", flag));
+        }
+        let regex = Regex::new(pattern, flags.contains('i'))
+            .map_err(|e| format!("invalid regular expression: {}", e))?;
+        return Ok(Filter::Regex(regex));
+    }
+
+    if let Some(kind) = term.strip_prefix("type:") {
+        return non_empty(kind, "type:").map(Filter::Type);
+    }
+    if let Some(owner) = term.strip_prefix("owner:") {
+        return non_empty(owner, "owner:").map(Filter::Owner);
+    }
+
+    if let Some((field, comparison, value)) = split_comparison(term) {
+        return match field {
+            "size" => Ok(Filter::Size(comparison, parse_size(value)?)),
+            "modified" => Ok(Filter::Date(DateField::Modified, comparison, parse_date(value)?)),
+            "created" | "uploaded" => Ok(Filter::Date(DateField::Created, comparison, parse_date(value)?)),
+            _ => Err(format!("unknown field '{}', use size, modified or created", field)),
+        };
+    }
+
+    if term.contains(['*', '?', '[']) {
+        return Regex::glob(term)
+            .map(Filter::Glob)
+            .map_err(|e| format!("invalid pattern '{}': {}", term, e));
+    }
+
+    Ok(Filter::Contains(term.to_lowercase()))
+}
+
+fn non_empty(value: &str, prefix: &str) -> Result<String, String> {
+    if value.is_empty() {
+        Err(format!("'{}' needs a value", prefix))
+    } else {
+        Ok(value.to_lowercase())
+    }
+}
+
+/// `size>=10MB` -> `("size", GreaterOrEqual, "10MB")`, only for word-like field names.
+fn split_comparison(term: &str) -> Option<(&str, Comparison, &str)> {
+    let index = term.find(['<', '>', '='])?;
+    let field = &term[..index];
+    if field.is_empty() || !field.chars().all(|c| c.is_ascii_alphabetic()) {
+        return None;
+    }
+
+    let rest = &term[index..];
+    let (comparison, length) = if rest.starts_with("<=") {
+        (Comparison::LessOrEqual, 2)
+    } else if rest.starts_with(">=") {
+        (Comparison::GreaterOrEqual, 2)
+    } else if rest.starts_with('<') {
+        (Comparison::Less, 1)
+    } else if rest.starts_with('>') {
+        (Comparison::Greater, 1)
+    } else {
+        (Comparison::Equal, 1)
+    };
+
+    Some((field, comparison, &rest[length..]))
+}
+
+fn parse_size(value: &str) -> Result<u64, String> {
+    let split = value
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("invalid size '{}'", value))?;
+    let multiplier: u64 = match unit.to_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" => 1024,
+        "M" | "MB" => 1024 * 1024,
+        "G" | "GB" => 1024 * 1024 * 1024,
+        "T" | "TB" => 1024 * 1024 * 1024 * 1024,
+        _ => return Err(format!("unknown size unit '{}', use B, KB, MB, GB or TB", unit)),
+    };
+
+    Ok((number * multiplier as f64) as u64)
+}
+
+fn parse_date(value: &str) -> Result<DateValue, String> {
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        let timestamp = date
+            .and_hms_opt(0, 0, 0)
+            .map(|date| date.and_utc().timestamp())
+            .unwrap_or_default();
+        return Ok(DateValue::At(timestamp));
+    }
+
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+
+    let number: i64 = number
+        .parse()
+        .map_err(|_| format!("invalid date '{}', use an age like 7d or a date like 2024-01-31", value))?;
+    let seconds = match unit {
+        "s" => 1,
+        "m" | "min" => 60,
+        "h" => 60 * 60,
+        "" | "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return Err(format!("unknown age unit '{}', use s, m, h, d or w", unit)),
+    };
+
+    number
+        .checked_mul(seconds)
+        .map(DateValue::Ago)
+        .ok_or_else(|| format!("age '{}' is too large", value))
+}
+
+/// Searches pinned as quick filters under the search box.
+pub fn load_saved_searches() -> Vec<String> {
+    fs::read_to_string(saved_searches_path())
+        .ok()
+        .and_then(|data| serde_json::from_str(&data).ok())
+        .unwrap_or_default()
+}
+
+pub fn save_saved_searches(searches: &[String]) {
+    let path = saved_searches_path();
+
+    if let Some(dir) = path.parent() {
+        let _ = fs::create_dir_all(dir);
+    }
+    match serde_json::to_string_pretty(searches) {
+        Ok(data) => {
+            if let Err(e) = fs::write(&path, data) {
+                eprintln!("Failed to save searches: {}", e);
+            }
+        }
+        Err(e) => eprintln!("Failed to serialize searches: {}", e),
+    }
+}
+
+fn saved_searches_path() -> PathBuf {
+    data_dir().join(SAVED_SEARCHES_FILE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: i64 = 24 * 60 * 60;
+
+    fn file(name: &str, size: u64, modified: i64) -> FileInfo {
+        FileInfo {
+            name: name.to_string(),
+            size: Some(size),
+            modified: Some(modified),
+            ..Default::default()
+        }
+    }
+
+    fn matches(query: &str, info: &FileInfo, now: i64) -> bool {
+        Query::parse(query).unwrap().filters.iter().all(|filter| filter.matches(info, now))
+    }
+
+    #[test]
+    fn parses_terms() {
+        let query = Query::parse(r#"report "annual plan" -*.tmp /^a b$/i size>=10MB type:PDF owner:Bob"#).unwrap();
+        assert_eq!(query.filters.len(), 7);
+        assert_eq!(query.filters[0], Filter::Contains("report".into()));
+        assert_eq!(query.filters[1], Filter::Contains("annual plan".into()));
+        assert!(matches!(&query.filters[2], Filter::Not(filter) if matches!(**filter, Filter::Glob(_))));
+        assert!(matches!(query.filters[3], Filter::Regex(_)));
+        assert_eq!(query.filters[4], Filter::Size(Comparison::GreaterOrEqual, 10 * 1024 * 1024));
+        assert_eq!(query.filters[5], Filter::Type("pdf".into()));
+        assert_eq!(query.filters[6], Filter::Owner("bob".into()));
+        assert_eq!(Query::parse("  ").unwrap(), Query::default());
+    }
+
+    #[test]
+    fn rejects_bad_terms() {
+        for query in ["\"unclosed", "/unclosed", "/a/x", "/(/", "size>10XB", "color=red", "type:", "file[0-9"] {
+            assert!(Query::parse(query).is_err(), "{}", query);
+        }
+    }
+
+    #[test]
+    fn parses_dates() {
+        assert_eq!(parse_date("7d"), Ok(DateValue::Ago(7 * DAY)));
+        assert_eq!(parse_date("7"), Ok(DateValue::Ago(7 * DAY)));
+        assert_eq!(parse_date("90min"), Ok(DateValue::Ago(90 * 60)));
+        assert_eq!(parse_date("2w"), Ok(DateValue::Ago(14 * DAY)));
+        assert_eq!(parse_date("1970-01-02"), Ok(DateValue::At(DAY)));
+        assert!(parse_date("7y").is_err());
+        assert!(parse_date("d").is_err());
+        assert!(parse_date("999999999999999d").is_err());
+        assert!(parse_date("99999999999999999999s").is_err());
+    }
+
+    #[test]
+    fn matches_files() {
+        let now = 1_000 * DAY;
+        let info = file("Report 2024.PDF", 20 * 1024 * 1024, now - 3 * DAY);
+        assert!(matches("report *.pdf size>10MB modified<7d", &info, now));
+        assert!(matches("-draft modified>1d", &info, now));
+        assert!(!matches("created<7d", &info, now));
+        assert!(matches("modified>1970-01-02 type:pdf", &info, now));
+        assert!(!matches("modified>7d", &info, now));
+        assert!(!matches("size<1KB", &info, now));
+        // The cutoff would be before i64::MIN, everything is younger than that.
+        assert!(matches("modified<9223372036854775807s", &info, -DAY));
+    }
+}
@@ -0,0 +1,148 @@
+use std::path::PathBuf;
+
+use crate::transfer::{Kind, State, Transfer};
+
+pub const DEFAULT_MAX_CONCURRENT: usize = 3;
+
+/// Every upload and download of the session, in priority order.
+/// Only the first `max_concurrent` runnable jobs are actually transferring.
+pub struct TransferQueue {
+    jobs: Vec<Transfer>,
+    max_concurrent: usize,
+    next_id: usize,
+}
+
+impl TransferQueue {
+    pub fn new(max_concurrent: usize) -> Self {
+        TransferQueue {
+            jobs: vec![],
+            max_concurrent: max_concurrent.max(1),
+            next_id: 0,
+        }
+    }
+
+    pub fn jobs(&self) -> &[Transfer] {
+        &self.jobs
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.jobs.is_empty()
+    }
+
+    pub fn max_concurrent(&self) -> usize {
+        self.max_concurrent
+    }
+
+    pub fn set_max_concurrent(&mut self, max_concurrent: usize) {
+        self.max_concurrent = max_concurrent.max(1);
+        self.schedule();
+    }
+
+    pub fn running(&self) -> impl Iterator<Item = &Transfer> {
+        self.jobs.iter().filter(|job| job.is_running())
+    }
+
+    /// Adds a job at the end of the queue and returns its id.
+    pub fn push(&mut self, kind: Kind, file_name: String, path: PathBuf, total: u64) -> usize {
+        self.push_with(kind, file_name, path, total, |_| {})
+    }
+
+    /// Like [`TransferQueue::push`], letting the caller fill in resume data before the job may start.
+    pub fn push_with(
+        &mut self,
+        kind: Kind,
+        file_name: String,
+        path: PathBuf,
+        total: u64,
+        setup: impl FnOnce(&mut Transfer),
+    ) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let mut job = Transfer::new(id, kind, file_name, path, total);
+        setup(&mut job);
+        self.jobs.push(job);
+        self.schedule();
+        id
+    }
+
+    pub fn get(&self, id: usize) -> Option<&Transfer> {
+        self.jobs.iter().find(|job| job.id == id)
+    }
+
+    pub fn get_mut(&mut self, id: usize) -> Option<&mut Transfer> {
+        self.jobs.iter_mut().find(|job| job.id == id)
+    }
+
+    /// The latest unfinished download of `file_name`, if any.
+    pub fn download_of(&self, file_name: &str) -> Option<&Transfer> {
+        self.jobs
+            .iter()
+            .rev()
+            .find(|job| job.kind == Kind::Download && job.file_name == file_name && !job.is_finished())
+    }
+
+    pub fn remove(&mut self, id: usize) -> Option<Transfer> {
+        let index = self.jobs.iter().position(|job| job.id == id)?;
+        let job = self.jobs.remove(index);
+        self.schedule();
+        Some(job)
+    }
+
+    pub fn pause(&mut self, id: usize) {
+        if let Some(job) = self.get_mut(id) {
+            if matches!(job.state, State::Queued | State::Running) {
+                job.state = State::Paused;
+                job.resume = true;
+            }
+        }
+        self.schedule();
+    }
+
+    pub fn requeue(&mut self, id: usize) {
+        if let Some(job) = self.get_mut(id) {
+            if matches!(job.state, State::Paused | State::Failed(_)) {
+                job.requeue();
+            }
+        }
+        self.schedule();
+    }
+
+    /// Moves a job `offset` places towards the front (negative) or back (positive) of the queue.
+    pub fn move_by(&mut self, id: usize, offset: isize) {
+        let Some(index) = self.jobs.iter().position(|job| job.id == id) else {
+            return;
+        };
+        let target = index
+            .saturating_add_signed(offset)
+            .min(self.jobs.len() - 1);
+
+        let job = self.jobs.remove(index);
+        self.jobs.insert(target, job);
+        self.schedule();
+    }
+
+    pub fn clear_finished(&mut self) {
+        self.jobs.retain(|job| !job.is_finished());
+    }
+
+    /// Starts queued jobs in priority order while there is room, and puts running
+    /// jobs that fell below the limit (after a reorder or a lower limit) back in line.
+    pub fn schedule(&mut self) {
+        let mut slots = self.max_concurrent;
+
+        for job in &mut self.jobs {
+            match job.state {
+                State::Running | State::Queued if slots > 0 => {
+                    job.state = State::Running;
+                    slots -= 1;
+                }
+                State::Running => {
+                    job.state = State::Queued;
+                    job.resume = true;
+                }
+                _ => {}
+            }
+        }
+    }
+}
@@ -0,0 +1,141 @@
+//! A logged in server, for code that moves whole files instead of feeding the transfer queue.
+
+use std::path::Path;
+use futures::{Sink, SinkExt};
+use reqwest::Client;
+
+use crate::api::{delete_selected_request, file_head_request, files_request, limits_request, DEFAULT_SIZE_LIMIT};
+use crate::chunks::{assemble, chunk_name, chunk_ranges, group_chunks, ListedFile};
+use crate::encryption::{plaintext_limit, EncryptionKey};
+use crate::journal::fingerprint;
+use crate::transfer::{download_file, upload_file, DownloadRequest, Progress, UploadRequest};
+
+#[derive(Debug, Clone)]
+pub struct Remote {
+    pub client: Client,
+    pub server_url: String,
+    pub token: String,
+    /// Decrypts files stored encrypted and encrypts uploads, see [`crate::encryption`].
+    pub key: Option<EncryptionKey>,
+    /// Encrypt uploads, the profile's setting.
+    pub encrypt: bool,
+}
+
+impl Remote {
+    pub async fn listing(&self) -> Result<Vec<ListedFile>, String> {
+        let files = files_request(self.client.clone(), self.server_url.clone(), self.token.clone()).await?;
+        Ok(group_chunks(files))
+    }
+
+    /// The server's upload limit, or the default when it doesn't say.
+    pub async fn size_limit(&self) -> u64 {
+        limits_request(self.client.clone(), self.server_url.clone(), self.token.clone())
+            .await
+            .unwrap_or(DEFAULT_SIZE_LIMIT)
+    }
+
+    pub async fn delete(&self, names: Vec<String>) -> Result<(), String> {
+        delete_selected_request(self.client.clone(), self.server_url.clone(), self.token.clone(), names).await
+    }
+
+    /// Up to `length` bytes from the start of `name` as stored, still encrypted if it is.
+    /// The flag tells whether the file ended before that.
+    pub async fn head(&self, name: &str, length: u64) -> Result<(Vec<u8>, bool), String> {
+        file_head_request(self.client.clone(), self.server_url.clone(), self.token.clone(), name.to_string(), length).await
+    }
+
+    /// Uploads `path` as `name`, cut into chunks like the client does when it is over `limit`.
+    /// With `encrypt` on the file is encrypted with `key`, every chunk on its own.
+    /// `progress` hands out a sink per piece, given the piece's name and size. Every sink
+    /// ends with `Finished` or `Errored`.
+    pub async fn upload<P>(&self, path: &Path, name: &str, size: u64, limit: u64, mut progress: impl FnMut(&str, u64) -> P) -> Result<(), String>
+    where
+        P: Sink<Progress> + Unpin,
+    {
+        let checksum = fingerprint(path);
+        let limit = if self.encrypt { plaintext_limit(limit) } else { limit };
+        let pieces: Vec<(String, u64, u64)> = if size > limit {
+            let ranges = chunk_ranges(size, limit);
+            ranges
+                .iter()
+                .enumerate()
+                .map(|(index, (offset, length))| (chunk_name(name, index, ranges.len()), *offset, *length))
+                .collect()
+        } else {
+            vec![(name.to_string(), 0, size)]
+        };
+
+        for (file_name, offset, length) in pieces {
+            let mut output = progress(&file_name, length);
+            let request = UploadRequest {
+                client: self.client.clone(),
+                server_url: self.server_url.clone(),
+                token: self.token.clone(),
+                path: path.to_path_buf(),
+                file_name,
+                total: length,
+                source_offset: offset,
+                checksum: checksum.clone(),
+                upload_id: None,
+                sha256: None,
+                encrypt: self.encrypt,
+                key: self.key.filter(|_| self.encrypt),
+            };
+            let result = upload_file(&mut output, request).await;
+            finish(&mut output, result).await?;
+        }
+        Ok(())
+    }
+
+    /// Downloads `file` into `target`. The chunks of a chunked file are fetched next to
+    /// `target` one by one and then joined.
+    pub async fn download<P>(&self, file: &ListedFile, target: &Path, mut progress: impl FnMut(&str, u64) -> P) -> Result<(), String>
+    where
+        P: Sink<Progress> + Unpin,
+    {
+        let (name, chunks) = (file.info.name.as_str(), &file.chunks);
+        let target_name = target.file_name().unwrap_or_default().to_string_lossy().to_string();
+        let pieces: Vec<(&str, _)> = if chunks.is_empty() {
+            vec![(name, target.to_path_buf())]
+        } else {
+            chunks
+                .iter()
+                .enumerate()
+                .map(|(index, chunk)| (chunk.as_str(), target.with_file_name(chunk_name(&target_name, index, chunks.len()))))
+                .collect()
+        };
+
+        for (file_name, path) in &pieces {
+            let mut output = progress(file_name, 0);
+            let request = DownloadRequest {
+                client: self.client.clone(),
+                server_url: self.server_url.clone(),
+                token: self.token.clone(),
+                path: path.clone(),
+                file_name: file_name.to_string(),
+                etag: None,
+                resume: false,
+                key: self.key,
+                // The listing has no checksums of single chunks.
+                sha256: file.info.checksum.clone().filter(|_| chunks.is_empty()),
+            };
+            let result = download_file(&mut output, request).await;
+            finish(&mut output, result).await?;
+        }
+
+        if !chunks.is_empty() {
+            let parts = pieces.into_iter().map(|(_, path)| path).collect();
+            assemble(parts, target.to_path_buf()).await?;
+        }
+        Ok(())
+    }
+}
+
+async fn finish(output: &mut (impl Sink<Progress> + Unpin), result: Result<(), String>) -> Result<(), String> {
+    let progress = match &result {
+        Ok(()) => Progress::Finished,
+        Err(e) => Progress::Errored(e.clone()),
+    };
+    let _ = output.send(progress).await;
+    result
+}
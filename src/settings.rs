@@ -0,0 +1,391 @@
+//! User preferences, kept in `settings.toml` in the config dir.
+//!
+//! Only the small part of TOML this file needs is read and written: `key = value` pairs
+//! with strings, booleans and numbers, grouped under `[table]` headers. Unknown keys
+//! are ignored, so older and newer versions can share the file.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+use iced::Theme;
+
+use crate::columns::{ColumnLayout, FileColumn, SortOrder};
+use crate::utils::config_dir;
+
+const SETTINGS_FILE: &str = "settings.toml";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThemeChoice {
+    Light,
+    Dark,
+}
+
+impl ThemeChoice {
+    pub const ALL: [ThemeChoice; 2] = [ThemeChoice::Light, ThemeChoice::Dark];
+
+    pub fn theme(self) -> Theme {
+        match self {
+            ThemeChoice::Light => Theme::Light,
+            ThemeChoice::Dark => Theme::Dark,
+        }
+    }
+}
+
+impl fmt::Display for ThemeChoice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ThemeChoice::Light => "Light",
+            ThemeChoice::Dark => "Dark",
+        })
+    }
+}
+
+/// How the file list is laid out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileView {
+    List,
+    /// Tiles with thumbnails of images and icons for other files.
+    Grid,
+}
+
+/// Size and position of the main window when it was last closed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WindowGeometry {
+    pub width: f32,
+    pub height: f32,
+    pub x: Option<i32>,
+    pub y: Option<i32>,
+}
+
+impl Default for WindowGeometry {
+    fn default() -> Self {
+        WindowGeometry {
+            width: 1024.0,
+            height: 768.0,
+            x: None,
+            y: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Settings {
+    pub theme: ThemeChoice,
+    /// Where downloads go without asking, unless `always_ask` is on.
+    pub download_dir: Option<PathBuf>,
+    pub always_ask: bool,
+    pub window: WindowGeometry,
+    /// Name of the profile picked on the last successful login.
+    pub last_profile: Option<String>,
+    pub columns: ColumnLayout,
+    pub view: FileView,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            theme: ThemeChoice::Dark,
+            download_dir: None,
+            always_ask: true,
+            window: WindowGeometry::default(),
+            last_profile: None,
+            columns: ColumnLayout::default(),
+            view: FileView::List,
+        }
+    }
+}
+
+impl Settings {
+    pub fn load() -> Self {
+        Settings::from_toml(&fs::read_to_string(settings_path()).unwrap_or_default())
+    }
+
+    fn from_toml(data: &str) -> Self {
+        let values = parse(data);
+        let string = |key: &str| match values.get(key) {
+            Some(Value::String(value)) => Some(value.clone()),
+            _ => None,
+        };
+        let boolean = |key: &str| match values.get(key) {
+            Some(Value::Bool(value)) => Some(*value),
+            _ => None,
+        };
+        let number = |key: &str| match values.get(key) {
+            Some(Value::Number(value)) => Some(*value),
+            _ => None,
+        };
+
+        let defaults = Settings::default();
+        let columns = if values.keys().any(|key| key.starts_with("columns.")) {
+            let default = ColumnLayout::default();
+            ColumnLayout {
+                sort_by: string("columns.sort_by").and_then(|name| parse_column(&name)),
+                order: match string("columns.order").as_deref() {
+                    Some("descending") => SortOrder::Descending,
+                    _ => SortOrder::Ascending,
+                },
+                size_width: number("columns.size_width").map_or(default.size_width, |width| width as f32),
+                modified_width: number("columns.modified_width").map_or(default.modified_width, |width| width as f32),
+                type_width: number("columns.type_width").map_or(default.type_width, |width| width as f32),
+                owner_width: number("columns.owner_width").map_or(default.owner_width, |width| width as f32),
+            }
+        } else {
+            // Layouts saved before there was a settings file.
+            ColumnLayout::load()
+        };
+
+        Settings {
+            theme: match string("theme").as_deref() {
+                Some("light") => ThemeChoice::Light,
+                Some("dark") => ThemeChoice::Dark,
+                _ => defaults.theme,
+            },
+            download_dir: string("download_dir").filter(|dir| !dir.is_empty()).map(PathBuf::from),
+            always_ask: boolean("always_ask").unwrap_or(defaults.always_ask),
+            window: WindowGeometry {
+                width: number("window.width").map_or(defaults.window.width, |width| width as f32),
+                height: number("window.height").map_or(defaults.window.height, |height| height as f32),
+                x: number("window.x").map(|x| x as i32),
+                y: number("window.y").map(|y| y as i32),
+            },
+            last_profile: string("last_profile"),
+            columns,
+            view: match string("view").as_deref() {
+                Some("grid") => FileView::Grid,
+                _ => defaults.view,
+            },
+        }
+    }
+
+    pub fn save(&self) {
+        let path = settings_path();
+
+        if let Some(dir) = path.parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        // Written aside and renamed over the old file, so a crash never leaves half of one.
+        let temp = path.with_extension("toml.tmp");
+        if let Err(e) = fs::write(&temp, self.to_toml()).and_then(|()| fs::rename(&temp, &path)) {
+            eprintln!("Failed to save settings: {}", e);
+            let _ = fs::remove_file(&temp);
+        }
+    }
+
+    fn to_toml(&self) -> String {
+        let mut out = String::new();
+        let theme = match self.theme {
+            ThemeChoice::Light => "light",
+            ThemeChoice::Dark => "dark",
+        };
+        out.push_str(&format!("theme = {}\n", quote(theme)));
+        if let Some(dir) = &self.download_dir {
+            out.push_str(&format!("download_dir = {}\n", quote(&dir.to_string_lossy())));
+        }
+        out.push_str(&format!("always_ask = {}\n", self.always_ask));
+        if let Some(profile) = &self.last_profile {
+            out.push_str(&format!("last_profile = {}\n", quote(profile)));
+        }
+        let view = match self.view {
+            FileView::List => "list",
+            FileView::Grid => "grid",
+        };
+        out.push_str(&format!("view = {}\n", quote(view)));
+
+        out.push_str("\n[window]\n");
+        out.push_str(&format!("width = {:.1}\n", self.window.width));
+        out.push_str(&format!("height = {:.1}\n", self.window.height));
+        if let (Some(x), Some(y)) = (self.window.x, self.window.y) {
+            out.push_str(&format!("x = {}\ny = {}\n", x, y));
+        }
+
+        let columns = &self.columns;
+        out.push_str("\n[columns]\n");
+        if let Some(column) = columns.sort_by {
+            out.push_str(&format!("sort_by = {}\n", quote(column_name(column))));
+        }
+        let order = match columns.order {
+            SortOrder::Ascending => "ascending",
+            SortOrder::Descending => "descending",
+        };
+        out.push_str(&format!("order = {}\n", quote(order)));
+        out.push_str(&format!("size_width = {:.1}\n", columns.size_width));
+        out.push_str(&format!("modified_width = {:.1}\n", columns.modified_width));
+        out.push_str(&format!("type_width = {:.1}\n", columns.type_width));
+        out.push_str(&format!("owner_width = {:.1}\n", columns.owner_width));
+        out
+    }
+}
+
+fn settings_path() -> PathBuf {
+    config_dir().join(SETTINGS_FILE)
+}
+
+fn column_name(column: FileColumn) -> &'static str {
+    match column {
+        FileColumn::Name => "name",
+        FileColumn::Size => "size",
+        FileColumn::Modified => "modified",
+        FileColumn::Type => "type",
+        FileColumn::Owner => "owner",
+    }
+}
+
+fn parse_column(name: &str) -> Option<FileColumn> {
+    [FileColumn::Name, FileColumn::Size, FileColumn::Modified, FileColumn::Type, FileColumn::Owner]
+        .into_iter()
+        .find(|column| column_name(*column) == name)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    String(String),
+    Bool(bool),
+    Number(f64),
+}
+
+/// Flattens the file into `table.key` → value. Lines that don't parse are reported and skipped.
+fn parse(data: &str) -> HashMap<String, Value> {
+    let mut values = HashMap::new();
+    let mut table = String::new();
+
+    for (number, line) in data.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[').and_then(|line| line.strip_suffix(']')) {
+            table = format!("{}.", name.trim());
+            continue;
+        }
+
+        let parsed = line
+            .split_once('=')
+            .and_then(|(key, value)| Some((key.trim(), parse_value(value.trim())?)));
+        match parsed {
+            Some((key, value)) => {
+                values.insert(format!("{}{}", table, key), value);
+            }
+            None => eprintln!("{}:{}: can't read '{}'", SETTINGS_FILE, number + 1, line),
+        }
+    }
+
+    values
+}
+
+fn parse_value(text: &str) -> Option<Value> {
+    if let Some(rest) = text.strip_prefix('"') {
+        return unquote(rest).map(Value::String);
+    }
+    // Anything after the value is a comment.
+    let text = text.split('#').next().unwrap_or_default().trim();
+    match text {
+        "true" => Some(Value::Bool(true)),
+        "false" => Some(Value::Bool(false)),
+        _ => text.replace('_', "").parse().ok().map(Value::Number),
+    }
+}
+
+/// Reads a basic string up to its closing quote, `rest` starts after the opening one.
+fn unquote(rest: &str) -> Option<String> {
+    let mut value = String::new();
+    let mut chars = rest.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => return Some(value),
+            '\\' => value.push(match chars.next()? {
+                'n' => '\n',
+                't' => '\t',
+                'r' => '\r',
+                other => other,
+            }),
+            c => value.push(c),
+        }
+    }
+    None
+}
+
+fn quote(value: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            '\r' => quoted.push_str("\\r"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_through_the_file() {
+        let settings = Settings {
+            theme: ThemeChoice::Light,
+            download_dir: Some(PathBuf::from(r#"C:\Users\Ann "Work"\Downloads\"#)),
+            always_ask: false,
+            window: WindowGeometry { width: 800.5, height: 600.0, x: Some(-20), y: Some(40) },
+            last_profile: Some(String::from("work # \"main\"\tserver")),
+            columns: ColumnLayout {
+                sort_by: Some(FileColumn::Modified),
+                order: SortOrder::Descending,
+                size_width: 90.0,
+                ..ColumnLayout::default()
+            },
+            view: FileView::Grid,
+        };
+        assert_eq!(Settings::from_toml(&settings.to_toml()), settings);
+
+        let defaults = Settings::default();
+        assert_eq!(Settings::from_toml(&defaults.to_toml()), defaults);
+    }
+
+    #[test]
+    fn reads_tables_and_skips_what_it_does_not_know() {
+        let values = parse(
+            "# settings\n\
+             theme = \"light\" # comment\n\
+             future_key = \"kept out of the way\"\n\
+             always_ask = false\n\
+             no equals sign\n\
+             broken = \"unterminated\n\
+             size = maybe\n\
+             [window]\n\
+             width = 1_280\n\
+             [ columns ]\n\
+             order = \"descending\"\n",
+        );
+
+        assert_eq!(values.get("theme"), Some(&Value::String(String::from("light"))));
+        assert_eq!(values.get("always_ask"), Some(&Value::Bool(false)));
+        assert_eq!(values.get("window.width"), Some(&Value::Number(1280.0)));
+        assert_eq!(values.get("columns.order"), Some(&Value::String(String::from("descending"))));
+        assert_eq!(values.get("broken"), None);
+        assert_eq!(values.get("size"), None);
+        assert_eq!(values.len(), 5);
+
+        let settings = Settings::from_toml("theme = \"sepia\"\nview = 3\n[window]\nwidth = \"wide\"\n[columns]\nsort_by = \"colour\"\n");
+        assert_eq!(settings.theme, Settings::default().theme);
+        assert_eq!(settings.view, FileView::List);
+        assert_eq!(settings.window, WindowGeometry::default());
+        assert_eq!(settings.columns.sort_by, None);
+    }
+
+    #[test]
+    fn quotes_and_unquotes_escapes() {
+        for text in ["", "plain", r"C:\Program Files\", "say \"hi\"", "tab\tline\nreturn\r"] {
+            let quoted = quote(text);
+            assert_eq!(unquote(&quoted[1..]).as_deref(), Some(text), "{}", quoted);
+        }
+        assert_eq!(quote(r#"a\"b"#), r#""a\\\"b""#);
+        assert_eq!(unquote(r#"no end\""#), None);
+    }
+}
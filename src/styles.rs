@@ -1,6 +1,9 @@
 use iced::{Background, Border, Color, Shadow, Theme, Vector};
 use iced::widget::{button, container};
 
+use crate::file_info::FileKind;
+use crate::preview::Token;
+
 pub enum ButtonStyle {
     Standard,
     ThemeButton,
@@ -78,6 +81,17 @@ impl button::StyleSheet for ButtonStyle {
     }
 }
 
+/// Highlighted code in the preview, in colors that read on both themes.
+pub fn token_color(token: Token) -> Option<Color> {
+    match token {
+        Token::Plain => None,
+        Token::Keyword => Some(Color::from_rgb(0.58, 0.35, 0.85)),
+        Token::String => Some(Color::from_rgb(0.3, 0.6, 0.3)),
+        Token::Comment => Some(Color::from_rgb(0.5, 0.5, 0.5)),
+        Token::Number => Some(Color::from_rgb(0.85, 0.5, 0.2)),
+    }
+}
+
 pub struct ContainerStyle;
 
 impl container::StyleSheet for ContainerStyle {
@@ -116,3 +130,90 @@ impl container::StyleSheet for FileStyle {
     }
 }
 
+
+pub struct ResizeHandleStyle;
+
+impl container::StyleSheet for ResizeHandleStyle {
+    type Style = Theme;
+
+    fn appearance(&self, theme: &Self::Style) -> container::Appearance {
+        container::Appearance {
+            text_color: Default::default(),
+            border: Border::with_radius(2),
+            background: Some(Background::Color(theme.extended_palette().background.strong.color)),
+            shadow: Shadow::default(),
+        }
+    }
+}
+
+pub struct DropZoneStyle;
+
+impl container::StyleSheet for DropZoneStyle {
+    type Style = Theme;
+
+    fn appearance(&self, theme: &Self::Style) -> container::Appearance {
+        let palette = theme.extended_palette();
+
+        container::Appearance {
+            text_color: Some(palette.primary.strong.text),
+            border: Border {
+                color: palette.primary.strong.color,
+                width: 3.0,
+                radius: 10.into(),
+            },
+            background: Some(Background::Color(Color {
+                a: 0.35,
+                ..palette.primary.base.color
+            })),
+            shadow: Shadow::default(),
+        }
+    }
+}
+
+/// Grid view tiles.
+pub struct TileStyle;
+
+impl container::StyleSheet for TileStyle {
+    type Style = Theme;
+
+    fn appearance(&self, _: &Self::Style) -> container::Appearance {
+        container::Appearance {
+            text_color: Default::default(),
+            border: Border::with_radius(10),
+            background: None,
+            shadow: Shadow {
+                color: Color::BLACK,
+                offset: Vector::new(0.0, 2.0),
+                blur_radius: 20.0,
+            },
+        }
+    }
+}
+
+/// The icon of files without a thumbnail, colored by their kind. `None` is a folder.
+pub struct IconStyle(pub Option<FileKind>);
+
+impl container::StyleSheet for IconStyle {
+    type Style = Theme;
+
+    fn appearance(&self, _: &Self::Style) -> container::Appearance {
+        let color = match self.0 {
+            None => Color::from_rgb(0.85, 0.68, 0.25),
+            Some(FileKind::Image) => Color::from_rgb(0.16, 0.6, 0.55),
+            Some(FileKind::Video) => Color::from_rgb(0.55, 0.3, 0.75),
+            Some(FileKind::Audio) => Color::from_rgb(0.9, 0.55, 0.15),
+            Some(FileKind::Pdf) => Color::from_rgb(0.8, 0.2, 0.2),
+            Some(FileKind::Document) => Color::from_rgb(0.2, 0.45, 0.8),
+            Some(FileKind::Code) => Color::from_rgb(0.25, 0.55, 0.3),
+            Some(FileKind::Archive) => Color::from_rgb(0.6, 0.45, 0.25),
+            Some(FileKind::Other) => Color::from_rgb(0.45, 0.45, 0.45),
+        };
+
+        container::Appearance {
+            text_color: Some(Color::WHITE),
+            border: Border::with_radius(6),
+            background: Some(Background::Color(color)),
+            shadow: Shadow::default(),
+        }
+    }
+}
@@ -0,0 +1,103 @@
+//! Runs the transfers from `transfer` as iced subscriptions, one per running job,
+//! and watches synced folders.
+
+use std::path::PathBuf;
+use std::time::Duration;
+use futures::channel::mpsc;
+use futures::future;
+use futures::never::Never;
+use futures::{SinkExt, StreamExt};
+use iced::{subscription, Subscription};
+use notify::{EventKind, RecursiveMode, Watcher};
+use reqwest::Client;
+
+use crate::app::Message;
+use crate::encryption::EncryptionKey;
+use crate::transfer::{download_file, upload_file, DownloadRequest, Progress, Transfer, UploadRequest};
+
+/// Dropping the subscription (e.g. on cancel) aborts the request.
+pub fn upload(transfer: &Transfer, client: Client, server_url: String, token: String, key: Option<EncryptionKey>) -> Subscription<Message> {
+    let id = transfer.id;
+    let request = UploadRequest {
+        key,
+        ..UploadRequest::new(transfer, client, server_url, token)
+    };
+
+    subscription::channel(("upload", id), 100, move |mut output| async move {
+        let result = {
+            let mut progress = (&mut output).with(move |progress| future::ok::<_, mpsc::SendError>(Message::TransferProgress(id, progress)));
+            upload_file(&mut progress, request).await
+        };
+        finish(&mut output, id, result).await
+    })
+}
+
+pub fn download(transfer: &Transfer, client: Client, server_url: String, token: String, key: Option<EncryptionKey>) -> Subscription<Message> {
+    let id = transfer.id;
+    let request = DownloadRequest {
+        key,
+        ..DownloadRequest::new(transfer, client, server_url, token)
+    };
+
+    subscription::channel(("download", id), 100, move |mut output| async move {
+        let result = {
+            let mut progress = (&mut output).with(move |progress| future::ok::<_, mpsc::SendError>(Message::TransferProgress(id, progress)));
+            download_file(&mut progress, request).await
+        };
+        finish(&mut output, id, result).await
+    })
+}
+
+/// Reports how the transfer ended, then idles until the queue drops the subscription.
+async fn finish(output: &mut mpsc::Sender<Message>, id: usize, result: Result<(), String>) -> Never {
+    let progress = match result {
+        Ok(()) => Progress::Finished,
+        Err(e) => Progress::Errored(e),
+    };
+    let _ = output.send(Message::TransferProgress(id, progress)).await;
+
+    future::pending().await
+}
+
+/// Changes are reported once the folder has been quiet for this long,
+/// so that copying a tree in starts one sync instead of hundreds.
+const SETTLE: Duration = Duration::from_secs(2);
+
+/// Reports changes below `path` to the sync job `id`. Reads and unfinished downloads don't count.
+pub fn watch_folder(id: usize, path: PathBuf) -> Subscription<Message> {
+    subscription::channel(("sync-watch", id, path.clone()), 10, move |mut output| async move {
+        let (events_tx, mut events) = mpsc::unbounded();
+        let watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+            let _ = events_tx.unbounded_send(event);
+        })
+        .and_then(|mut watcher| watcher.watch(&path, RecursiveMode::Recursive).map(|()| watcher));
+
+        // Dropping the watcher stops it, it has to live as long as the subscription.
+        let _watcher = match watcher {
+            Ok(watcher) => watcher,
+            Err(e) => {
+                let _ = output.send(Message::SyncWatchFailed(id, format!("Can't watch {:?}: {}", path, e))).await;
+                match future::pending::<Never>().await {}
+            }
+        };
+
+        let relevant = |event: &notify::Result<notify::Event>| match event {
+            Ok(event) => {
+                !matches!(event.kind, EventKind::Access(_))
+                    && !event.paths.iter().all(|path| path.extension().is_some_and(|extension| extension == "part"))
+            }
+            Err(_) => true,
+        };
+
+        loop {
+            match events.next().await {
+                Some(event) if relevant(&event) => {}
+                Some(_) => continue,
+                None => future::pending::<()>().await,
+            }
+            while let Ok(Some(_)) = tokio::time::timeout(SETTLE, events.next()).await {}
+
+            let _ = output.send(Message::SyncLocalChanged(id)).await;
+        }
+    })
+}
@@ -0,0 +1,572 @@
+//! Two-way sync between a local folder and a server folder, kept in `sync.json`.
+//!
+//! Every synced file remembers how both sides looked after it was last synced, its base.
+//! A side whose size or modification time moved away from the base has changed. When both
+//! sides changed, the file is only taken as unchanged if the server's SHA-256 checksum
+//! matches the local file, anything else is a conflict left to the user.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use chrono::Local;
+use futures::sink;
+use serde::{Deserialize, Serialize};
+
+use crate::checksum::{parse_sha256, sha256_file};
+use crate::chunks::ListedFile;
+use crate::folder_upload::scan;
+use crate::folders::SEPARATOR;
+use crate::remote::Remote;
+use crate::utils::data_dir;
+
+const SYNC_FILE: &str = "sync.json";
+/// More local deletions than this in one run are turned into conflicts.
+const MAX_LOCAL_DELETIONS: usize = 10;
+
+/// Both sides of a file right after it was synced.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Synced {
+    pub size: u64,
+    /// Local modification time, in unix seconds.
+    pub mtime: i64,
+    pub remote_size: Option<u64>,
+    pub remote_modified: Option<i64>,
+    #[serde(default)]
+    pub remote_checksum: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SyncFolder {
+    pub local: PathBuf,
+    /// Server folder mirroring `local`, ending with `/`.
+    pub remote_dir: String,
+    /// Keyed by the path below `local`, with `/` separators.
+    #[serde(default)]
+    pub base: BTreeMap<String, Synced>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LocalFile {
+    pub path: PathBuf,
+    pub size: u64,
+    pub mtime: i64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Conflict {
+    BothChanged,
+    BothAdded,
+    /// Changed here, deleted on the server.
+    DeletedRemotely,
+    /// Deleted here, changed on the server.
+    DeletedLocally,
+    /// Deleted on the server in the same run as many other files, which is more
+    /// likely a short listing than someone clearing the folder out.
+    ManyDeletedRemotely,
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Conflict::BothChanged => "Changed on both sides",
+            Conflict::BothAdded => "Added on both sides",
+            Conflict::DeletedRemotely => "Changed here, deleted on the server",
+            Conflict::DeletedLocally => "Deleted here, changed on the server",
+            Conflict::ManyDeletedRemotely => "Deleted on the server with many other files",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Upload,
+    Download,
+    DeleteRemote,
+    DeleteLocal,
+    /// Moves the local file aside as a conflict copy, then downloads the server's.
+    KeepBoth,
+    /// Both sides already hold the same file, only the base is updated.
+    Record,
+    /// Gone on both sides, only the base is dropped.
+    Forget,
+    Conflict(Conflict),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+    KeepLocal,
+    KeepRemote,
+    KeepBoth,
+}
+
+#[derive(Debug, Clone)]
+pub struct SyncItem {
+    /// Path below the synced folder, on both sides.
+    pub relative: String,
+    pub action: Action,
+    pub local: Option<LocalFile>,
+    pub remote: Option<ListedFile>,
+}
+
+impl SyncItem {
+    /// The conflict settled the way the user picked.
+    pub fn resolve(mut self, resolution: Resolution) -> Self {
+        self.action = match (resolution, self.local.is_some(), self.remote.is_some()) {
+            (Resolution::KeepLocal, true, _) => Action::Upload,
+            (Resolution::KeepLocal, false, _) => Action::DeleteRemote,
+            (Resolution::KeepRemote, _, true) => Action::Download,
+            (Resolution::KeepRemote, _, false) => Action::DeleteLocal,
+            (Resolution::KeepBoth, true, true) => Action::KeepBoth,
+            (Resolution::KeepBoth, true, false) => Action::Upload,
+            (Resolution::KeepBoth, false, _) => Action::Download,
+        };
+        self
+    }
+}
+
+/// Synced folders of every server, keyed by server URL.
+type SyncFile = BTreeMap<String, Vec<SyncFolder>>;
+
+fn sync_path() -> PathBuf {
+    data_dir().join(SYNC_FILE)
+}
+
+fn load_file() -> SyncFile {
+    fs::read_to_string(sync_path())
+        .ok()
+        .and_then(|data| serde_json::from_str(&data).ok())
+        .unwrap_or_default()
+}
+
+pub fn load_folders(server_url: &str) -> Vec<SyncFolder> {
+    load_file().remove(server_url).unwrap_or_default()
+}
+
+pub fn save_folders(server_url: &str, folders: &[SyncFolder]) {
+    let mut file = load_file();
+    if folders.is_empty() {
+        file.remove(server_url);
+    } else {
+        file.insert(server_url.to_string(), folders.to_vec());
+    }
+
+    let path = sync_path();
+    if let Some(dir) = path.parent() {
+        let _ = fs::create_dir_all(dir);
+    }
+    match serde_json::to_string_pretty(&file) {
+        Ok(data) => {
+            if let Err(e) = fs::write(&path, data) {
+                eprintln!("Failed to save synced folders: {}", e);
+            }
+        }
+        Err(e) => eprintln!("Failed to serialize synced folders: {}", e),
+    }
+}
+
+fn mtime(metadata: &fs::Metadata) -> i64 {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |modified| modified.as_secs() as i64)
+}
+
+fn local_file(path: PathBuf) -> Result<LocalFile, String> {
+    let metadata = fs::metadata(&path).map_err(|e| format!("{:?}: {}", path, e))?;
+    Ok(LocalFile {
+        size: metadata.len(),
+        mtime: mtime(&metadata),
+        path,
+    })
+}
+
+/// Files under `root`, honouring `.ftaignore`. Unfinished downloads are left out.
+fn scan_local(root: &Path) -> Result<BTreeMap<String, LocalFile>, String> {
+    let name = root.file_name().unwrap_or_default().to_string_lossy().to_string();
+    let prefix = format!("{}{}", name, SEPARATOR);
+
+    scan(root.to_path_buf(), String::new(), u64::MAX)?
+        .files
+        .into_iter()
+        .filter(|file| !file.relative.ends_with(".part"))
+        .map(|file| {
+            let relative = file.relative.strip_prefix(&prefix).unwrap_or(&file.relative).to_string();
+            Ok((relative, local_file(file.path)?))
+        })
+        .collect()
+}
+
+/// Files of the listing under `remote_dir`, keyed by their path below it. Names that would
+/// land outside the synced folder on disk are left out.
+fn remote_files(listing: &[ListedFile], remote_dir: &str) -> BTreeMap<String, ListedFile> {
+    listing
+        .iter()
+        .filter(|file| !file.info.is_dir && !file.info.name.ends_with(SEPARATOR))
+        .filter_map(|file| {
+            let relative = file.info.name.strip_prefix(remote_dir)?;
+            if !is_safe_relative(relative) {
+                eprintln!("Not syncing {:?}, it points outside the synced folder", file.info.name);
+                return None;
+            }
+            Some((relative.to_string(), file.clone()))
+        })
+        .collect()
+}
+
+/// Plain `/` separated names only: no `..`, `.` or empty segments, nothing absolute and
+/// no backslashes, which are separators on Windows.
+fn is_safe_relative(relative: &str) -> bool {
+    !relative.contains('\\')
+        && relative
+            .split(SEPARATOR)
+            .all(|segment| !segment.is_empty() && segment != "." && segment != "..")
+        && Path::new(relative)
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+}
+
+/// Where `relative` lives below `root`, refusing anything outside it.
+fn local_path(root: &Path, relative: &str) -> Result<PathBuf, String> {
+    let path = root.join(relative);
+    if !is_safe_relative(relative) || !path.starts_with(root) {
+        return Err(format!("{} is outside the synced folder", relative));
+    }
+    Ok(path)
+}
+
+/// `None` when the server has no checksum to compare with.
+fn same_bytes(local: &LocalFile, remote: &ListedFile) -> Option<bool> {
+    if remote.info.size.is_some_and(|size| size != local.size) {
+        return Some(false);
+    }
+    let remote = parse_sha256(remote.info.checksum.as_deref()?)?;
+    Some(sha256_file(&local.path).is_ok_and(|local| local == remote))
+}
+
+fn base_of(local: &LocalFile, remote: &ListedFile) -> Synced {
+    Synced {
+        size: local.size,
+        mtime: local.mtime,
+        remote_size: remote.info.size,
+        remote_modified: remote.info.modified,
+        remote_checksum: remote.info.checksum.clone(),
+    }
+}
+
+fn local_changed(local: &LocalFile, base: &Synced) -> bool {
+    local.size != base.size || local.mtime != base.mtime
+}
+
+fn remote_changed(remote: &ListedFile, base: &Synced) -> bool {
+    remote.info.size != base.remote_size
+        || remote.info.modified != base.remote_modified
+        || remote.info.checksum != base.remote_checksum
+}
+
+/// What to do with every file that is out of step, files in step are left out.
+fn compare(
+    base: &BTreeMap<String, Synced>,
+    mut local: BTreeMap<String, LocalFile>,
+    mut remote: BTreeMap<String, ListedFile>,
+) -> Vec<SyncItem> {
+    let names: BTreeSet<String> = base.keys().chain(local.keys()).chain(remote.keys()).cloned().collect();
+
+    let mut items: Vec<SyncItem> = names
+        .into_iter()
+        .filter_map(|relative| {
+            let local = local.remove(&relative);
+            let remote = remote.remove(&relative);
+
+            let action = match (&local, &remote, base.get(&relative)) {
+                (Some(l), Some(r), Some(b)) => match (local_changed(l, b), remote_changed(r, b)) {
+                    (false, false) => return None,
+                    (true, false) => Action::Upload,
+                    (false, true) => Action::Download,
+                    (true, true) if same_bytes(l, r) == Some(true) => Action::Record,
+                    (true, true) => Action::Conflict(Conflict::BothChanged),
+                },
+                // Same size and no checksum is taken as the same file, so that folders
+                // copied by hand before syncing don't come up as one conflict per file.
+                (Some(l), Some(r), None) => match same_bytes(l, r) {
+                    Some(true) => Action::Record,
+                    None if r.info.size.is_some() => Action::Record,
+                    _ => Action::Conflict(Conflict::BothAdded),
+                },
+                (Some(_), None, None) => Action::Upload,
+                (None, Some(_), None) => Action::Download,
+                (Some(l), None, Some(b)) if local_changed(l, b) => Action::Conflict(Conflict::DeletedRemotely),
+                (Some(_), None, Some(_)) => Action::DeleteLocal,
+                (None, Some(r), Some(b)) if remote_changed(r, b) => Action::Conflict(Conflict::DeletedLocally),
+                (None, Some(_), Some(_)) => Action::DeleteRemote,
+                (None, None, _) => Action::Forget,
+            };
+            Some(SyncItem { relative, action, local, remote })
+        })
+        .collect();
+
+    // Past a handful of files, local deletions wait for the user to confirm them.
+    let deletions = items.iter().filter(|item| item.action == Action::DeleteLocal).count();
+    if deletions > MAX_LOCAL_DELETIONS {
+        for item in items.iter_mut().filter(|item| item.action == Action::DeleteLocal) {
+            item.action = Action::Conflict(Conflict::ManyDeletedRemotely);
+        }
+    }
+    items
+}
+
+/// Looks at both sides of `folder` and says what has to happen.
+pub async fn plan(remote: Remote, folder: SyncFolder) -> Result<Vec<SyncItem>, String> {
+    let listing = remote.listing().await?;
+    let remote_files = remote_files(&listing, &folder.remote_dir);
+    if remote_files.is_empty() && !folder.base.is_empty() {
+        return Err(format!(
+            "The server lists nothing in {}, not syncing so local files aren't deleted",
+            folder.remote_dir
+        ));
+    }
+
+    // Scanning and hashing read the disk.
+    tokio::task::spawn_blocking(move || {
+        if !folder.local.is_dir() {
+            return Err(format!("{:?} is not a folder anymore", folder.local));
+        }
+        let local = scan_local(&folder.local)?;
+        Ok(compare(&folder.base, local, remote_files))
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// `name.ext` -> `name (conflict 2024-05-01 1530).ext`.
+fn conflict_copy(path: &Path) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = path.extension().map(|extension| format!(".{}", extension.to_string_lossy()));
+    let stamp = Local::now().format("%Y-%m-%d %H%M");
+
+    path.with_file_name(format!("{} (conflict {}){}", stem, stamp, extension.as_deref().unwrap_or("")))
+}
+
+/// Carries out one item. `Ok(false)` when the file is gone on both sides afterwards.
+async fn apply_item(remote: &Remote, folder: &SyncFolder, item: &SyncItem, limit: u64) -> Result<bool, String> {
+    let name = format!("{}{}", folder.remote_dir, item.relative);
+    let target = local_path(&folder.local, &item.relative)?;
+    let missing = || format!("{} changed while syncing", item.relative);
+
+    match item.action {
+        Action::Upload => {
+            let local = item.local.as_ref().ok_or_else(missing)?;
+            // A file that was or will be split may not end up with the same chunk names.
+            if let Some(existing) = item.remote.as_ref().filter(|existing| !existing.chunks.is_empty() || local.size > limit) {
+                remote.delete(existing.server_names()).await?;
+            }
+            remote.upload(&local.path, &name, local.size, limit, |_, _| sink::drain()).await?;
+            Ok(true)
+        }
+        Action::Download | Action::KeepBoth => {
+            let file = item.remote.as_ref().ok_or_else(missing)?;
+            if item.action == Action::KeepBoth {
+                tokio::fs::rename(&target, conflict_copy(&target)).await.map_err(|e| e.to_string())?;
+            }
+            if let Some(dir) = target.parent() {
+                tokio::fs::create_dir_all(dir).await.map_err(|e| e.to_string())?;
+            }
+            remote.download(file, &target, |_, _| sink::drain()).await?;
+            Ok(true)
+        }
+        Action::DeleteRemote => {
+            let file = item.remote.as_ref().ok_or_else(missing)?;
+            remote.delete(file.server_names()).await?;
+            Ok(false)
+        }
+        Action::DeleteLocal => {
+            tokio::fs::remove_file(&target).await.map_err(|e| e.to_string())?;
+            Ok(false)
+        }
+        Action::Record => Ok(true),
+        Action::Forget => Ok(false),
+        Action::Conflict(conflict) => Err(format!("{}: {}", item.relative, conflict)),
+    }
+}
+
+/// Carries out `items` one after the other and returns the new base of each file,
+/// `None` for files that are gone on both sides now.
+pub async fn apply(remote: Remote, folder: SyncFolder, items: Vec<SyncItem>, limit: u64) -> Vec<(String, Result<Option<Synced>, String>)> {
+    let mut results = vec![];
+    for item in &items {
+        let result = apply_item(&remote, &folder, item, limit).await;
+        results.push((item.relative.clone(), result));
+    }
+
+    // The server's side of the base is only known once it lists the uploads.
+    let listing = remote.listing().await.map(|listing| remote_files(&listing, &folder.remote_dir));
+
+    results
+        .into_iter()
+        .map(|(relative, result)| {
+            let base = result.and_then(|kept| {
+                if !kept {
+                    return Ok(None);
+                }
+                let local = local_file(local_path(&folder.local, &relative)?)?;
+                let listing = listing.as_ref().map_err(Clone::clone)?;
+                let remote = listing
+                    .get(&relative)
+                    .ok_or_else(|| format!("{} is not on the server after syncing", relative))?;
+                Ok(Some(base_of(&local, remote)))
+            });
+            (relative, base)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_info::FileInfo;
+
+    fn local(size: u64, mtime: i64) -> LocalFile {
+        LocalFile { path: PathBuf::from("/nonexistent"), size, mtime }
+    }
+
+    fn remote(name: &str, size: u64, modified: i64) -> ListedFile {
+        ListedFile {
+            info: FileInfo {
+                name: name.to_string(),
+                size: Some(size),
+                modified: Some(modified),
+                ..Default::default()
+            },
+            chunks: vec![],
+        }
+    }
+
+    fn base(size: u64, mtime: i64, remote_size: u64, remote_modified: i64) -> Synced {
+        Synced {
+            size,
+            mtime,
+            remote_size: Some(remote_size),
+            remote_modified: Some(remote_modified),
+            remote_checksum: None,
+        }
+    }
+
+    fn actions(
+        base: &[(&str, Synced)],
+        local: &[(&str, LocalFile)],
+        remote: &[(&str, ListedFile)],
+    ) -> Vec<(String, Action)> {
+        let owned = |name: &&str| name.to_string();
+        compare(
+            &base.iter().map(|(name, synced)| (owned(name), synced.clone())).collect(),
+            local.iter().map(|(name, file)| (owned(name), file.clone())).collect(),
+            remote.iter().map(|(name, file)| (owned(name), file.clone())).collect(),
+        )
+        .into_iter()
+        .map(|item| (item.relative, item.action))
+        .collect()
+    }
+
+    #[test]
+    fn compares_against_the_base() {
+        let synced = base(10, 100, 10, 200);
+        let found = actions(
+            &[("same", synced.clone()), ("up", synced.clone()), ("down", synced.clone()), ("both", synced.clone())],
+            &[("same", local(10, 100)), ("up", local(11, 100)), ("down", local(10, 100)), ("both", local(10, 101))],
+            &[
+                ("same", remote("same", 10, 200)),
+                ("up", remote("up", 10, 200)),
+                ("down", remote("down", 10, 201)),
+                ("both", remote("both", 12, 200)),
+            ],
+        );
+        assert_eq!(found, vec![
+            ("both".to_string(), Action::Conflict(Conflict::BothChanged)),
+            ("down".to_string(), Action::Download),
+            ("up".to_string(), Action::Upload),
+        ]);
+    }
+
+    #[test]
+    fn compares_new_and_deleted_files() {
+        let synced = base(10, 100, 10, 200);
+        let found = actions(
+            &[
+                ("gone here", synced.clone()),
+                ("gone there", synced.clone()),
+                ("changed, gone here", synced.clone()),
+                ("changed, gone there", synced.clone()),
+                ("gone", synced.clone()),
+            ],
+            &[
+                ("new here", local(1, 1)),
+                ("copied", local(5, 1)),
+                ("clash", local(5, 1)),
+                ("gone there", local(10, 100)),
+                ("changed, gone there", local(10, 999)),
+            ],
+            &[
+                ("new there", remote("new there", 1, 1)),
+                ("copied", remote("copied", 5, 2)),
+                ("clash", remote("clash", 6, 2)),
+                ("gone here", remote("gone here", 10, 200)),
+                ("changed, gone here", remote("changed, gone here", 10, 999)),
+            ],
+        );
+        assert_eq!(found, vec![
+            ("changed, gone here".to_string(), Action::Conflict(Conflict::DeletedLocally)),
+            ("changed, gone there".to_string(), Action::Conflict(Conflict::DeletedRemotely)),
+            ("clash".to_string(), Action::Conflict(Conflict::BothAdded)),
+            ("copied".to_string(), Action::Record),
+            ("gone".to_string(), Action::Forget),
+            ("gone here".to_string(), Action::DeleteRemote),
+            ("gone there".to_string(), Action::DeleteLocal),
+            ("new here".to_string(), Action::Upload),
+            ("new there".to_string(), Action::Download),
+        ]);
+    }
+
+    #[test]
+    fn asks_before_deleting_many_local_files() {
+        let names: Vec<String> = (0..=MAX_LOCAL_DELETIONS).map(|i| format!("file {:02}", i)).collect();
+        let synced: Vec<(&str, Synced)> = names.iter().map(|name| (name.as_str(), base(10, 100, 10, 200))).collect();
+        let here: Vec<(&str, LocalFile)> = names.iter().map(|name| (name.as_str(), local(10, 100))).collect();
+
+        let found = actions(&synced, &here, &[]);
+        assert_eq!(found.len(), MAX_LOCAL_DELETIONS + 1);
+        assert!(found.iter().all(|(_, action)| *action == Action::Conflict(Conflict::ManyDeletedRemotely)));
+
+        // One fewer is still deleted without asking.
+        let found = actions(&synced[1..], &here[1..], &[]);
+        assert!(found.iter().all(|(_, action)| *action == Action::DeleteLocal));
+    }
+
+    #[test]
+    fn skips_names_outside_the_folder() {
+        let listing: Vec<ListedFile> = [
+            "docs/a.txt",
+            "docs/sub/b.txt",
+            "docs/dir/../../.bashrc",
+            "docs/../x",
+            "docs//c",
+            "docs/./d",
+            "docs/sub\\..\\e",
+            "docs//etc/passwd",
+            "other/f",
+        ]
+        .iter()
+        .map(|name| remote(name, 1, 1))
+        .collect();
+
+        let names: Vec<String> = remote_files(&listing, "docs/").into_keys().collect();
+        assert_eq!(names, vec!["a.txt", "sub/b.txt"]);
+
+        let root = Path::new("/home/user/docs");
+        assert_eq!(local_path(root, "sub/b.txt"), Ok(root.join("sub").join("b.txt")));
+        assert!(local_path(root, "../b.txt").is_err());
+        assert!(local_path(root, "/etc/passwd").is_err());
+        assert!(local_path(root, "").is_err());
+    }
+}
@@ -0,0 +1,64 @@
+//! Small pictures of image files for the grid view.
+//!
+//! They are made from the file fetched for its preview and kept as PNG in the
+//! [`THUMBNAIL_CACHE`] under the same key, so a changed file gets a new one. Thumbnails of
+//! encrypted files are never written to disk.
+
+use std::io::Cursor;
+use image::ImageFormat;
+
+use crate::cache::{self, cache_dir, cache_key, THUMBNAIL_CACHE};
+use crate::chunks::ListedFile;
+use crate::encryption::encrypted_size;
+use crate::file_info::FileKind;
+use crate::preview::{fetch, WHOLE_LIMIT};
+use crate::remote::Remote;
+
+/// Longest side in pixels.
+pub const THUMBNAIL_SIZE: u32 = 128;
+
+/// Whether a thumbnail may be made of `file`, images that are stored whole and not too big.
+pub fn has_thumbnail(file: &ListedFile) -> bool {
+    file.chunks.is_empty()
+        && file.info.kind() == FileKind::Image
+        && !file.info.mime().starts_with("image/svg")
+        && file.info.size.is_none_or(|size| size <= encrypted_size(WHOLE_LIMIT))
+}
+
+/// The PNG thumbnail of `file`, made on first use.
+pub async fn load(remote: Remote, file: ListedFile) -> Result<Vec<u8>, String> {
+    let dir = cache_dir(THUMBNAIL_CACHE);
+    let key = format!("{}.png", cache_key(&remote.server_url, &file.info));
+    if let Some(png) = cache::read(&dir, &key) {
+        return Ok(png);
+    }
+
+    let fetched = fetch(&remote, &file, WHOLE_LIMIT).await?;
+    if !fetched.complete {
+        return Err(String::from("Too big for a thumbnail"));
+    }
+    let bytes = fetched.bytes;
+    let png = tokio::task::spawn_blocking(move || make_thumbnail(&bytes))
+        .await
+        .map_err(|e| e.to_string())??;
+
+    if !fetched.encrypted {
+        if let Err(e) = cache::write(&dir, &key, &png) {
+            eprintln!("Failed to cache the thumbnail of {}: {}", file.info.name, e);
+        }
+    }
+    Ok(png)
+}
+
+fn make_thumbnail(bytes: &[u8]) -> Result<Vec<u8>, String> {
+    let mut image = image::load_from_memory(bytes).map_err(|e| e.to_string())?;
+    // Small images are kept as they are rather than blown up.
+    if image.width() > THUMBNAIL_SIZE || image.height() > THUMBNAIL_SIZE {
+        image = image.thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE);
+    }
+    let mut png = Cursor::new(vec![]);
+    image
+        .write_to(&mut png, ImageFormat::Png)
+        .map_err(|e| e.to_string())?;
+    Ok(png.into_inner())
+}
@@ -0,0 +1,608 @@
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use futures::channel::mpsc;
+use futures::{Sink, SinkExt, StreamExt, TryStreamExt};
+use reqwest::header::{CONTENT_RANGE, ETAG, IF_RANGE, RANGE};
+use reqwest::StatusCode;
+use reqwest::multipart::{Form, Part};
+use reqwest::{Body, Client};
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt, Take};
+use tokio_util::io::ReaderStream;
+
+use crate::checksum::{mismatch, parse_sha256, sha256_range, Sha256, CHECKSUM_HEADER, CHECKSUM_MISMATCH};
+use crate::encryption::{encrypt_stream, encrypted_size, has_header, Decryptor, EncryptionKey, MAGIC_LEN, NO_KEY};
+use crate::journal::fingerprint;
+use crate::permissions::status_error;
+
+const CHUNK_SIZE: usize = 64 * 1024;
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone)]
+pub enum Progress {
+    Started(u64),
+    /// The first `n` bytes were already transferred by an earlier attempt.
+    Resumed(u64),
+    Advanced(u64),
+    /// Upload session id for uploads, remote ETag for downloads.
+    Resumable(String),
+    /// SHA-256 of an upload's source, kept so that a resume doesn't read the file again.
+    Hashed(String),
+    Finished,
+    Errored(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Kind {
+    Upload,
+    Download,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum State {
+    Queued,
+    Running,
+    Paused,
+    Failed(String),
+    Done,
+}
+
+#[derive(Debug, Clone)]
+pub struct Transfer {
+    pub id: usize,
+    pub kind: Kind,
+    pub file_name: String,
+    pub path: PathBuf,
+    pub total: u64,
+    pub done: u64,
+    resumed_at: u64,
+    pub started: Instant,
+    pub state: State,
+    pub checksum: Option<String>,
+    pub upload_id: Option<String>,
+    /// Where the uploaded bytes begin inside `path`, non-zero for chunks of a split file.
+    pub source_offset: u64,
+    /// Continue from what is already on the other side instead of starting over.
+    pub resume: bool,
+    /// SHA-256 the listing gave for a download, to check it against, or the one an
+    /// upload sends along.
+    pub sha256: Option<String>,
+    /// Encrypt an upload with the server key, as the profile said when it was queued.
+    pub encrypt: bool,
+}
+
+impl Transfer {
+    pub fn new(id: usize, kind: Kind, file_name: String, path: PathBuf, total: u64) -> Self {
+        Transfer {
+            id,
+            kind,
+            file_name,
+            path,
+            total,
+            done: 0,
+            resumed_at: 0,
+            started: Instant::now(),
+            state: State::Queued,
+            checksum: None,
+            upload_id: None,
+            source_offset: 0,
+            resume: false,
+            sha256: None,
+            encrypt: false,
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.state == State::Running
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.state == State::Done
+    }
+
+    pub fn update(&mut self, progress: &Progress) {
+        match progress {
+            Progress::Started(total) => {
+                self.total = *total;
+                self.started = Instant::now();
+            }
+            Progress::Resumed(offset) => {
+                self.done = *offset;
+                self.resumed_at = *offset;
+                self.started = Instant::now();
+            }
+            Progress::Advanced(done) => self.done = *done,
+            Progress::Finished => {
+                self.done = self.total;
+                self.state = State::Done;
+            }
+            Progress::Resumable(_) => {}
+            Progress::Hashed(sha256) => self.sha256 = Some(sha256.clone()),
+            Progress::Errored(e) => self.state = State::Failed(e.clone()),
+        }
+    }
+
+    /// Puts a paused or failed transfer back in line, continuing where it stopped.
+    pub fn requeue(&mut self) {
+        self.state = State::Queued;
+        self.resume = true;
+    }
+
+    pub fn percent(&self) -> f32 {
+        if self.total == 0 {
+            return 100.0;
+        }
+        (self.done as f64 / self.total as f64 * 100.0) as f32
+    }
+
+    /// Average speed in bytes per second since the transfer (or its resume) started.
+    pub fn throughput(&self) -> f64 {
+        let elapsed = self.started.elapsed().as_secs_f64();
+        if elapsed <= 0.0 {
+            return 0.0;
+        }
+        self.done.saturating_sub(self.resumed_at) as f64 / elapsed
+    }
+
+    pub fn eta(&self) -> Option<Duration> {
+        let throughput = self.throughput();
+        if throughput <= 0.0 {
+            return None;
+        }
+        let left = self.total.saturating_sub(self.done) as f64;
+        Some(Duration::from_secs_f64(left / throughput))
+    }
+}
+
+/// Everything [`upload_file`] needs, taken from a [`Transfer`] or filled in by hand.
+#[derive(Debug, Clone)]
+pub struct UploadRequest {
+    pub client: Client,
+    pub server_url: String,
+    pub token: String,
+    pub path: PathBuf,
+    pub file_name: String,
+    pub total: u64,
+    pub source_offset: u64,
+    pub checksum: Option<String>,
+    /// Session of an earlier attempt to continue, `None` starts a new one.
+    pub upload_id: Option<String>,
+    /// SHA-256 an earlier attempt worked out, used while the file's `checksum` still matches.
+    pub sha256: Option<String>,
+    /// Encrypt the file on the way, it fails without `key`.
+    pub encrypt: bool,
+    /// Key to encrypt with.
+    pub key: Option<EncryptionKey>,
+}
+
+impl UploadRequest {
+    pub fn new(transfer: &Transfer, client: Client, server_url: String, token: String) -> Self {
+        UploadRequest {
+            client,
+            server_url,
+            token,
+            path: transfer.path.clone(),
+            file_name: transfer.file_name.clone(),
+            total: transfer.total,
+            source_offset: transfer.source_offset,
+            checksum: transfer.checksum.clone(),
+            upload_id: transfer.upload_id.clone().filter(|_| transfer.resume),
+            sha256: transfer.sha256.clone(),
+            encrypt: transfer.encrypt,
+            key: None,
+        }
+    }
+
+    /// Bytes that go to the server.
+    fn size(&self) -> u64 {
+        match self.key {
+            Some(_) => encrypted_size(self.total),
+            None => self.total,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct UploadSession {
+    upload_id: String,
+}
+
+#[derive(Deserialize)]
+struct UploadOffset {
+    offset: u64,
+}
+
+/// Streams `request.path` to the server.
+///
+/// Uploads go through a resumable session when the server supports it:
+///
+/// * `POST {server}/files/uploads` with `{"filename", "size", "checksum"}` answers `{"upload_id"}`;
+/// * `GET {server}/files/uploads/{upload_id}` answers `{"offset"}`, the number of bytes already stored;
+/// * `PUT {server}/files/uploads/{upload_id}` with `Content-Range: bytes {offset}-{last}/{size}`
+///   appends the raw body and completes the file once `size` bytes are stored.
+///
+/// Servers answering 404 to the first call get the plain multipart `files/upload` instead.
+/// The SHA-256 of the file goes along as `sha256` and in a [`CHECKSUM_HEADER`], a server
+/// that stored other bytes answers 422. It is reported as [`Progress::Hashed`] the first
+/// time, resumes take it from the request.
+///
+/// Encrypted uploads always start over, their bytes differ on every attempt. They carry no
+/// checksum either, the cipher checks them on the way back.
+/// Progress goes to `output`, dropping the future (e.g. on cancel) aborts the request.
+pub async fn upload_file(output: &mut (impl Sink<Progress> + Unpin), mut request: UploadRequest) -> Result<(), String> {
+    if request.encrypt && request.key.is_none() {
+        return Err(String::from(NO_KEY));
+    }
+    request.key = request.key.filter(|_| request.encrypt);
+
+    let sha256 = match request.key {
+        Some(_) => None,
+        None => Some(source_sha256(output, &request).await?),
+    };
+
+    let upload_id = match request.upload_id.clone().filter(|_| request.key.is_none()) {
+        Some(upload_id) => upload_id,
+        None => match create_upload_session(&request, sha256.as_deref()).await? {
+            Some(upload_id) => {
+                let _ = output.send(Progress::Resumable(upload_id.clone())).await;
+                upload_id
+            }
+            None => return multipart_upload(output, request, sha256).await,
+        },
+    };
+
+    let url = format!("{}/files/uploads/{}", request.server_url, upload_id);
+
+    let offset: UploadOffset = request.client.get(&url)
+        .header("Authorization", format!("Bearer {}", request.token))
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| e.to_string())?
+        .json()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let size = request.size();
+    if offset.offset >= size {
+        return Ok(());
+    }
+    let _ = output.send(Progress::Resumed(offset.offset)).await;
+
+    let (body, sent_rx) = upload_body(&request, offset.offset).await?;
+    let content_range = format!("bytes {}-{}/{}", offset.offset, size - 1, size);
+
+    let mut send = request.client.put(&url)
+        .body(body)
+        .header(CONTENT_RANGE, content_range)
+        .header("Authorization", format!("Bearer {}", request.token));
+    if let Some(sha256) = sha256 {
+        send = send.header(CHECKSUM_HEADER, sha256);
+    }
+    let send = send.send();
+
+    let response = send_with_progress(output, offset.offset, request.total, send, sent_rx).await;
+    check_status(response)
+}
+
+/// Returns `None` when the server has no resumable upload endpoint.
+async fn create_upload_session(request: &UploadRequest, sha256: Option<&str>) -> Result<Option<String>, String> {
+    let response = request.client.post(format!("{}/files/uploads", request.server_url))
+        .json(&serde_json::json!({
+            "filename": request.file_name,
+            "size": request.size(),
+            "checksum": request.checksum,
+            "sha256": sha256,
+        }))
+        .header("Authorization", format!("Bearer {}", request.token))
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    match response.status() {
+        StatusCode::NOT_FOUND | StatusCode::METHOD_NOT_ALLOWED => Ok(None),
+        status if status.is_success() => {
+            let session: UploadSession = response.json().await.map_err(|e| e.to_string())?;
+            Ok(Some(session.upload_id))
+        }
+        status => Err(status_error("upload files", status)),
+    }
+}
+
+async fn multipart_upload(output: &mut (impl Sink<Progress> + Unpin), request: UploadRequest, sha256: Option<String>) -> Result<(), String> {
+    let (body, sent_rx) = upload_body(&request, 0).await?;
+    let part = Part::stream_with_length(body, request.size()).file_name(request.file_name.clone());
+    let form = Form::new().part("files", part);
+
+    let mut send = request.client.post(format!("{}/files/upload", request.server_url))
+        .multipart(form)
+        .header("Authorization", format!("Bearer {}", request.token));
+    if let Some(sha256) = sha256 {
+        send = send.header(CHECKSUM_HEADER, sha256);
+    }
+    let send = send.send();
+
+    let response = send_with_progress(output, 0, request.total, send, sent_rx).await;
+    check_status(response)
+}
+
+async fn source_sha256(output: &mut (impl Sink<Progress> + Unpin), request: &UploadRequest) -> Result<String, String> {
+    if let Some(sha256) = request.sha256.clone().filter(|_| fingerprint(&request.path) == request.checksum) {
+        return Ok(sha256);
+    }
+
+    let (path, offset, length) = (request.path.clone(), request.source_offset, request.total);
+    let sha256 = tokio::task::spawn_blocking(move || sha256_range(&path, offset, length))
+        .await
+        .unwrap_or_else(|e| Err(e.to_string()))?;
+    let _ = output.send(Progress::Hashed(sha256.clone())).await;
+    Ok(sha256)
+}
+
+/// Opens the bytes of `request` that are still to be sent, starting `offset` bytes in.
+async fn open_source(request: &UploadRequest, offset: u64) -> Result<Take<tokio::fs::File>, String> {
+    let mut file = tokio::fs::File::open(&request.path).await.map_err(|e| e.to_string())?;
+    file.seek(std::io::SeekFrom::Start(request.source_offset + offset)).await.map_err(|e| e.to_string())?;
+
+    Ok(file.take(request.total - offset))
+}
+
+/// A streaming body of what is left to send from `offset` on, encrypted if `request` has a key.
+/// The receiver gets the number of source bytes in every chunk handed to reqwest.
+async fn upload_body(request: &UploadRequest, offset: u64) -> Result<(Body, mpsc::UnboundedReceiver<u64>), String> {
+    let file = open_source(request, offset).await?;
+    let (sent_tx, sent_rx) = mpsc::unbounded::<u64>();
+
+    let body = match &request.key {
+        Some(key) => {
+            let stream = encrypt_stream(file, request.total, key)?
+                .inspect_ok(move |(_, read)| {
+                    let _ = sent_tx.unbounded_send(*read);
+                })
+                .map_ok(|(sealed, _)| sealed);
+            Body::wrap_stream(stream)
+        }
+        None => {
+            let stream = ReaderStream::with_capacity(file, CHUNK_SIZE)
+                .inspect_ok(move |chunk| {
+                    let _ = sent_tx.unbounded_send(chunk.len() as u64);
+                });
+            Body::wrap_stream(stream)
+        }
+    };
+    Ok((body, sent_rx))
+}
+
+async fn send_with_progress(
+    output: &mut (impl Sink<Progress> + Unpin),
+    offset: u64,
+    total: u64,
+    send: impl std::future::Future<Output = reqwest::Result<reqwest::Response>>,
+    mut sent_rx: mpsc::UnboundedReceiver<u64>,
+) -> reqwest::Result<reqwest::Response> {
+    tokio::pin!(send);
+
+    let mut sent = offset;
+    let mut last_report = Instant::now();
+    loop {
+        tokio::select! {
+            response = &mut send => break response,
+            Some(n) = sent_rx.next() => {
+                sent += n;
+                if last_report.elapsed() >= PROGRESS_INTERVAL || sent == total {
+                    last_report = Instant::now();
+                    let _ = output.send(Progress::Advanced(sent)).await;
+                }
+            }
+        }
+    }
+}
+
+fn check_status(response: reqwest::Result<reqwest::Response>) -> Result<(), String> {
+    match response {
+        Ok(response) if response.status().is_success() => Ok(()),
+        Ok(response) if response.status() == StatusCode::UNPROCESSABLE_ENTITY => {
+            Err(format!("{}, the server received other bytes than were sent", CHECKSUM_MISMATCH))
+        }
+        Ok(response) => Err(status_error("upload files", response.status())),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+pub fn part_path(path: &Path) -> PathBuf {
+    let mut part: OsString = path.as_os_str().to_owned();
+    part.push(".part");
+    PathBuf::from(part)
+}
+
+#[derive(Debug, Clone)]
+pub struct DownloadRequest {
+    pub client: Client,
+    pub server_url: String,
+    pub token: String,
+    /// Where the file ends up, next to its `.part` file while downloading.
+    pub path: PathBuf,
+    pub file_name: String,
+    /// ETag of the `.part` file's contents, so a changed file is fetched from the start.
+    pub etag: Option<String>,
+    pub resume: bool,
+    /// Key for files whose header says they are encrypted.
+    pub key: Option<EncryptionKey>,
+    /// Expected SHA-256 when the server sends none with the file.
+    pub sha256: Option<String>,
+}
+
+impl DownloadRequest {
+    pub fn new(transfer: &Transfer, client: Client, server_url: String, token: String) -> Self {
+        DownloadRequest {
+            client,
+            server_url,
+            token,
+            path: transfer.path.clone(),
+            file_name: transfer.file_name.clone(),
+            etag: transfer.checksum.clone(),
+            resume: transfer.resume,
+            key: None,
+            sha256: transfer.sha256.clone(),
+        }
+    }
+}
+
+/// Downloads `file_name` into `request.path`, going through a `.part` file
+/// that is only renamed into place once the whole body has been written.
+/// The `.part` file survives failures and pauses, a resumed download asks
+/// for the rest of the file with a `Range` request. It holds the bytes as stored, files
+/// whose header says they are encrypted are only decrypted once all of it is there.
+///
+/// The bytes are checked against the SHA-256 in the response's [`CHECKSUM_HEADER`], or
+/// `request.sha256` without one. A file that doesn't match is thrown away.
+pub async fn download_file(output: &mut (impl Sink<Progress> + Unpin), request: DownloadRequest) -> Result<(), String> {
+    let DownloadRequest { client, server_url, token, path, file_name, etag, resume, key, sha256 } = request;
+    let part = part_path(&path);
+
+    let offset = match tokio::fs::metadata(&part).await {
+        Ok(metadata) if resume => metadata.len(),
+        _ => 0,
+    };
+
+    let mut request = client.get(format!("{}/files/", server_url))
+        .json(&file_name)
+        .header("Authorization", format!("Bearer {}", token));
+    if offset > 0 {
+        request = request.header(RANGE, format!("bytes={}-", offset));
+        if let Some(etag) = &etag {
+            request = request.header(IF_RANGE, etag);
+        }
+    }
+
+    let mut response = request.send().await.map_err(|e| e.to_string())?;
+
+    let expected = response.headers()
+        .get(CHECKSUM_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(parse_sha256)
+        .or_else(|| sha256.as_deref().and_then(parse_sha256));
+
+    // The `.part` file already holds the whole body, the previous run only missed the rename.
+    if response.status() == StatusCode::RANGE_NOT_SATISFIABLE && offset > 0 {
+        if let Some(expected) = &expected {
+            let mut hasher = Sha256::new();
+            hash_part(&part, &mut hasher).await?;
+            check_part(&part, expected, hasher).await?;
+        }
+        return finish_part(&part, &path, key).await;
+    }
+
+    if !response.status().is_success() {
+        return Err(status_error("download files", response.status()));
+    }
+
+    if let Some(etag) = response.headers().get(ETAG).and_then(|etag| etag.to_str().ok()) {
+        let _ = output.send(Progress::Resumable(etag.to_string())).await;
+    }
+
+    // Anything but 206 means the server ignored the range and sends the whole file again.
+    let offset = if response.status() == StatusCode::PARTIAL_CONTENT { offset } else { 0 };
+    let total = response.content_length().map(|length| length + offset).unwrap_or(0);
+    let _ = output.send(Progress::Started(total)).await;
+
+    // Checksums are over the stored bytes, before any decryption.
+    let mut hasher = expected.as_ref().map(|_| Sha256::new());
+    if let Some(hasher) = hasher.as_mut().filter(|_| offset > 0) {
+        hash_part(&part, hasher).await?;
+    }
+
+    let mut file = if offset > 0 {
+        tokio::fs::OpenOptions::new().append(true).open(&part).await
+    } else {
+        tokio::fs::File::create(&part).await
+    }.map_err(|e| e.to_string())?;
+
+    let mut received = offset;
+    let _ = output.send(Progress::Resumed(received)).await;
+
+    let mut last_report = Instant::now();
+    while let Some(chunk) = response.chunk().await.map_err(|e| e.to_string())? {
+        file.write_all(&chunk).await.map_err(|e| e.to_string())?;
+        if let Some(hasher) = &mut hasher {
+            hasher.update(&chunk);
+        }
+        received += chunk.len() as u64;
+
+        if last_report.elapsed() >= PROGRESS_INTERVAL {
+            last_report = Instant::now();
+            let _ = output.send(Progress::Advanced(received)).await;
+        }
+    }
+
+    file.flush().await.map_err(|e| e.to_string())?;
+    drop(file);
+
+    if let (Some(expected), Some(hasher)) = (&expected, hasher) {
+        check_part(&part, expected, hasher).await?;
+    }
+    finish_part(&part, &path, key).await
+}
+
+/// Moves the complete `part` to `path`, decrypting it if it is stored encrypted.
+async fn finish_part(part: &Path, path: &Path, key: Option<EncryptionKey>) -> Result<(), String> {
+    let mut file = tokio::fs::File::open(part).await.map_err(|e| e.to_string())?;
+    let mut start = [0; MAGIC_LEN];
+    if file.read_exact(&mut start).await.is_err() || !has_header(&start) {
+        drop(file);
+        return tokio::fs::rename(part, path).await.map_err(|e| e.to_string());
+    }
+    // Kept, the download is done once a key is imported.
+    let key = key.ok_or_else(|| String::from(NO_KEY))?;
+
+    // Into a file of its own, a failure mustn't leave half a file at `path`.
+    let plain = part_path(part);
+    file.rewind().await.map_err(|e| e.to_string())?;
+    if let Err(e) = decrypt(file, &plain, key).await {
+        let _ = tokio::fs::remove_file(&plain).await;
+        let _ = tokio::fs::remove_file(part).await;
+        return Err(e);
+    }
+    tokio::fs::rename(&plain, path).await.map_err(|e| e.to_string())?;
+    let _ = tokio::fs::remove_file(part).await;
+    Ok(())
+}
+
+async fn decrypt(mut source: tokio::fs::File, target: &Path, key: EncryptionKey) -> Result<(), String> {
+    let mut file = tokio::fs::File::create(target).await.map_err(|e| e.to_string())?;
+    let mut decryptor = Decryptor::new(key);
+    let mut buffer = vec![0; CHUNK_SIZE];
+    loop {
+        let read = source.read(&mut buffer).await.map_err(|e| e.to_string())?;
+        if read == 0 {
+            break;
+        }
+        file.write_all(&decryptor.update(&buffer[..read])?).await.map_err(|e| e.to_string())?;
+    }
+    file.write_all(&decryptor.finish()?).await.map_err(|e| e.to_string())?;
+    file.flush().await.map_err(|e| e.to_string())
+}
+
+/// Feeds what an earlier attempt left in `part` to `hasher`.
+async fn hash_part(part: &Path, hasher: &mut Sha256) -> Result<(), String> {
+    let mut file = tokio::fs::File::open(part).await.map_err(|e| e.to_string())?;
+    let mut buffer = vec![0; CHUNK_SIZE];
+    loop {
+        let read = file.read(&mut buffer).await.map_err(|e| e.to_string())?;
+        if read == 0 {
+            return Ok(());
+        }
+        hasher.update(&buffer[..read]);
+    }
+}
+
+/// Removes `part` if it doesn't hash to `expected`, so a retry fetches it from the start.
+async fn check_part(part: &Path, expected: &str, hasher: Sha256) -> Result<(), String> {
+    let actual = hasher.finish();
+    if actual == expected {
+        return Ok(());
+    }
+    let _ = tokio::fs::remove_file(part).await;
+    Err(mismatch(expected, &actual))
+}
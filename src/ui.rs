@@ -1,28 +1,73 @@
-use iced::{theme, Alignment, Element, Length, Padding};
+use iced::{theme, Alignment, ContentFit, Element, Font, Length, Padding};
 use iced::alignment::{Horizontal, Vertical};
-use iced::widget::{button, container, text, Button, Checkbox, Column, Container, Image, Row, Scrollable, Space, TextInput};
-use crate::styles::{ContainerStyle, ButtonStyle, FileStyle};
-use crate::app::{App, LoginField, Message, Page};
+use iced::widget::{button, container, horizontal_rule, image, mouse_area, pick_list, progress_bar, scrollable, text, tooltip, Button, Text, Checkbox, Column, Container, Image, Row, Scrollable, Space, TextInput};
+use crate::styles::{token_color, ContainerStyle, ButtonStyle, DropZoneStyle, FileStyle, IconStyle, ResizeHandleStyle, TileStyle};
+use crate::app::{App, LoginField, Message, Page, PreviewPane, ProfileEditor, ProfileField, RejectedFile, Session, SyncJob, Thumbnail, Toast};
+use crate::queue::TransferQueue;
+use crate::transfer::{Kind, State, Transfer};
+use crate::utils::{format_duration, format_size};
+use crate::file_info::{format_timestamp, FileInfo, FileKind};
+use crate::cache::cache_key;
+use crate::columns::{ColumnLayout, FileColumn, SortOrder};
+use crate::folder_upload::FolderUpload;
+use crate::checksum::CHECKSUM_MISMATCH;
+use crate::collision::Collision;
+use crate::encryption::{is_encrypted, plain_name};
+use crate::folders::{base_name, breadcrumbs, is_direct_child, subfolders};
+use crate::jwt::Claims;
+use crate::permissions::Permission;
+use crate::preview::{Block, Content, Preview, Span};
+use crate::thumbnail::THUMBNAIL_SIZE;
+use crate::profiles::Profile;
+use crate::settings::{FileView, ThemeChoice};
+use crate::sync::{Action, Resolution};
 
-pub fn view(app: &App) -> Element<Message> {
+pub fn view(app: &App) -> Element<'_, Message> {
     let content =
-        match app.page {
-            Page::Login => log_in_page(&app.login_field, app.login_error.clone()),
-            Page::Main => main_page(app)
+        match app.session.page {
+            Page::Login => {
+                let form = match &app.profile_editor {
+                    Some(editor) => profile_editor(editor, !app.profiles.is_empty()),
+                    None => log_in_page(&app.session.login_field, app.session.login_error.clone(), app.saved_login),
+                };
+                let back = (!app.background.is_empty()).then(|| small_btn("Back", Message::BackToSessions));
+                // A tab that was logged in before keeps its server, only a new one can pick.
+                let server: Element<'_, Message> = if app.session.token.is_empty() {
+                    profile_bar(&app.profiles, app.session.profile).push_maybe(back).into()
+                } else {
+                    Row::new()
+                        .push(text(format!("Session expired on {}", session_label(app, &app.session))).size(16))
+                        .push_maybe(back)
+                        .spacing(10)
+                        .align_items(Alignment::Center)
+                        .into()
+                };
+                container(
+                    Column::new()
+                        .push(server)
+                        .push(form)
+                        .spacing(10)
+                        .align_items(Alignment::Center)
+                )
+            }
+            Page::Main => main_page(app),
+            Page::Settings => settings_page(app),
+            Page::Sync => sync_page(app),
         };
 
 
     let wrapper =  Column::new();
 
     let wrapper =
-        match app.page {
-            Page::Login => wrapper.spacing(10)
+        match app.session.page {
+            Page::Login | Page::Settings | Page::Sync => wrapper.spacing(10)
                 .width(Length::Fill)
                 .align_items(Alignment::Center)
                 .push(content)
-                .push(page_footer(app.page.clone(), &app.search_text)),
+                .push(page_footer(app.session.page.clone(), &app.session.search_text, app.collision, &app.session.claims)),
 
-            Page::Main => wrapper.push(page_footer(app.page.clone(), &app.search_text))
+            Page::Main => wrapper.push(page_footer(app.session.page.clone(), &app.session.search_text, app.collision, &app.session.claims))
+                .push(session_tabs(app))
                 .spacing(10)
                 .width(Length::Fill)
                 .align_items(Alignment::Center)
@@ -36,8 +81,8 @@ pub fn view(app: &App) -> Element<Message> {
         .center_x()
         .style(theme::Container::Custom(Box::new(ContainerStyle)));
 
-    let container = match app.page {
-        Page::Login => temp_container.center_y(),
+    let container = match app.session.page {
+        Page::Login | Page::Settings | Page::Sync => temp_container.center_y(),
         Page::Main => temp_container.align_y(Vertical::Top),
     };
     container.width(Length::Fill).height(Length::Fill).into()
@@ -46,28 +91,110 @@ pub fn view(app: &App) -> Element<Message> {
 #[derive(Debug, Clone)]
 pub struct PackageRow {
     pub checked: bool,
-    pub filename: String,
+    pub info: FileInfo,
+    /// Server names of the pieces of a split file, empty for plain files.
+    pub chunks: Vec<String>,
+    /// Stored under an encrypted name, shown without the suffix.
+    pub encrypted: bool,
 }
 
 impl PackageRow {
-    pub fn new(filename: String) -> Self {
+    pub fn new(info: FileInfo) -> Self {
         PackageRow {
             checked: false,
-            filename,
+            encrypted: is_encrypted(&info.name),
+            info,
+            chunks: vec![],
+        }
+    }
+
+    pub fn chunked(info: FileInfo, chunks: Vec<String>) -> Self {
+        PackageRow {
+            chunks,
+            ..PackageRow::new(info)
         }
     }
-    pub fn view(&self, index: usize) -> Container<'static, Message> {
-        let row = Row::new()
+
+    /// Names to address on the server for this row.
+    pub fn server_names(&self) -> Vec<String> {
+        if self.chunks.is_empty() {
+            vec![self.info.name.clone()]
+        } else {
+            self.chunks.clone()
+        }
+    }
+
+    /// `dir` is stripped from the shown name. Buttons the claims don't allow leave a gap,
+    /// so the columns stay lined up with the header.
+    pub fn view(&self, index: usize, download: Option<&Transfer>, columns: &ColumnLayout, dir: &str, claims: &Claims) -> Container<'static, Message> {
+        let info = &self.info;
+
+        let mut details = format!("Uploaded: {}", format_timestamp(info.created));
+        if let Some(checksum) = &info.checksum {
+            details.push_str(&format!("\nChecksum: {}", checksum));
+        }
+        if !self.chunks.is_empty() {
+            details.push_str(&format!("\nStored in {} parts", self.chunks.len()));
+        }
+        if self.encrypted {
+            details.push_str("\nEncrypted end to end");
+        }
+
+        let shown = plain_name(info.name.strip_prefix(dir).unwrap_or(&info.name)).to_string();
+        let name: Element<'static, Message> = tooltip(
+            Row::new()
+                .push(text(shown).size(20))
+                .push_maybe(self.encrypted.then(|| text("encrypted").size(14)))
+                .spacing(10)
+                .align_items(Alignment::Center),
+            container(text(details).size(14))
+                .padding(Padding::from(10))
+                .style(theme::Container::Box),
+            tooltip::Position::Bottom,
+        ).into();
+        // Clicking the name shows the file in the preview panel.
+        let name = if claims.can(Permission::Download) {
+            mouse_area(name).on_press(Message::OpenPreview(info.name.clone())).into()
+        } else {
+            name
+        };
+
+        // Dragging the handle onto another tab copies the file to that server.
+        let handle: Element<'static, Message> = if claims.can(Permission::Download) {
+            mouse_area(text("⠿").size(20).width(20))
+                .on_press(Message::StartDrag(index))
+                .into()
+        } else {
+            Space::with_width(20).into()
+        };
+
+        let mut row = Row::new()
             .push(Space::with_width(10))
-            .push(Space::with_width(20))
+            .push(handle)
             .push(Checkbox::new("", self.checked).on_toggle(move |_| Message::ToggleCheck(index)))
-            .push(text(self.filename.to_string()).size(20))
-            .push(Space::with_width(Length::Fill))
-            .push(download_btn(self.filename.clone()))
-            .push(Space::with_width(20))
-            .push(del_btn(index))
+            .push(container(name).width(Length::Fill))
+            .push(column_text(info.size.map(|size| format_size(size as f64)), columns.size_width))
+            .push(column_text(Some(format_timestamp(info.modified.or(info.created))), columns.modified_width))
+            .push(column_text(Some(info.mime()), columns.type_width))
+            .push(column_text(info.owner.clone(), columns.owner_width));
+
+        row = match download {
+            Some(download) => row.push(download_progress(download)),
+            None if claims.can(Permission::Download) => row.push(download_btn(info.name.clone())),
+            None => row.push(Space::with_width(32)),
+        };
+
+        row = row.push(Space::with_width(20));
+        row = if claims.can(Permission::Delete) {
+            row.push(del_btn(index))
+        } else {
+            row.push(Space::with_width(32))
+        };
+
+        let row = row
             .push(Space::with_width(10))
             .height(60)
+            .spacing(10)
             .align_items(Alignment::Center);
 
         container(row)
@@ -75,7 +202,70 @@ impl PackageRow {
     }
 }
 
-pub fn page_footer(page: Page, search_text: &String) -> Container<'static, Message> {
+const RESIZE_HANDLE_WIDTH: f32 = 6.0;
+
+fn column_text(value: Option<String>, width: f32) -> Text<'static> {
+    text(value.unwrap_or_else(|| String::from("-")))
+        .size(15)
+        .width(width)
+}
+
+/// Column titles above the file list, lined up with [`PackageRow::view`].
+/// Clicking a title sorts by it, dragging the bar on its right edge resizes it.
+pub fn table_header(columns: &ColumnLayout, all_checked: bool) -> Container<'static, Message> {
+    let row = Row::new()
+        .push(Space::with_width(10))
+        .push(Space::with_width(20))
+        .push(Checkbox::new("", all_checked).on_toggle(Message::SelectAll))
+        .push(header_cell("Name", FileColumn::Name, columns))
+        .push(header_cell("Size", FileColumn::Size, columns))
+        .push(header_cell("Modified", FileColumn::Modified, columns))
+        .push(header_cell("Type", FileColumn::Type, columns))
+        .push(header_cell("Owner", FileColumn::Owner, columns))
+        .push(Space::with_width(32))
+        .push(Space::with_width(20))
+        .push(Space::with_width(32))
+        .push(Space::with_width(10))
+        .height(40)
+        .spacing(10)
+        .align_items(Alignment::Center);
+
+    container(row)
+        .padding(Padding::from([0, 15, 0, 5]))
+}
+
+fn header_cell(label: &str, column: FileColumn, columns: &ColumnLayout) -> Element<'static, Message> {
+    let arrow = match (columns.sort_by == Some(column), columns.order) {
+        (true, SortOrder::Ascending) => " ↑",
+        (true, SortOrder::Descending) => " ↓",
+        (false, _) => "",
+    };
+
+    let title = button(text(format!("{}{}", label, arrow)).size(15))
+        .on_press(Message::SortBy(column))
+        .padding(0)
+        .style(theme::Button::Custom(Box::new(ButtonStyle::Transparent)));
+
+    match columns.width(column) {
+        Some(width) => {
+            let handle = mouse_area(
+                container(Space::new(RESIZE_HANDLE_WIDTH, 24))
+                    .style(theme::Container::Custom(Box::new(ResizeHandleStyle)))
+            )
+                .on_press(Message::ResizeColumn(column));
+
+            Row::new()
+                .push(title.width(Length::Fill))
+                .push(handle)
+                .width(width)
+                .align_items(Alignment::Center)
+                .into()
+        }
+        None => title.width(Length::Fill).into(),
+    }
+}
+
+pub fn page_footer(page: Page, search_text: &str, collision: Collision, claims: &Claims) -> Container<'static, Message> {
     let mut footer = Row::new();
 
         if page == Page::Main {
@@ -84,7 +274,7 @@ pub fn page_footer(page: Page, search_text: &String) -> Container<'static, Messa
                     search_input_field("Search...", search_text)
                         .on_input(
                             |search| {
-                                Message::SearchFieldChanged(search.to_lowercase())
+                                Message::SearchFieldChanged(search)
                             }
                         )
                 )
@@ -92,9 +282,22 @@ pub fn page_footer(page: Page, search_text: &String) -> Container<'static, Messa
                     .style(theme::Button::Custom(Box::new(ButtonStyle::Standard))))
                 .push(button("Unselect all").on_press(Message::SelectAll(false))
                     .style(theme::Button::Custom(Box::new(ButtonStyle::Standard))))
+        }
+        if page == Page::Main && claims.can(Permission::Download) {
+            footer = footer
+                .push(button("Download selected").on_press(Message::DownloadSelected(false))
+                    .style(theme::Button::Custom(Box::new(ButtonStyle::Standard))))
+                .push(button("As zip").on_press(Message::DownloadSelected(true))
+                    .style(theme::Button::Custom(Box::new(ButtonStyle::Standard))))
+                .push(pick_list(&Collision::ALL[..], Some(collision), Message::CollisionChanged))
+        }
+        if page == Page::Main && claims.can(Permission::Delete) {
+            footer = footer
                 .push(button("Delete selected").on_press(Message::DeleteSelected)
                     .style(theme::Button::Custom(Box::new(ButtonStyle::DeleteButton))))
-                .push(Space::with_width(Length::Fill))
+        }
+        if page == Page::Main {
+            footer = footer.push(Space::with_width(Length::Fill))
         }
 
         footer = footer
@@ -102,19 +305,33 @@ pub fn page_footer(page: Page, search_text: &String) -> Container<'static, Messa
                 .on_press(Message::ToggleTheme)
                 .style(theme::Button::Custom(Box::new(ButtonStyle::ThemeButton)))
             )
+            .push_maybe((page != Page::Settings).then(|| {
+                button("Settings")
+                    .on_press(Message::OpenSettings)
+                    .style(theme::Button::Custom(Box::new(ButtonStyle::ThemeButton)))
+            }))
         .align_items(Alignment::Center)
         .spacing(10);
-    if page == Page::Main {
+    if page == Page::Main && claims.can(Permission::Upload) {
         footer = footer
             .push(button("Upload files").on_press(Message::UploadFiles)
                 .style(theme::Button::Custom(Box::new(ButtonStyle::Standard))))
-            .push(refresh_btn());
+            .push(button("Upload folder").on_press(Message::UploadFolder)
+                .style(theme::Button::Custom(Box::new(ButtonStyle::Standard))));
+    }
+    if page == Page::Main && claims.can(Permission::Upload) && claims.can(Permission::Download) {
+        footer = footer
+            .push(button("Sync").on_press(Message::OpenSync)
+                .style(theme::Button::Custom(Box::new(ButtonStyle::Standard))));
+    }
+    if page == Page::Main {
+        footer = footer.push(refresh_btn());
     }
 
     container(footer).center_y().padding(Padding::from(10))
 
 }
-pub fn log_in_page(login_field: &LoginField, login_error: Option<String>) -> Container<Message> {
+pub fn log_in_page(login_field: &LoginField, login_error: Option<String>, saved_login: bool) -> Container<'_, Message> {
     let mut column = Column::new()
         .push(text("File Transferring App"))
         .push(
@@ -132,8 +349,23 @@ pub fn log_in_page(login_field: &LoginField, login_error: Option<String>) -> Con
                         Message::LoginFieldChanged(login_field.login.clone(), password)
                     }
                 )
+                .secure(true)
         )
+        .push(Checkbox::new("Remember me", login_field.remember).on_toggle(Message::RememberToggled))
+        .push_maybe((login_field.remember || saved_login).then(|| {
+            log_in_input_field("Passphrase for the saved login", &login_field.passphrase)
+                .on_input(Message::PassphraseChanged)
+                .secure(true)
+        }))
         .push(submit_btn("Log In", Message::LoginSubmit))
+        .push_maybe(saved_login.then(|| {
+            Row::new()
+                .push(small_btn("Unlock saved login", Message::UnlockSaved))
+                .push(button(text("Forget").size(14))
+                    .on_press(Message::ForgetSaved)
+                    .style(theme::Button::Custom(Box::new(ButtonStyle::DeleteButton))))
+                .spacing(10)
+        }))
         .padding(Padding::from([50, 20]))
         .align_items(Alignment::Center)
         .spacing(40);
@@ -155,6 +387,313 @@ pub fn log_in_page(login_field: &LoginField, login_error: Option<String>) -> Con
     }
 }
 
+pub fn settings_page(app: &App) -> Container<'_, Message> {
+    let settings = &app.settings;
+
+    let download_dir = match &settings.download_dir {
+        Some(dir) => dir.to_string_lossy().to_string(),
+        None => String::from("Not set, asked on every download"),
+    };
+    let profiles: Vec<String> = app.profiles.iter().map(|profile| profile.name.clone()).collect();
+    let window = &settings.window;
+    let position = match (window.x, window.y) {
+        (Some(x), Some(y)) => format!(" at {}, {}", x, y),
+        _ => String::new(),
+    };
+
+    let column = Column::new()
+        .push(text("Settings"))
+        .push(settings_row("Theme", pick_list(&ThemeChoice::ALL[..], Some(settings.theme), Message::ThemeChanged).into()))
+        .push(settings_row(
+            "Download folder",
+            Row::new()
+                .push(text(download_dir).size(16).width(Length::Fill))
+                .push(small_btn("Choose...", Message::ChooseDownloadDir))
+                .push_maybe(settings.download_dir.as_ref().map(|_| small_btn("Clear", Message::ClearDownloadDir)))
+                .spacing(10)
+                .align_items(Alignment::Center)
+                .into(),
+        ))
+        .push(settings_row(
+            "",
+            Checkbox::new("Always ask where to save", settings.always_ask || settings.download_dir.is_none())
+                .on_toggle_maybe(settings.download_dir.as_ref().map(|_| Message::AlwaysAskToggled))
+                .into(),
+        ))
+        .push(settings_row(
+            "Server on start",
+            pick_list(profiles, settings.last_profile.clone(), Message::LastProfileChanged)
+                .placeholder("First in the list")
+                .into(),
+        ))
+        .push(settings_row(
+            "Window",
+            Row::new()
+                .push(text(format!("{:.0} x {:.0}{}", window.width, window.height, position)).size(16).width(Length::Fill))
+                .push(small_btn("Reset", Message::ResetWindow))
+                .spacing(10)
+                .align_items(Alignment::Center)
+                .into(),
+        ))
+        .push(settings_row(
+            "File columns",
+            Row::new()
+                .push(text("Sort order and widths are kept as you change them").size(16).width(Length::Fill))
+                .push(small_btn("Reset", Message::ResetColumns))
+                .spacing(10)
+                .align_items(Alignment::Center)
+                .into(),
+        ))
+        .push(submit_btn("Done", Message::CloseSettings))
+        .width(Length::Fixed(700.0))
+        .padding(Padding::from([30, 20]))
+        .align_items(Alignment::Center)
+        .spacing(25);
+
+    container(column)
+        .padding(Padding::from(20))
+        .style(theme::Container::Custom(Box::new(ContainerStyle)))
+}
+
+pub fn sync_page(app: &App) -> Container<'static, Message> {
+    let mut column = Column::new()
+        .push(text("Synced folders"))
+        .spacing(20);
+
+    if app.session.sync.is_empty() {
+        column = column.push(
+            text("Pick a local folder to keep in step with the current server folder").size(16)
+        );
+    }
+    for job in &app.session.sync {
+        column = column.push(sync_job(job));
+    }
+
+    column = column.push(
+        Row::new()
+            .push(small_btn("Add folder...", Message::AddSyncFolder))
+            .push(submit_btn("Done", Message::CloseSync))
+            .spacing(10)
+            .align_items(Alignment::Center)
+    );
+
+    container(Scrollable::new(
+        column
+            .width(Length::Fixed(700.0))
+            .padding(Padding::from([30, 20]))
+            .align_items(Alignment::Center)
+    ))
+        .padding(Padding::from(20))
+        .style(theme::Container::Custom(Box::new(ContainerStyle)))
+}
+
+fn sync_job(job: &SyncJob) -> Column<'static, Message> {
+    let status = if job.running {
+        String::from("Syncing...")
+    } else if job.last_run.is_none() {
+        String::from("Not synced yet")
+    } else {
+        format!("Last synced {}", format_timestamp(job.last_run))
+    };
+
+    let mut column = Column::new()
+        .push(
+            Row::new()
+                .push(text(format!("{} \u{2194} {}", job.folder.local.display(), job.folder.remote_dir)).size(16).width(Length::Fill))
+                .push(small_btn("Sync now", Message::SyncNow(job.id)))
+                .push(button(text("Remove").size(14))
+                    .on_press(Message::RemoveSyncFolder(job.id))
+                    .style(theme::Button::Custom(Box::new(ButtonStyle::DeleteButton))))
+                .spacing(10)
+                .align_items(Alignment::Center)
+        )
+        .push(text(status).size(14))
+        .spacing(8);
+
+    for item in &job.pending {
+        column = column.push(text(format!("{}: {}", sync_action(item.action), item.relative)).size(14));
+    }
+    for item in &job.conflicts {
+        let reason = match item.action {
+            Action::Conflict(conflict) => conflict.to_string(),
+            _ => String::new(),
+        };
+        column = column.push(
+            Row::new()
+                .push(text(format!("{} ({})", item.relative, reason)).size(14).width(Length::Fill))
+                .push(small_btn("Keep mine", Message::ResolveConflict(job.id, item.relative.clone(), Resolution::KeepLocal)))
+                .push(small_btn("Keep server's", Message::ResolveConflict(job.id, item.relative.clone(), Resolution::KeepRemote)))
+                .push(small_btn("Keep both", Message::ResolveConflict(job.id, item.relative.clone(), Resolution::KeepBoth)))
+                .spacing(10)
+                .align_items(Alignment::Center)
+        );
+    }
+    for (file, error) in &job.errors {
+        let line = if file.is_empty() { error.clone() } else { format!("{}: {}", file, error) };
+        column = column.push(
+            text(line)
+                .size(14)
+                .style(theme::Text::Color(iced::Color::from_rgb(1.0, 0.0, 0.0)))
+        );
+    }
+    if !job.synced.is_empty() {
+        column = column.push(text(format!("Recently synced: {}", job.synced.join(", "))).size(14));
+    }
+    column
+}
+
+fn sync_action(action: Action) -> &'static str {
+    match action {
+        Action::Upload => "Upload",
+        Action::Download => "Download",
+        Action::DeleteRemote => "Delete on the server",
+        Action::DeleteLocal => "Delete here",
+        Action::KeepBoth => "Keep both",
+        Action::Record => "Up to date",
+        Action::Forget => "Forget",
+        Action::Conflict(_) => "Conflict",
+    }
+}
+
+fn settings_row<'a>(label: &str, control: Element<'a, Message>) -> Row<'a, Message> {
+    Row::new()
+        .push(text(label).size(16).width(Length::Fixed(160.0)))
+        .push(container(control).width(Length::Fill))
+        .spacing(20)
+        .align_items(Alignment::Center)
+}
+
+/// Server switcher above the login form.
+pub fn profile_bar(profiles: &[Profile], selected: Option<usize>) -> Row<'static, Message> {
+    let names: Vec<String> = profiles.iter().map(|profile| profile.name.clone()).collect();
+    let selected = selected.map(|index| names[index].clone());
+    let has_selection = selected.is_some();
+
+    Row::new()
+        .push(text("Server").size(16))
+        .push(pick_list(names, selected, Message::ProfileSelected)
+            .placeholder("No servers yet")
+            .width(Length::Fixed(300.0)))
+        .push(small_btn("Add", Message::NewProfile))
+        .push_maybe(has_selection.then(|| small_btn("Edit", Message::EditProfile)))
+        .push_maybe(has_selection.then(|| {
+            button(text("Remove").size(14))
+                .on_press(Message::RemoveProfile)
+                .style(theme::Button::Custom(Box::new(ButtonStyle::DeleteButton)))
+        }))
+        .spacing(10)
+        .align_items(Alignment::Center)
+}
+
+/// `can_cancel` is false when there is no other profile to go back to.
+pub fn profile_editor(editor: &ProfileEditor, can_cancel: bool) -> Container<'_, Message> {
+    let title = if editor.index.is_some() { "Edit server" } else { "Add a server" };
+
+    let mut column = Column::new()
+        .push(text(title))
+        .push(log_in_input_field("Name", &editor.name)
+            .on_input(|value| Message::ProfileFieldChanged(ProfileField::Name, value)))
+        .push(log_in_input_field("URL, like https://files.example.com", &editor.url)
+            .on_input(|value| Message::ProfileFieldChanged(ProfileField::Url, value)))
+        .push(log_in_input_field("Default username (optional)", &editor.username)
+            .on_input(|value| Message::ProfileFieldChanged(ProfileField::Username, value)))
+        .push(log_in_input_field("CA certificate PEM file (optional)", &editor.ca_certificate)
+            .on_input(|value| Message::ProfileFieldChanged(ProfileField::CaCertificate, value)))
+        .push(Checkbox::new("Accept invalid certificates (test servers only)", editor.accept_invalid_certs)
+            .on_toggle(Message::InvalidCertsToggled))
+        .push(log_in_input_field("Proxy, like socks5://127.0.0.1:1080 (optional)", &editor.proxy)
+            .on_input(|value| Message::ProfileFieldChanged(ProfileField::Proxy, value)))
+        .push(Checkbox::new("Encrypt uploads end to end", editor.encrypt)
+            .on_toggle(Message::EncryptToggled))
+        .push(
+            Row::new()
+                .push(log_in_input_field("Passphrase for key export and import", &editor.key_passphrase)
+                    .on_input(|value| Message::ProfileFieldChanged(ProfileField::KeyPassphrase, value))
+                    .secure(true))
+                .push(small_btn("Export key...", Message::ExportKey))
+                .push(small_btn("Import key...", Message::ImportKey))
+                .spacing(10)
+                .align_items(Alignment::Center)
+        )
+        .push_maybe(editor.key_status.as_ref().map(|status| match status {
+            Ok(note) => text(note).size(16),
+            Err(e) => text(e).size(16).style(theme::Text::Color(iced::Color::from_rgb(1.0, 0.0, 0.0))),
+        }))
+        .push_maybe(editor.key_import.as_ref().map(|_| {
+            Row::new()
+                .push(small_btn("Import anyway", Message::ConfirmKeyImport))
+                .push(small_btn("Cancel", Message::CancelKeyImport))
+                .spacing(10)
+        }))
+        .push(submit_btn("Save", Message::SaveProfile))
+        .push_maybe(can_cancel.then(|| small_btn("Cancel", Message::CancelProfileEdit)))
+        .padding(Padding::from([30, 20]))
+        .align_items(Alignment::Center)
+        .spacing(20);
+
+    if let Some(error) = &editor.error {
+        column = column.push(
+            text(error)
+                .size(16)
+                .style(theme::Text::Color(iced::Color::from_rgb(1.0, 0.0, 0.0)))
+        );
+    }
+
+    container(column)
+        .padding(Padding::from(20))
+        .style(theme::Container::Custom(Box::new(ContainerStyle)))
+}
+
+/// Profile name, or the server URL for sessions not tied to a profile.
+fn session_label(app: &App, session: &Session) -> String {
+    let server = session.profile
+        .and_then(|index| app.profiles.get(index))
+        .map(|profile| profile.name.clone())
+        .unwrap_or_else(|| session.server.url.clone());
+
+    match &session.claims.sub {
+        Some(user) => format!("{} ({})", server, user),
+        None => server,
+    }
+}
+
+/// One tab per session. While files are dragged, releasing over a tab drops them there.
+pub fn session_tabs(app: &App) -> Container<'static, Message> {
+    let mut row = Row::new()
+        .spacing(5)
+        .align_items(Alignment::Center);
+
+    for session in app.sessions() {
+        let mut label = session_label(app, session);
+        if session.token.is_empty() {
+            label = String::from("New session");
+        } else if !session.is_logged_in() {
+            label.push_str(" - expired");
+        }
+
+        let style = if session.id == app.session.id { ButtonStyle::ThemeButton } else { ButtonStyle::Standard };
+        let tab = Row::new()
+            .push(button(text(label).size(14))
+                .on_press(Message::ShowSession(session.id))
+                .style(theme::Button::Custom(Box::new(style))))
+            .push(button(text("x").size(14))
+                .on_press(Message::CloseSession(session.id))
+                .style(theme::Button::Custom(Box::new(ButtonStyle::Transparent))))
+            .align_items(Alignment::Center);
+
+        row = match app.dragging {
+            Some(_) => row.push(mouse_area(tab).on_release(Message::DropOnSession(session.id))),
+            None => row.push(tab),
+        };
+    }
+
+    row = row.push(small_btn("+", Message::NewSession));
+
+    container(row)
+        .width(Length::Fill)
+        .padding(Padding::from([0, 15, 0, 15]))
+}
+
 pub fn main_page(app: &App) -> Container<'static, Message> {
     let mut column = Column::new()
         .width(Length::Fill)
@@ -162,12 +701,54 @@ pub fn main_page(app: &App) -> Container<'static, Message> {
 
     column = column.push(Space::with_height(0));
 
+    for (index, toast) in app.toasts.iter().enumerate() {
+        column = column.push(toast_row(index, toast));
+    }
 
-    for (index, package) in app.packages.iter().enumerate() {
-        if package.filename
-            .to_lowercase()
-            .contains(app.search_text.as_str()) || app.search_text == "" {
-            column = column.push(package.view(index));
+    if let Some(files) = &app.dragging {
+        column = column.push(
+            container(
+                text(format!("Drop on another tab to copy {} file(s) to that server", files.len())).size(18)
+            )
+                .padding(Padding::from([15, 30]))
+                .width(Length::Fill)
+                .style(theme::Container::Custom(Box::new(DropZoneStyle)))
+        );
+    }
+
+    for (index, rejected) in app.session.rejected.iter().enumerate() {
+        column = column.push(rejected_row(index, rejected, app.session.size_limit));
+    }
+
+    for (index, upload) in app.session.folder_uploads.iter().enumerate() {
+        column = column.push(folder_upload_summary(index, upload, app.session.size_limit));
+    }
+
+    let pending = app.journal.pending(&app.session.server.url, app.session.transfers.jobs());
+    if !pending.is_empty() {
+        column = column.push(resume_banner(pending.len()));
+    }
+
+    let dir = app.session.current_dir.as_str();
+    let folders = if is_searching(app) {
+        vec![]
+    } else {
+        let names = app.session.packages.iter().map(|package| package.info.name.as_str());
+        subfolders(names, &app.session.folders, dir)
+    };
+
+    if app.settings.view == FileView::Grid {
+        column = column.push(file_grid(app, folders, visible_files(app)));
+    } else {
+        for folder in folders {
+            column = column.push(folder_row(app, folder));
+        }
+        for index in visible_files(app) {
+            let package = &app.session.packages[index];
+            let download = package.server_names()
+                .iter()
+                .find_map(|name| app.session.transfers.download_of(name));
+            column = column.push(package.view(index, download, &app.settings.columns, dir, &app.session.claims));
         }
     }
 
@@ -178,11 +759,715 @@ pub fn main_page(app: &App) -> Container<'static, Message> {
 
     let scrollable = Scrollable::new(column);
 
+    let all_checked = !app.session.packages.is_empty() && app.session.packages.iter().all(|package| package.checked);
+    let table = Column::new()
+        .push(folder_bar(app))
+        .push(search_bar(app))
+        .push_maybe((app.settings.view == FileView::List).then(|| table_header(&app.settings.columns, all_checked)))
+        .push(scrollable);
+
+    // iced 0.12 has no stacking widget, so the drop zone takes the place of the list while hovering.
+    let table: Element<'static, Message> = if app.drop_hover {
+        drop_zone(&app.session.current_dir).into()
+    } else {
+        table.into()
+    };
 
-    container(scrollable)
+    let file_list = container(table)
         .style(theme::Container::Custom(Box::new(ContainerStyle)))
         .align_y(Vertical::Top)
+        .width(Length::FillPortion(3));
+
+    if app.session.transfers.is_empty() && app.session.preview.is_none() {
+        return file_list;
+    }
 
+    let side = Column::new()
+        .push_maybe(app.session.preview.as_ref().map(preview_panel))
+        .push_maybe((!app.session.transfers.is_empty()).then(|| transfers_panel(&app.session.transfers)))
+        .spacing(10)
+        .width(Length::FillPortion(2));
+
+    let row = Row::new()
+        .push(file_list)
+        .push(side)
+        .spacing(10);
+
+    container(row)
+        .style(theme::Container::Custom(Box::new(ContainerStyle)))
+        .align_y(Vertical::Top)
+}
+
+/// While searching, the whole subtree of the current folder is searched and shown flat.
+fn is_searching(app: &App) -> bool {
+    app.session.search.as_ref().is_ok_and(|query| !query.filters.is_empty())
+}
+
+/// Indices of the packages shown in the current folder, in the sort order.
+pub fn visible_files(app: &App) -> Vec<usize> {
+    let searching = is_searching(app);
+    let dir = app.session.current_dir.as_str();
+
+    let mut visible: Vec<usize> = app.session.packages
+        .iter()
+        .enumerate()
+        .filter(|(_, package)| {
+            if searching {
+                package.info.name.starts_with(dir)
+            } else {
+                is_direct_child(&package.info.name, dir)
+            }
+        })
+        // A query that does not parse filters nothing, the error is shown above the table.
+        .filter(|(_, package)| app.session.search.as_ref().map_or(true, |query| query.matches(&package.info)))
+        .map(|(index, _)| index)
+        .collect();
+    visible.sort_by(|a, b| app.settings.columns.compare(&app.session.packages[*a].info, &app.session.packages[*b].info));
+    visible
+}
+
+const TILE_WIDTH: f32 = 150.0;
+const TILE_SPACING: f32 = 10.0;
+
+/// Folders and then files as tiles, as many to a row as fit next to the side panel.
+fn file_grid(app: &App, folders: Vec<String>, files: Vec<usize>) -> Column<'static, Message> {
+    let side_panel = !app.session.transfers.is_empty() || app.session.preview.is_some();
+    // The list gets 3 of 5 parts of the window next to the panel, see `main_page`.
+    let width = app.settings.window.width * if side_panel { 0.6 } else { 1.0 } - 40.0;
+    let per_row = ((width + TILE_SPACING) / (TILE_WIDTH + TILE_SPACING)).max(1.0) as usize;
+
+    let mut tiles = folders
+        .into_iter()
+        .map(|path| folder_tile(app, path))
+        .chain(files.into_iter().map(|index| file_tile(app, index)))
+        .peekable();
+
+    let mut column = Column::new().spacing(TILE_SPACING);
+    while tiles.peek().is_some() {
+        column = column.push(Row::with_children(tiles.by_ref().take(per_row)).spacing(TILE_SPACING));
+    }
+    column
+}
+
+fn file_tile(app: &App, index: usize) -> Element<'static, Message> {
+    let package = &app.session.packages[index];
+    let info = &package.info;
+    let dir = app.session.current_dir.as_str();
+    let shown = plain_name(info.name.strip_prefix(dir).unwrap_or(&info.name)).to_string();
+
+    let picture = match app.session.thumbnails.get(&cache_key(&app.session.server.url, info)) {
+        Some(Thumbnail::Ready(handle)) => Image::new(handle.clone())
+            .width(THUMBNAIL_SIZE as f32)
+            .height(THUMBNAIL_SIZE as f32)
+            .content_fit(ContentFit::ScaleDown)
+            .into(),
+        _ => file_icon(Some(info.kind()), &info.extension()),
+    };
+
+    let details = Row::new()
+        .push(Checkbox::new("", package.checked).on_toggle(move |_| Message::ToggleCheck(index)))
+        .push_maybe(info.size.map(|size| text(format_size(size as f64)).size(12)))
+        .push_maybe(package.encrypted.then(|| text("encrypted").size(12)))
+        .spacing(5)
+        .align_items(Alignment::Center);
+
+    let tile = tile(picture, &shown, details.into());
+    if app.session.claims.can(Permission::Download) {
+        mouse_area(tile).on_press(Message::OpenPreview(info.name.clone())).into()
+    } else {
+        tile
+    }
+}
+
+/// A subfolder of the current folder, double click opens it like in the list.
+fn folder_tile(app: &App, path: String) -> Element<'static, Message> {
+    let count = app.session.packages
+        .iter()
+        .filter(|package| package.info.name.starts_with(&path))
+        .count();
+    let details = text(format!("{} files", count)).size(12).into();
+
+    let tile = tile(file_icon(None, ""), &format!("{}/", base_name(&path)), details);
+    mouse_area(tile).on_press(Message::FolderClicked(path)).into()
+}
+
+fn tile(picture: Element<'static, Message>, name: &str, details: Element<'static, Message>) -> Element<'static, Message> {
+    const NAME_CHARS: usize = 18;
+    let short = if name.chars().count() > NAME_CHARS {
+        format!("{}…", name.chars().take(NAME_CHARS - 1).collect::<String>())
+    } else {
+        name.to_string()
+    };
+
+    let column = Column::new()
+        .push(container(picture).width(Length::Fill).height(THUMBNAIL_SIZE as f32).center_x().center_y())
+        .push(text(short).size(14))
+        .push(details)
+        .spacing(5)
+        .align_items(Alignment::Center);
+
+    tooltip(
+        container(column)
+            .width(TILE_WIDTH)
+            .padding(Padding::from(8))
+            .style(theme::Container::Custom(Box::new(TileStyle))),
+        container(text(name.to_string()).size(14))
+            .padding(Padding::from(10))
+            .style(theme::Container::Box),
+        tooltip::Position::Bottom,
+    )
+        .into()
+}
+
+/// The extension on a color for the kind of file, for files without a thumbnail.
+/// `None` draws a folder.
+fn file_icon(kind: Option<FileKind>, extension: &str) -> Element<'static, Message> {
+    let label = match kind {
+        None => String::from("DIR"),
+        Some(_) if extension.is_empty() => String::from("FILE"),
+        Some(_) => extension.to_uppercase().chars().take(4).collect(),
+    };
+
+    container(text(label).size(18))
+        .width(80)
+        .height(96)
+        .center_x()
+        .center_y()
+        .style(theme::Container::Custom(Box::new(IconStyle(kind))))
+        .into()
+}
+
+/// The side panel with the file picked in the list.
+pub fn preview_panel(pane: &PreviewPane) -> Container<'static, Message> {
+    let header = Row::new()
+        .push(text(plain_name(base_name(&pane.name)).to_string()).size(20).width(Length::Fill))
+        .push(small_btn("Close", Message::ClosePreview))
+        .spacing(10)
+        .align_items(Alignment::Center);
+
+    let (body, truncated): (Element<'static, Message>, bool) = match &pane.preview {
+        None => (text("Loading preview...").size(15).into(), false),
+        Some(Err(e)) => (text(format!("No preview: {}", e)).size(15).into(), false),
+        Some(Ok(preview)) => (preview_content(preview, &pane.images), preview.truncated),
+    };
+
+    let column = Column::new()
+        .push(header)
+        .push_maybe(truncated.then(|| text("Only the start of the file is shown, download it to see all of it").size(13)))
+        .push(body)
+        .spacing(10)
+        .padding(Padding::from([15, 5, 15, 5]));
+
+    container(column)
+        .height(Length::Fill)
+        .style(theme::Container::Custom(Box::new(ContainerStyle)))
+}
+
+fn preview_content(preview: &Preview, images: &[image::Handle]) -> Element<'static, Message> {
+    let both_ways = scrollable::Direction::Both {
+        vertical: scrollable::Properties::default(),
+        horizontal: scrollable::Properties::default(),
+    };
+
+    match &preview.content {
+        Content::Image(_) => match images.first() {
+            Some(handle) => Image::new(handle.clone())
+                .width(Length::Fill)
+                .height(Length::Fill)
+                .content_fit(ContentFit::Contain)
+                .into(),
+            None => Space::with_height(0).into(),
+        },
+        Content::Pdf(info) => {
+            let mut column = Column::new()
+                .push(text(format!("PDF {}", info.version)).size(15))
+                .push(text(format!("Pages: {}", info.pages.map_or_else(|| String::from("unknown"), |pages| pages.to_string()))).size(15))
+                .push_maybe(info.title.clone().map(|title| text(format!("Title: {}", title)).size(15)))
+                .spacing(10)
+                .padding(Padding::from([0, 15, 0, 0]));
+
+            if images.is_empty() {
+                column = column.push(text("Only scanned pages can be shown as thumbnails, this PDF has none").size(13));
+            }
+            for handle in images {
+                column = column.push(
+                    Image::new(handle.clone())
+                        .width(Length::Fill)
+                        .height(260)
+                        .content_fit(ContentFit::Contain)
+                );
+            }
+            Scrollable::new(column).into()
+        }
+        Content::Markdown(blocks) => {
+            let column = Column::with_children(blocks.iter().map(markdown_block))
+                .spacing(10)
+                .padding(Padding::from([0, 15, 0, 0]));
+            Scrollable::new(column).into()
+        }
+        Content::Text(lines) => Scrollable::new(code_lines(lines).padding(Padding::from([0, 15, 15, 0])))
+            .direction(both_ways)
+            .into(),
+        Content::Hex(lines) => Scrollable::new(container(text(lines.join("\n")).font(Font::MONOSPACE).size(13)).padding(Padding::from([0, 15, 15, 0])))
+            .direction(both_ways)
+            .into(),
+    }
+}
+
+fn markdown_block(block: &Block) -> Element<'static, Message> {
+    match block {
+        Block::Heading(level, heading) => text(heading.clone())
+            .size(match level {
+                1 => 26,
+                2 => 22,
+                3 => 19,
+                _ => 17,
+            })
+            .into(),
+        Block::Paragraph(paragraph) => text(paragraph.clone()).size(15).into(),
+        Block::Item(depth, item) => Row::new()
+            .push(Space::with_width(Length::Fixed(8.0 + *depth as f32 * 16.0)))
+            .push(text(item.clone()).size(15))
+            .into(),
+        Block::Quote(quote) => container(text(quote.clone()).size(15))
+            .padding(Padding::from(8))
+            .width(Length::Fill)
+            .style(theme::Container::Box)
+            .into(),
+        Block::Code(lines) => container(code_lines(lines))
+            .padding(Padding::from(8))
+            .width(Length::Fill)
+            .style(theme::Container::Box)
+            .into(),
+        Block::Rule => horizontal_rule(1).into(),
+    }
+}
+
+fn code_lines(lines: &[Vec<Span>]) -> Column<'static, Message> {
+    Column::with_children(lines.iter().map(|line| {
+        if line.is_empty() {
+            return text(" ").font(Font::MONOSPACE).size(13).into();
+        }
+        Row::with_children(line.iter().map(|span| {
+            let piece = text(span.text.clone()).font(Font::MONOSPACE).size(13);
+            match token_color(span.token) {
+                Some(color) => piece.style(color).into(),
+                None => piece.into(),
+            }
+        }))
+        .into()
+    }))
+}
+
+pub fn resume_banner(count: usize) -> Container<'static, Message> {
+    let row = Row::new()
+        .push(Space::with_width(30))
+        .push(text(format!("{} unfinished transfer(s) from the previous session", count)).size(18))
+        .push(Space::with_width(Length::Fill))
+        .push(button("Resume").on_press(Message::ResumeTransfers)
+            .style(theme::Button::Custom(Box::new(ButtonStyle::Standard))))
+        .push(button("Discard").on_press(Message::DiscardTransfers)
+            .style(theme::Button::Custom(Box::new(ButtonStyle::DeleteButton))))
+        .push(Space::with_width(10))
+        .height(60)
+        .spacing(20)
+        .align_items(Alignment::Center);
+
+    container(row)
+        .style(theme::Container::Custom(Box::new(FileStyle)))
+}
+
+pub fn toast_row(index: usize, toast: &Toast) -> Container<'static, Message> {
+    let row = Row::new()
+        .push(Space::with_width(30))
+        .push(
+            text(toast.message.clone())
+                .size(18)
+                .style(theme::Text::Color(iced::Color::from_rgb(1.0, 0.0, 0.0)))
+                .width(Length::Fill)
+        )
+        .push(small_btn("x", Message::DismissToast(index)))
+        .push(Space::with_width(10))
+        .height(50)
+        .spacing(20)
+        .align_items(Alignment::Center);
+
+    container(row)
+        .style(theme::Container::Custom(Box::new(FileStyle)))
+}
+
+pub fn rejected_row(index: usize, rejected: &RejectedFile, size_limit: u64) -> Container<'static, Message> {
+    let row = Row::new()
+        .push(Space::with_width(30))
+        .push(
+            text(format!(
+                "{} is {}, the server accepts files up to {}",
+                plain_name(&rejected.file_name),
+                format_size(rejected.size as f64),
+                format_size(size_limit as f64)
+            ))
+                .size(18)
+                .style(theme::Text::Color(iced::Color::from_rgb(1.0, 0.0, 0.0)))
+        )
+        .push(Space::with_width(Length::Fill))
+        .push(button("Split and upload").on_press(Message::SplitUpload(index))
+            .style(theme::Button::Custom(Box::new(ButtonStyle::Standard))))
+        .push(button("Dismiss").on_press(Message::DismissRejected(index))
+            .style(theme::Button::Custom(Box::new(ButtonStyle::DeleteButton))))
+        .push(Space::with_width(10))
+        .height(60)
+        .spacing(20)
+        .align_items(Alignment::Center);
+
+    container(row)
+        .style(theme::Container::Custom(Box::new(FileStyle)))
+}
+
+pub fn drop_zone(current_dir: &str) -> Container<'static, Message> {
+    let target = if current_dir.is_empty() { "Home" } else { current_dir };
+
+    let column = Column::new()
+        .push(text("Drop files or folders to upload").size(28))
+        .push(text(format!("into {}", target)).size(18))
+        .spacing(10)
+        .align_items(Alignment::Center);
+
+    container(column)
+        .width(Length::Fill)
+        .height(Length::Fill)
+        .center_x()
+        .center_y()
+        .padding(Padding::from(20))
+        .style(theme::Container::Custom(Box::new(DropZoneStyle)))
+}
+
+/// Shown after a folder is scanned, nothing is queued until it is confirmed.
+pub fn folder_upload_summary(index: usize, upload: &FolderUpload, size_limit: u64) -> Container<'static, Message> {
+    let root = upload.root
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let target = if upload.target_dir.is_empty() { String::from("Home") } else { upload.target_dir.clone() };
+
+    let mut details = Column::new()
+        .push(text(format!("Upload folder {} into {}", root, target)).size(20))
+        .push(text(format!(
+            "{} files, {}",
+            upload.files.len(),
+            format_size(upload.total_size() as f64)
+        )).size(16))
+        .spacing(5);
+
+    if upload.ignored > 0 {
+        details = details.push(text(format!("{} entries excluded by .ftaignore", upload.ignored)).size(16));
+    }
+    if !upload.oversized.is_empty() {
+        let names: Vec<&str> = upload.oversized.iter().map(|file| file.relative.as_str()).collect();
+        details = details.push(
+            text(format!(
+                "{} files over {} are skipped and can be split afterwards: {}",
+                upload.oversized.len(),
+                format_size(size_limit as f64),
+                names.join(", ")
+            ))
+                .size(16)
+                .style(theme::Text::Color(iced::Color::from_rgb(1.0, 0.0, 0.0)))
+        );
+    }
+
+    let row = Row::new()
+        .push(Space::with_width(30))
+        .push(details.width(Length::Fill))
+        .push(button("Upload").on_press(Message::ConfirmFolderUpload(index))
+            .style(theme::Button::Custom(Box::new(ButtonStyle::Standard))))
+        .push(button("Cancel").on_press(Message::CancelFolderUpload(index))
+            .style(theme::Button::Custom(Box::new(ButtonStyle::DeleteButton))))
+        .push(Space::with_width(10))
+        .padding(Padding::from([10, 0]))
+        .spacing(20)
+        .align_items(Alignment::Center);
+
+    container(row)
+        .style(theme::Container::Custom(Box::new(FileStyle)))
+}
+
+pub fn transfers_panel(transfers: &TransferQueue) -> Container<'static, Message> {
+    let max_concurrent = transfers.max_concurrent();
+
+    let header = Row::new()
+        .push(text("Transfers").size(22))
+        .push(Space::with_width(Length::Fill))
+        .push(text(format!("Parallel: {}", max_concurrent)).size(16))
+        .push(small_btn("-", Message::SetMaxConcurrent(max_concurrent.saturating_sub(1))))
+        .push(small_btn("+", Message::SetMaxConcurrent(max_concurrent + 1)))
+        .push(button("Clear finished").on_press(Message::ClearFinishedTransfers)
+            .style(theme::Button::Custom(Box::new(ButtonStyle::Standard))))
+        .spacing(10)
+        .align_items(Alignment::Center);
+
+    let mut jobs = Column::new()
+        .spacing(10)
+        .padding(Padding::from([0, 15, 0, 0]));
+
+    for job in transfers.jobs() {
+        jobs = jobs.push(transfer_row(job));
+    }
+
+    let column = Column::new()
+        .push(header)
+        .push(Scrollable::new(jobs))
+        .spacing(15)
+        .padding(Padding::from([15, 5, 0, 5]));
+
+    container(column)
+        .style(theme::Container::Custom(Box::new(ContainerStyle)))
+}
+
+pub fn transfer_row(transfer: &Transfer) -> Container<'static, Message> {
+    let status = match &transfer.state {
+        State::Running => {
+            let eta = transfer.eta()
+                .map(format_duration)
+                .unwrap_or_else(|| String::from("--"));
+            format!(
+                "{:.0}%  {}/s  ETA {}",
+                transfer.percent(),
+                format_size(transfer.throughput()),
+                eta
+            )
+        }
+        State::Queued => String::from("Queued"),
+        State::Paused => format!("Paused at {:.0}%", transfer.percent()),
+        State::Failed(e) => e.clone(),
+        State::Done => String::from("Done"),
+    };
+
+    let direction = match transfer.kind {
+        Kind::Upload => "Upload",
+        Kind::Download => "Download",
+    };
+
+    let id = transfer.id;
+    let mut buttons = Row::new()
+        .push(small_btn("Up", Message::MoveTransfer(id, -1)))
+        .push(small_btn("Down", Message::MoveTransfer(id, 1)))
+        .push(Space::with_width(Length::Fill))
+        .spacing(10);
+
+    buttons = match transfer.state {
+        State::Queued | State::Running => buttons.push(small_btn("Pause", Message::PauseTransfer(id))),
+        State::Paused => buttons.push(small_btn("Resume", Message::ResumeTransfer(id))),
+        // What failed its checksum was thrown away, it starts over.
+        State::Failed(ref e) if e.starts_with(CHECKSUM_MISMATCH) => {
+            let label = match transfer.kind {
+                Kind::Upload => "Upload again",
+                Kind::Download => "Download again",
+            };
+            buttons.push(small_btn(label, Message::ResumeTransfer(id)))
+        }
+        State::Failed(_) => buttons.push(small_btn("Retry", Message::ResumeTransfer(id))),
+        State::Done => buttons,
+    };
+
+    let cancel_label = if transfer.is_finished() { "Dismiss" } else { "Cancel" };
+    buttons = buttons.push(button(cancel_label).on_press(Message::CancelTransfer(id))
+        .style(theme::Button::Custom(Box::new(ButtonStyle::DeleteButton))));
+
+    let column = Column::new()
+        .push(
+            Row::new()
+                .push(text(direction).size(14))
+                .push(text(transfer.file_name.to_string()).size(18))
+                .spacing(10)
+                .align_items(Alignment::Center)
+        )
+        .push(progress_bar(0.0..=100.0, transfer.percent()).height(10))
+        .push(text(status).size(14))
+        .push(buttons)
+        .spacing(8)
+        .padding(Padding::from(10));
+
+    container(column)
+        .style(theme::Container::Custom(Box::new(ContainerStyle)))
+}
+
+pub fn download_progress(download: &Transfer) -> Row<'static, Message> {
+    let status = match &download.state {
+        State::Running if download.total == 0 => format_size(download.done as f64),
+        State::Running | State::Done => format!("{:.0}%", download.percent()),
+        State::Queued => String::from("Queued"),
+        State::Paused => String::from("Paused"),
+        State::Failed(e) => e.clone(),
+    };
+
+    let mut row = Row::new()
+        .push(progress_bar(0.0..=100.0, download.percent()).height(10).width(150))
+        .push(text(status).size(16));
+
+    row = match download.state {
+        State::Paused => row.push(small_btn("Resume", Message::ResumeTransfer(download.id))),
+        State::Failed(_) => row.push(small_btn("Retry", Message::ResumeTransfer(download.id))),
+        _ => row,
+    };
+
+    row
+        .push(button("Cancel").on_press(Message::CancelTransfer(download.id))
+            .style(theme::Button::Custom(Box::new(ButtonStyle::DeleteButton))))
+        .spacing(10)
+        .align_items(Alignment::Center)
+}
+
+/// Breadcrumbs of the current folder and the folder actions.
+pub fn folder_bar(app: &App) -> Container<'static, Message> {
+    let mut row = Row::new()
+        .spacing(5)
+        .align_items(Alignment::Center);
+
+    let crumbs = breadcrumbs(&app.session.current_dir);
+    let last = crumbs.len() - 1;
+    for (index, (label, path)) in crumbs.into_iter().enumerate() {
+        if index > 0 {
+            row = row.push(text("/").size(16));
+        }
+        let crumb = button(text(label).size(16))
+            .style(theme::Button::Custom(Box::new(ButtonStyle::Transparent)));
+        // The folder being browsed is not a link.
+        row = row.push(if index == last { crumb } else { crumb.on_press(Message::OpenFolder(path)) });
+    }
+
+    row = row.push(Space::with_width(Length::Fill));
+
+    row = match app.settings.view {
+        FileView::List => row.push(small_btn("Grid view", Message::ViewChanged(FileView::Grid))),
+        FileView::Grid => row.push(small_btn("List view", Message::ViewChanged(FileView::List))),
+    };
+
+    let manage = app.session.claims.can(Permission::ManageFolders);
+    if manage {
+        row = row
+            .push(
+                TextInput::new("New folder", &app.session.new_folder_name)
+                    .on_input(Message::NewFolderNameChanged)
+                    .on_submit(Message::CreateFolder)
+                    .width(Length::Fixed(180.0))
+                    .padding(Padding::from(5))
+            )
+            .push(small_btn("Create", Message::CreateFolder));
+    }
+
+    if manage && app.session.packages.iter().any(|package| package.checked) {
+        row = row.push(small_btn("Move selected here", Message::MoveSelectedHere));
+    }
+
+    let mut column = Column::new().push(row).spacing(5);
+    if let Some(error) = &app.session.folder_error {
+        column = column.push(
+            text(error)
+                .size(14)
+                .style(theme::Text::Color(iced::Color::from_rgb(1.0, 0.0, 0.0)))
+        );
+    }
+
+    container(column).padding(Padding::from([5, 15, 0, 5]))
+}
+
+/// A subfolder of the current folder, double click opens it.
+fn folder_row(app: &App, path: String) -> Element<'static, Message> {
+    let contents: Vec<&FileInfo> = app.session.packages
+        .iter()
+        .map(|package| &package.info)
+        .filter(|info| info.name.starts_with(&path))
+        .collect();
+    let size: u64 = contents.iter().filter_map(|info| info.size).sum();
+    let modified = contents.iter().filter_map(|info| info.modified.or(info.created)).max();
+
+    let renaming = app.session.renaming.as_ref().filter(|renaming| renaming.path == path);
+    let name: Element<'static, Message> = match renaming {
+        Some(renaming) => TextInput::new("Folder name", &renaming.name)
+            .on_input(Message::RenameChanged)
+            .on_submit(Message::SubmitRename)
+            .padding(Padding::from(5))
+            .into(),
+        None => text(format!("{}/", base_name(&path))).size(20).into(),
+    };
+
+    let mut row = Row::new()
+        .push(Space::with_width(10))
+        .push(Space::with_width(20))
+        .push(Space::with_width(20))
+        .push(container(name).width(Length::Fill))
+        .push(column_text(Some(format_size(size as f64)), app.settings.columns.size_width))
+        .push(column_text(Some(format_timestamp(modified)), app.settings.columns.modified_width))
+        .push(column_text(Some(format!("{} files", contents.len())), app.settings.columns.type_width))
+        .push(column_text(None, app.settings.columns.owner_width));
+
+    let manage = app.session.claims.can(Permission::ManageFolders);
+    row = match renaming {
+        Some(_) => row
+            .push(small_btn("Save", Message::SubmitRename))
+            .push(small_btn("Cancel", Message::CancelRename)),
+        None => row
+            .push(small_btn("Open", Message::OpenFolder(path.clone())))
+            .push_maybe(manage.then(|| small_btn("Rename", Message::StartRename(path.clone())))),
+    };
+
+    let row = row
+        .push_maybe((manage && app.session.claims.can(Permission::Delete)).then(|| {
+            button(text("Delete").size(14))
+                .on_press(Message::DeleteFolder(path.clone()))
+                .style(theme::Button::Custom(Box::new(ButtonStyle::DeleteButton)))
+        }))
+        .push(Space::with_width(10))
+        .height(60)
+        .spacing(10)
+        .align_items(Alignment::Center);
+
+    mouse_area(container(row).style(theme::Container::Custom(Box::new(FileStyle))))
+        .on_press(Message::FolderClicked(path))
+        .into()
+}
+
+/// Saved searches as chips, and the reason the current query is rejected, if it is.
+pub fn search_bar(app: &App) -> Container<'static, Message> {
+    let mut row = Row::new()
+        .spacing(5)
+        .align_items(Alignment::Center);
+
+    for (index, search) in app.saved_searches.iter().enumerate() {
+        let style = if *search == app.session.search_text { ButtonStyle::ThemeButton } else { ButtonStyle::Standard };
+        row = row.push(
+            Row::new()
+                .push(button(text(search).size(14))
+                    .on_press(Message::ApplySavedSearch(index))
+                    .style(theme::Button::Custom(Box::new(style))))
+                .push(button(text("x").size(14))
+                    .on_press(Message::RemoveSavedSearch(index))
+                    .style(theme::Button::Custom(Box::new(ButtonStyle::Transparent))))
+                .align_items(Alignment::Center)
+        );
+    }
+
+    match &app.session.search {
+        Err(error) => {
+            row = row.push(
+                text(error)
+                    .size(14)
+                    .style(theme::Text::Color(iced::Color::from_rgb(1.0, 0.0, 0.0)))
+            );
+        }
+        Ok(_) if !app.session.search_text.trim().is_empty() && !app.saved_searches.contains(&app.session.search_text.trim().to_string()) => {
+            row = row.push(small_btn("Save search", Message::SaveSearch));
+        }
+        Ok(_) => {}
+    }
+
+    container(row).padding(Padding::from([5, 15, 5, 5]))
+}
+
+pub fn small_btn(label: &str, event: Message) -> Button<'static, Message> {
+    button(text(label).size(14))
+        .on_press(event)
+        .style(theme::Button::Custom(Box::new(ButtonStyle::Standard)))
 }
 
 pub fn log_in_input_field(_placeholder: &str, _value: &str, ) -> TextInput<'static, Message> {
@@ -228,7 +1513,7 @@ pub fn download_btn(filename: String) -> Button<'static, Message> {
 }
 
 
-pub fn submit_btn(name: &str, event: Message) -> Button<Message> {
+pub fn submit_btn(name: &str, event: Message) -> Button<'_, Message> {
     Button::new(
         text(name)
             .horizontal_alignment(Horizontal::Center)
@@ -1,36 +1,61 @@
-use iced::window;
-use iced::window::Icon;
-
-pub fn load_icon(path: &str) -> Icon {
-    let result = window::icon::from_file(path);
-
-    match result {
-        Ok(icon) => {
-            icon.into()
-        }
-        Err(_e) => {
-            println!("Load icon error");
-            let rgba: Vec<u8> = create_rgba_image(16, 16);
-            let width = 16;
-            let height = 16;
-            window::icon::from_rgba(rgba, width, height).expect("Failed to create icon")
-        }
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::Duration;
+
+pub fn format_size(bytes: f64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+
+    let mut size = bytes;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} {}", size as u64, UNITS[unit])
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
     }
 }
 
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
 
-pub fn create_rgba_image(width: u32, height: u32) -> Vec<u8> {
-    let mut image = vec![0; (width * height * 4) as usize];
+    if secs >= 3600 {
+        format!("{}h {:02}m", secs / 3600, secs % 3600 / 60)
+    } else if secs >= 60 {
+        format!("{}m {:02}s", secs / 60, secs % 60)
+    } else {
+        format!("{}s", secs)
+    }
+}
 
-    for y in 0..height {
-        for x in 0..width {
-            let index = (y * width + x) as usize * 4;
-            image[index] = 135;
-            image[index + 1] = 206;
-            image[index + 2] = 250;
-            image[index + 3] = 255;
-        }
+/// Directory for state the app keeps between runs.
+pub fn data_dir() -> PathBuf {
+    dirs_next::data_local_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("fta")
+}
+
+/// Directory for files the user may want to edit by hand.
+pub fn config_dir() -> PathBuf {
+    dirs_next::config_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("fta")
+}
+
+/// Only the owner may read the file, where the platform allows it.
+pub fn write_private(path: &PathBuf, data: &[u8]) -> std::io::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
     }
 
-    image
-}
\ No newline at end of file
+    let mut file = options.open(path)?;
+    file.write_all(data)
+}
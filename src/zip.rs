@@ -0,0 +1,379 @@
+//! Bundling downloads into a `.zip`.
+//!
+//! Files are downloaded through the normal transfer queue into a staging folder next
+//! to the archive, then written out as stored (uncompressed) entries. Zip64 records are
+//! added only where sizes or offsets need them.
+
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Seek, Write};
+use std::path::PathBuf;
+use chrono::{Datelike, Local, Timelike};
+
+const LOCAL_HEADER: u32 = 0x04034b50;
+const CENTRAL_HEADER: u32 = 0x02014b50;
+const END_OF_CENTRAL_DIRECTORY: u32 = 0x06054b50;
+const ZIP64_END_OF_CENTRAL_DIRECTORY: u32 = 0x06064b50;
+const ZIP64_LOCATOR: u32 = 0x07064b50;
+const ZIP64_EXTRA: u16 = 0x0001;
+const UTF8_NAMES: u16 = 1 << 11;
+const VERSION: u16 = 20;
+const VERSION_ZIP64: u16 = 45;
+const U32_MAX: u64 = u32::MAX as u64;
+
+/// One file of the archive, made of the downloaded `parts` (several for chunked files).
+pub struct ZipEntry {
+    pub name: String,
+    pub parts: Vec<PathBuf>,
+    pub jobs: Vec<usize>,
+}
+
+/// A "Download as zip" in progress: written once `pending` is empty.
+pub struct Bundle {
+    pub target: PathBuf,
+    pub staging: PathBuf,
+    pub entries: Vec<ZipEntry>,
+    pub pending: Vec<usize>,
+}
+
+/// Writes the archive and removes the staging folder.
+pub async fn write_zip(target: PathBuf, staging: PathBuf, entries: Vec<(String, Vec<PathBuf>)>) -> Result<PathBuf, String> {
+    tokio::task::spawn_blocking(move || {
+        write_archive(&target, &entries).map_err(|e| format!("{:?}: {}", target, e))?;
+        let _ = fs::remove_dir_all(&staging);
+        Ok(target)
+    })
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+struct Written {
+    name: String,
+    crc: u32,
+    size: u64,
+    offset: u64,
+}
+
+/// `name` as an archive path: `/` separators, no leading `/`, no empty or `.` segments.
+/// `None` for names with `..`, which would unpack outside the folder they are unpacked in.
+pub fn entry_name(name: &str) -> Option<String> {
+    let mut segments = vec![];
+    for segment in name.split(['/', '\\']) {
+        match segment {
+            "" | "." => continue,
+            ".." => return None,
+            segment => segments.push(segment),
+        }
+    }
+    Some(segments.join("/")).filter(|name| !name.is_empty())
+}
+
+fn write_archive(target: &PathBuf, entries: &[(String, Vec<PathBuf>)]) -> io::Result<()> {
+    let mut out = BufWriter::new(File::create(target)?);
+    let (time, date) = dos_time();
+    let mut written = vec![];
+
+    for (name, parts) in entries {
+        let (crc, size) = checksum(parts)?;
+        let offset = out.stream_position()?;
+        let entry = Written { name: name.clone(), crc, size, offset };
+
+        out.write_all(&local_header(&entry, time, date))?;
+        for part in parts {
+            io::copy(&mut File::open(part)?, &mut out)?;
+        }
+        written.push(entry);
+    }
+
+    let directory_offset = out.stream_position()?;
+    for entry in &written {
+        out.write_all(&central_header(entry, time, date))?;
+    }
+    let directory_end = out.stream_position()?;
+    out.write_all(&end_records(written.len() as u64, directory_offset, directory_end))?;
+
+    out.flush()
+}
+
+fn local_header(entry: &Written, time: u16, date: u16) -> Vec<u8> {
+    let zip64 = entry.size >= U32_MAX;
+    let short_size = if zip64 { u32::MAX } else { entry.size as u32 };
+    let mut out = vec![];
+
+    out.extend_from_slice(&LOCAL_HEADER.to_le_bytes());
+    out.extend_from_slice(&(if zip64 { VERSION_ZIP64 } else { VERSION }).to_le_bytes());
+    out.extend_from_slice(&UTF8_NAMES.to_le_bytes());
+    out.extend_from_slice(&0u16.to_le_bytes());
+    out.extend_from_slice(&time.to_le_bytes());
+    out.extend_from_slice(&date.to_le_bytes());
+    out.extend_from_slice(&entry.crc.to_le_bytes());
+    out.extend_from_slice(&short_size.to_le_bytes());
+    out.extend_from_slice(&short_size.to_le_bytes());
+    out.extend_from_slice(&(entry.name.len() as u16).to_le_bytes());
+    out.extend_from_slice(&(if zip64 { 20u16 } else { 0 }).to_le_bytes());
+    out.extend_from_slice(entry.name.as_bytes());
+    if zip64 {
+        out.extend_from_slice(&ZIP64_EXTRA.to_le_bytes());
+        out.extend_from_slice(&16u16.to_le_bytes());
+        out.extend_from_slice(&entry.size.to_le_bytes());
+        out.extend_from_slice(&entry.size.to_le_bytes());
+    }
+    out
+}
+
+fn central_header(entry: &Written, time: u16, date: u16) -> Vec<u8> {
+    let mut extra = vec![];
+    if entry.size >= U32_MAX {
+        extra.extend_from_slice(&entry.size.to_le_bytes());
+        extra.extend_from_slice(&entry.size.to_le_bytes());
+    }
+    if entry.offset >= U32_MAX {
+        extra.extend_from_slice(&entry.offset.to_le_bytes());
+    }
+    let zip64 = !extra.is_empty();
+    let version = if zip64 { VERSION_ZIP64 } else { VERSION };
+    let short_size = if entry.size >= U32_MAX { u32::MAX } else { entry.size as u32 };
+    let mut out = vec![];
+
+    out.extend_from_slice(&CENTRAL_HEADER.to_le_bytes());
+    out.extend_from_slice(&version.to_le_bytes());
+    out.extend_from_slice(&version.to_le_bytes());
+    out.extend_from_slice(&UTF8_NAMES.to_le_bytes());
+    out.extend_from_slice(&0u16.to_le_bytes());
+    out.extend_from_slice(&time.to_le_bytes());
+    out.extend_from_slice(&date.to_le_bytes());
+    out.extend_from_slice(&entry.crc.to_le_bytes());
+    out.extend_from_slice(&short_size.to_le_bytes());
+    out.extend_from_slice(&short_size.to_le_bytes());
+    out.extend_from_slice(&(entry.name.len() as u16).to_le_bytes());
+    out.extend_from_slice(&(if zip64 { extra.len() as u16 + 4 } else { 0 }).to_le_bytes());
+    out.extend_from_slice(&0u16.to_le_bytes());
+    out.extend_from_slice(&0u16.to_le_bytes());
+    out.extend_from_slice(&0u16.to_le_bytes());
+    out.extend_from_slice(&0u32.to_le_bytes());
+    out.extend_from_slice(&(entry.offset.min(U32_MAX) as u32).to_le_bytes());
+    out.extend_from_slice(entry.name.as_bytes());
+    if zip64 {
+        out.extend_from_slice(&ZIP64_EXTRA.to_le_bytes());
+        out.extend_from_slice(&(extra.len() as u16).to_le_bytes());
+        out.extend_from_slice(&extra);
+    }
+    out
+}
+
+/// The end of central directory record, after the zip64 one and its locator when the
+/// count, size or offset don't fit.
+fn end_records(count: u64, directory_offset: u64, directory_end: u64) -> Vec<u8> {
+    let directory_size = directory_end - directory_offset;
+    let mut out = vec![];
+
+    if count >= u16::MAX as u64 || directory_offset >= U32_MAX || directory_size >= U32_MAX {
+        out.extend_from_slice(&ZIP64_END_OF_CENTRAL_DIRECTORY.to_le_bytes());
+        out.extend_from_slice(&44u64.to_le_bytes());
+        out.extend_from_slice(&VERSION_ZIP64.to_le_bytes());
+        out.extend_from_slice(&VERSION_ZIP64.to_le_bytes());
+        out.extend_from_slice(&0u32.to_le_bytes());
+        out.extend_from_slice(&0u32.to_le_bytes());
+        out.extend_from_slice(&count.to_le_bytes());
+        out.extend_from_slice(&count.to_le_bytes());
+        out.extend_from_slice(&directory_size.to_le_bytes());
+        out.extend_from_slice(&directory_offset.to_le_bytes());
+
+        out.extend_from_slice(&ZIP64_LOCATOR.to_le_bytes());
+        out.extend_from_slice(&0u32.to_le_bytes());
+        out.extend_from_slice(&directory_end.to_le_bytes());
+        out.extend_from_slice(&1u32.to_le_bytes());
+    }
+
+    out.extend_from_slice(&END_OF_CENTRAL_DIRECTORY.to_le_bytes());
+    out.extend_from_slice(&0u16.to_le_bytes());
+    out.extend_from_slice(&0u16.to_le_bytes());
+    out.extend_from_slice(&(count.min(u16::MAX as u64) as u16).to_le_bytes());
+    out.extend_from_slice(&(count.min(u16::MAX as u64) as u16).to_le_bytes());
+    out.extend_from_slice(&(directory_size.min(U32_MAX) as u32).to_le_bytes());
+    out.extend_from_slice(&(directory_offset.min(U32_MAX) as u32).to_le_bytes());
+    out.extend_from_slice(&0u16.to_le_bytes());
+    out
+}
+
+/// CRC-32 and length of the parts read back to back.
+fn checksum(parts: &[PathBuf]) -> io::Result<(u32, u64)> {
+    let table = crc_table();
+    let mut crc = u32::MAX;
+    let mut size = 0;
+    let mut buffer = vec![0; 64 * 1024];
+
+    for part in parts {
+        let mut file = File::open(part)?;
+        loop {
+            let read = file.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            for byte in &buffer[..read] {
+                crc = table[((crc ^ *byte as u32) & 0xff) as usize] ^ (crc >> 8);
+            }
+            size += read as u64;
+        }
+    }
+
+    Ok((!crc, size))
+}
+
+fn crc_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    for (n, value) in table.iter_mut().enumerate() {
+        let mut c = n as u32;
+        for _ in 0..8 {
+            c = if c & 1 != 0 { 0xedb88320 ^ (c >> 1) } else { c >> 1 };
+        }
+        *value = c;
+    }
+    table
+}
+
+fn dos_time() -> (u16, u16) {
+    let now = Local::now();
+    let time = ((now.hour() << 11) | (now.minute() << 5) | (now.second() / 2)) as u16;
+    let date = ((((now.year() - 1980).max(0) as u32) << 9) | (now.month() << 5) | now.day()) as u16;
+    (time, date)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn u16_at(bytes: &[u8], at: usize) -> u16 {
+        u16::from_le_bytes(bytes[at..at + 2].try_into().unwrap())
+    }
+
+    fn u32_at(bytes: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+    }
+
+    fn u64_at(bytes: &[u8], at: usize) -> u64 {
+        u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap())
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("fta-zip-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn computes_crc32_across_parts() {
+        let dir = temp_dir("crc");
+        fs::write(dir.join("a"), "1234").unwrap();
+        fs::write(dir.join("b"), "56789").unwrap();
+
+        assert_eq!(checksum(&[dir.join("a"), dir.join("b")]).unwrap(), (0xcbf43926, 9));
+        assert_eq!(checksum(&[]).unwrap(), (0, 0));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn writes_headers_directory_and_end_record() {
+        let dir = temp_dir("layout");
+        fs::write(dir.join("a"), "hello").unwrap();
+        fs::write(dir.join("b"), "").unwrap();
+        let target = dir.join("out.zip");
+        write_archive(&target, &[
+            (String::from("a.txt"), vec![dir.join("a")]),
+            (String::from("sub/b.txt"), vec![dir.join("b")]),
+        ])
+        .unwrap();
+        let zip = fs::read(&target).unwrap();
+
+        // a.txt: 30 byte header, the name, the stored bytes.
+        assert_eq!(u32_at(&zip, 0), LOCAL_HEADER);
+        assert_eq!(u16_at(&zip, 4), VERSION);
+        assert_eq!(u16_at(&zip, 6), UTF8_NAMES);
+        assert_eq!(u16_at(&zip, 8), 0);
+        assert_eq!(u32_at(&zip, 18), 5);
+        assert_eq!(u32_at(&zip, 22), 5);
+        assert_eq!(u16_at(&zip, 26), 5);
+        assert_eq!(u16_at(&zip, 28), 0);
+        assert_eq!(&zip[30..35], b"a.txt");
+        assert_eq!(&zip[35..40], b"hello");
+        let second = 40;
+        assert_eq!(u32_at(&zip, second), LOCAL_HEADER);
+        assert_eq!(&zip[second + 30..second + 39], b"sub/b.txt");
+
+        let end = zip.len() - 22;
+        assert_eq!(u32_at(&zip, end), END_OF_CENTRAL_DIRECTORY);
+        assert_eq!(u16_at(&zip, end + 8), 2);
+        assert_eq!(u16_at(&zip, end + 10), 2);
+        let directory_size = u32_at(&zip, end + 12) as usize;
+        let directory = u32_at(&zip, end + 16) as usize;
+        assert_eq!(directory, second + 39);
+        assert_eq!(directory + directory_size, end);
+
+        assert_eq!(u32_at(&zip, directory), CENTRAL_HEADER);
+        assert_eq!(u32_at(&zip, directory + 16), u32_at(&zip, 14));
+        assert_eq!(u32_at(&zip, directory + 20), 5);
+        assert_eq!(u32_at(&zip, directory + 42), 0);
+        assert_eq!(&zip[directory + 46..directory + 51], b"a.txt");
+        let next = directory + 51;
+        assert_eq!(u32_at(&zip, next), CENTRAL_HEADER);
+        assert_eq!(u32_at(&zip, next + 42), second as u32);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn adds_zip64_records_past_four_gigabytes() {
+        let big = U32_MAX + 10;
+        let entry = Written { name: String::from("big"), crc: 7, size: big, offset: big };
+
+        let local = local_header(&entry, 0, 0);
+        assert_eq!(u16_at(&local, 4), VERSION_ZIP64);
+        assert_eq!(u32_at(&local, 18), u32::MAX);
+        assert_eq!(u32_at(&local, 22), u32::MAX);
+        assert_eq!(u16_at(&local, 28), 20);
+        assert_eq!(u16_at(&local, 33), ZIP64_EXTRA);
+        assert_eq!(u64_at(&local, 37), big);
+        assert_eq!(u64_at(&local, 45), big);
+        assert_eq!(local.len(), 30 + 3 + 20);
+
+        let central = central_header(&entry, 0, 0);
+        assert_eq!(u16_at(&central, 4), VERSION_ZIP64);
+        assert_eq!(u16_at(&central, 30), 28);
+        assert_eq!(u32_at(&central, 42), u32::MAX);
+        assert_eq!(u16_at(&central, 49), ZIP64_EXTRA);
+        assert_eq!(u16_at(&central, 51), 24);
+        assert_eq!(u64_at(&central, 53), big);
+        assert_eq!(u64_at(&central, 61), big);
+        assert_eq!(u64_at(&central, 69), big);
+
+        // Only the offset is too large: the extra field holds just that.
+        let entry = Written { size: 1, ..entry };
+        let central = central_header(&entry, 0, 0);
+        assert_eq!(u32_at(&central, 20), 1);
+        assert_eq!(u16_at(&central, 51), 8);
+        assert_eq!(u64_at(&central, 53), big);
+
+        let end = end_records(1, big, big + 100);
+        assert_eq!(u32_at(&end, 0), ZIP64_END_OF_CENTRAL_DIRECTORY);
+        assert_eq!(u64_at(&end, 4), 44);
+        assert_eq!(u64_at(&end, 24), 1);
+        assert_eq!(u64_at(&end, 40), 100);
+        assert_eq!(u64_at(&end, 48), big);
+        assert_eq!(u32_at(&end, 56), ZIP64_LOCATOR);
+        assert_eq!(u64_at(&end, 64), big + 100);
+        assert_eq!(u32_at(&end, 76), END_OF_CENTRAL_DIRECTORY);
+        assert_eq!(u32_at(&end, 92), u32::MAX);
+        assert_eq!(end.len(), 56 + 20 + 22);
+
+        assert_eq!(end_records(1, 10, 110).len(), 22);
+    }
+
+    #[test]
+    fn keeps_entry_names_inside_the_archive() {
+        assert_eq!(entry_name("docs/a.txt").as_deref(), Some("docs/a.txt"));
+        assert_eq!(entry_name("/docs//./a.txt").as_deref(), Some("docs/a.txt"));
+        assert_eq!(entry_name("docs\\a.txt").as_deref(), Some("docs/a.txt"));
+        assert_eq!(entry_name("../a.txt"), None);
+        assert_eq!(entry_name("docs/../../a.txt"), None);
+        assert_eq!(entry_name("docs\\..\\a.txt"), None);
+        assert_eq!(entry_name("/"), None);
+    }
+}